@@ -0,0 +1,106 @@
+// std imports
+use std::sync::Arc;
+
+// third-party imports
+use chrono::FixedOffset;
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde_json as json;
+
+// local imports
+use hl::{
+    DateTimeFormatter, IncludeExcludeKeyFilter, KeyMatchOptions, LinuxDateFormat, Parser,
+    ParserSettings, RawRecord, RecordFormatter, Settings, Theme,
+};
+
+// ---
+
+fn benchmark(c: &mut Criterion) {
+    let mut c = c.benchmark_group("format");
+    let settings = Settings::default();
+    let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+    let mut fields = IncludeExcludeKeyFilter::new(KeyMatchOptions::default());
+    fields.entry("agent").exclude();
+    fields.entry("kubernetes").entry("labels").exclude();
+    let fields = Arc::new(fields);
+    for (name, record) in RECORDS {
+        for theme in [
+            None,
+            Some("one-dark-green"),
+            Some("one-dark-green-truecolor"),
+        ] {
+            let theme_name = theme.unwrap_or("none");
+            c.bench_function(format!("{}/{}", name, theme_name), |b| {
+                let theme = match theme {
+                    Some(theme) => Theme::embedded(theme).unwrap(),
+                    None => Theme::none(),
+                };
+                let mut formatter = RecordFormatter::new(
+                    Arc::new(theme),
+                    DateTimeFormatter::new(
+                        LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                        FixedOffset::east(0),
+                    ),
+                    false,
+                    fields.clone(),
+                );
+                let record = parser.parse(json::from_str::<RawRecord>(record).unwrap());
+                let mut buf = Vec::new();
+                b.iter(|| {
+                    formatter.format_record(&mut buf, &record);
+                    buf.clear();
+                });
+            });
+        }
+    }
+    let mut fields = IncludeExcludeKeyFilter::new(KeyMatchOptions::default());
+    fields.exclude();
+    fields.entry("method").include();
+    let fields = Arc::new(fields);
+    for (name, record) in RECORDS {
+        for (suffix, hide_empty_fields) in [("", false), ("-empty", true)] {
+            c.bench_function(format!("{}/hidden{}", name, suffix), |b| {
+                let mut formatter = RecordFormatter::new(
+                    Arc::new(Theme::none()),
+                    DateTimeFormatter::new(
+                        LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                        FixedOffset::east(0),
+                    ),
+                    hide_empty_fields,
+                    fields.clone(),
+                );
+                let record = parser.parse(json::from_str::<RawRecord>(record).unwrap());
+                let mut buf = Vec::new();
+                b.iter(|| {
+                    formatter.format_record(&mut buf, &record);
+                    buf.clear();
+                });
+            });
+        }
+    }
+}
+
+// ---
+
+const RECORDS: [(&str, &str); 4] = [
+    (
+        "small",
+        r#"{"ts":"2021-06-20T00:00:00.393Z","level":"info","msg":"connected","peer":"10.0.0.1:443"}"#,
+    ),
+    (
+        "wide",
+        r#"{"ts":"2021-06-20T00:00:00.393Z","level":"warning","logger":"http","msg":"slow request","method":"GET","path":"/api/v1/items","status":200,"duration":1.234,"bytes":10240,"user-agent":"curl/7.68.0","request-id":"1a632cba-8480-4644-93f2-262bc0c13d04","retry":false,"error":null,"caller":"server/handler.go:128"}"#,
+    ),
+    (
+        "nested",
+        r#"{"ts":"2021-06-20T00:00:00.393Z","level":"error","msg":"failed to schedule","agent":{"id":"1a9b51ef","version":"7.8.3"},"kubernetes":{"labels":{"app":"some-segway","release":"as-rtrf"},"namespace":"as-rtrf","pod":{"name":"as-rtrf-some-segway-756d998476-jz4jm","uid":"9d445b65-fbf7-4d94-a7f4-4dbb7753d65c"}}}"#,
+    ),
+    (
+        "arrays",
+        r#"{"ts":"2021-06-20T00:00:00.393Z","level":"debug","msg":"batch \"processed\"\n","ids":[1,2,3,4,5,6,7,8],"tags":["alpha","beta","gamma"],"data":[104,101,108,108,111],"items":[{"id":1},{"id":2}]}"#,
+    ),
+];
+
+// ---
+
+criterion_group!(benches, benchmark);
+criterion_main!(benches);
@@ -0,0 +1,68 @@
+// std imports
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+
+// ---
+
+/// Replaces values with short tokens derived from their salted hashes,
+/// so that equal values are replaced with equal tokens while the original values are not shown.
+pub struct Anonymizer {
+    state: Option<RandomState>,
+    salt: String,
+}
+
+impl Anonymizer {
+    /// Creates an anonymizer using the given salt, or a random one chosen for this run if it is `None`.
+    /// Tokens produced with the same salt are the same across runs of the same build only.
+    pub fn new(salt: Option<String>) -> Self {
+        match salt {
+            Some(salt) => Self { state: None, salt },
+            None => Self {
+                state: Some(RandomState::new()),
+                salt: String::new(),
+            },
+        }
+    }
+
+    /// Writes a token like `user_7f3a91c20b5d4e86` for the value, using the prefix followed by 16 hex digits of the hash.
+    pub fn token(&self, buf: &mut Vec<u8>, prefix: &str, value: &[u8]) {
+        let mut hasher = match &self.state {
+            Some(state) => state.build_hasher(),
+            None => DefaultHasher::new(),
+        };
+        self.salt.hash(&mut hasher);
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        buf.extend_from_slice(prefix.as_bytes());
+        buf.push(b'_');
+        buf.extend_from_slice(format!("{:016x}", hash).as_bytes());
+    }
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(anonymizer: &Anonymizer, value: &str) -> String {
+        let mut buf = Vec::new();
+        anonymizer.token(&mut buf, "user", value.as_bytes());
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_token() {
+        let a = Anonymizer::new(Some("salt".into()));
+        let b = Anonymizer::new(Some("pepper".into()));
+        let t = token(&a, "alice");
+        assert!(t.starts_with("user_"));
+        assert_eq!(t.len(), 21);
+        assert_eq!(token(&a, "alice"), t);
+        assert_ne!(token(&a, "bob"), t);
+        assert_ne!(token(&b, "alice"), t);
+        assert_eq!(token(&Anonymizer::new(Some("salt".into())), "alice"), t);
+        let r = Anonymizer::new(None);
+        assert_eq!(token(&r, "alice"), token(&r, "alice"));
+    }
+}
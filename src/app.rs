@@ -1,7 +1,10 @@
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use chrono::FixedOffset;
+use chrono::{DateTime, FixedOffset, Utc};
 use closure::closure;
 use crossbeam_channel as channel;
 use crossbeam_channel::RecvError;
@@ -9,27 +12,104 @@ use crossbeam_utils::thread;
 use itertools::izip;
 use serde_json as json;
 
-use crate::datefmt::{DateTimeFormat, DateTimeFormatter};
+use crate::anonymize::Anonymizer;
+use crate::csvfmt::CsvFormatter;
+use crate::datefmt::{DateTimeFormat, DateTimeFormatter, LinuxDateFormat};
+use crate::envelope::Envelope;
 use crate::error::*;
-use crate::formatting::RecordFormatter;
-use crate::model::{Filter, Parser, ParserSettings, RawRecord};
-use crate::scanning::{BufFactory, Scanner, Segment, SegmentBufFactory};
+use crate::fieldstats::FieldStats;
+use crate::formatting::{format_separator, format_time_delta, Layout, RecordFormatter};
+use crate::input::{InputMap, LineReader};
+use crate::metrics::Metrics;
+use crate::model::{
+    DuplicateKeys, Filter, LevelMapping, Parser, ParserSettings, RawRecord, Record,
+};
+use crate::output::{LimitedWriter, SplitWriter};
+use crate::profile::{Profile, Stage};
+use crate::ratelimit::{RateLimiter, RateOverflow};
+use crate::scanning::{BufFactory, PartialPlacement, Scanner, Segment, SegmentBufFactory};
 use crate::settings::Fields;
+use crate::syslog;
 use crate::theme::Theme;
+use crate::types::{Level, SampleRate, TimeBucket};
+use crate::weblog::{self, W3cFields};
 use crate::IncludeExcludeKeyFilter;
 
+#[cfg(all(target_os = "linux", feature = "journald"))]
+use crate::journald::{self, Journal};
+
 // TODO: merge Options to Settings and replace Options with Settings.
 pub struct Options {
     pub theme: Arc<Theme>,
     pub time_format: DateTimeFormat,
     pub raw_fields: bool,
+    pub raw_field_keys: Vec<String>,
+    pub nested_fields: Vec<String>,
+    pub anonymized_fields: Vec<String>,
+    pub anonymizer: Arc<Anonymizer>,
     pub buffer_size: usize,
+    pub line_mode: bool,
     pub max_message_size: usize,
     pub concurrency: usize,
     pub filter: Filter,
     pub fields: FieldOptions,
     pub time_zone: FixedOffset,
+    pub secondary_time_zone: Option<FixedOffset>,
+    pub time_zone_field: Option<String>,
     pub hide_empty_fields: bool,
+    pub quiet: bool,
+    pub show_parse_errors: bool,
+    pub input_format: InputFormat,
+    pub unwrap: Option<String>,
+    pub output_format: OutputFormat,
+    pub normalize_time: bool,
+    pub flatten_objects: bool,
+    pub flatten_arrays: bool,
+    pub path_delimiter: u8,
+    pub align_fields: bool,
+    pub sort_fields: bool,
+    pub ellipsis: Option<String>,
+    pub null_text: Option<String>,
+    pub show_hidden_count: bool,
+    pub max_value_len: Option<usize>,
+    pub max_line_len: Option<usize>,
+    pub max_output_bytes: Option<usize>,
+    pub ascii: bool,
+    pub highlight_levels_in_message: bool,
+    pub highlight_kv: bool,
+    pub message_only: bool,
+    pub skip_missing_messages: bool,
+    pub max_fields: Option<usize>,
+    pub trailing_reset: bool,
+    pub status_fields: Vec<String>,
+    pub status_width: usize,
+    pub infer_level: bool,
+    pub status_field: Option<String>,
+    pub duplicate_keys: DuplicateKeys,
+    pub auto_time: bool,
+    pub time_input_format: Option<String>,
+    pub verbose: bool,
+    pub level_mapping: LevelMapping,
+    pub metrics: bool,
+    pub count: bool,
+    pub sample: Option<SampleRate>,
+    pub max_rate: Option<u32>,
+    pub rate_overflow: RateOverflow,
+    pub explain: Option<usize>,
+    pub debug_raw: bool,
+    pub byte_offsets: bool,
+    pub reverse: bool,
+    pub strip_key_prefixes: Vec<String>,
+    pub csv_columns: Vec<String>,
+    pub profile: bool,
+    pub time_separator: Option<TimeBucket>,
+    pub time_delta: bool,
+    pub line_prefix: String,
+    pub line_suffix: String,
+    pub fold_fields: bool,
+    pub layout: Layout,
+    pub split_level: Level,
+    pub split_by: SplitBy,
 }
 
 pub struct FieldOptions {
@@ -37,13 +117,51 @@ pub struct FieldOptions {
     pub settings: Fields,
 }
 
+/// Format of input lines, lines in formats other than JSON are converted to JSON records before parsing.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum InputFormat {
+    #[default]
+    Json,
+    Syslog,
+    Cef,
+    Apache,
+    W3c,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Human,
+    Json,
+    Csv,
+    #[cfg(all(target_os = "linux", feature = "journald"))]
+    Journald,
+}
+
+/// Record property choosing the file of each record when output is split into files, see [`App::run_split_files`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum SplitBy {
+    #[default]
+    Level,
+    /// Value of the top-level field with the given key.
+    Field(String),
+}
+
 pub struct App {
     options: Options,
+    inputs: Option<Arc<InputMap>>,
+    #[cfg(all(target_os = "linux", feature = "journald"))]
+    journal_path: Option<std::path::PathBuf>,
 }
 
 impl App {
     pub fn new(options: Options) -> Self {
-        Self { options }
+        Self {
+            options,
+            inputs: None,
+            #[cfg(all(target_os = "linux", feature = "journald"))]
+            journal_path: None,
+        }
     }
 
     pub fn run(
@@ -51,74 +169,306 @@ impl App {
         input: &mut (dyn Read + Send + Sync),
         output: &mut (dyn Write + Send + Sync),
     ) -> Result<()> {
+        self.cat(input, output, None, None)
+    }
+
+    /// Same as [`App::run`] but writes records with the split level or a more severe one to the `errors` output.
+    pub fn run_split(
+        &self,
+        input: &mut (dyn Read + Send + Sync),
+        output: &mut (dyn Write + Send + Sync),
+        errors: &mut (dyn Write + Send + Sync),
+    ) -> Result<()> {
+        self.cat(input, output, Some(errors), None)
+    }
+
+    /// Same as [`App::run`] but writes records to files chosen by [`Options::split_by`], records without the level
+    /// or the field go to `unknown.log`, lines that are not records go to the `output`. Reversed output is not supported.
+    pub fn run_split_files(
+        &self,
+        input: &mut (dyn Read + Send + Sync),
+        output: &mut (dyn Write + Send + Sync),
+        files: &mut SplitWriter,
+    ) -> Result<()> {
+        self.cat(input, output, None, Some(files))
+    }
+
+    fn cat(
+        &self,
+        input: &mut (dyn Read + Send + Sync),
+        output: &mut (dyn Write + Send + Sync),
+        mut errors: Option<&mut (dyn Write + Send + Sync)>,
+        mut files: Option<&mut SplitWriter>,
+    ) -> Result<()> {
+        let split = errors.is_some() || files.is_some();
+        let split_field = match (&files, &self.options.split_by) {
+            (Some(_), SplitBy::Field(key)) => Some(key.as_str()),
+            _ => None,
+        };
+        let exhausted = AtomicBool::new(false);
+        let mut limited;
+        let output: &mut (dyn Write + Send + Sync) = match self.options.max_output_bytes {
+            Some(limit) => {
+                limited = LimitedWriter::new(output, limit, &exhausted);
+                &mut limited
+            }
+            None => output,
+        };
         let n = self.options.concurrency;
         let sfi = Arc::new(SegmentBufFactory::new(self.options.buffer_size));
         let bfo = BufFactory::new(self.options.buffer_size);
-        let parser = Parser::new(ParserSettings::new(
-            &self.options.fields.settings,
-            self.options.filter.since.is_some() || self.options.filter.until.is_some(),
-        ));
+        let mut input = BufReader::with_capacity(self.options.buffer_size, input);
+        let parser = self.parser(&mut input)?;
+        let w3c_fields = self.w3c_fields(&mut input)?;
+        let csv = match self.options.output_format {
+            OutputFormat::Csv => {
+                let csv = CsvFormatter::new(self.options.csv_columns.clone());
+                let mut header = Vec::new();
+                csv.format_header(&mut header);
+                write_all(output, &header)?;
+                Some(csv)
+            }
+            _ => None,
+        };
+        let csv = csv.as_ref();
+        let envelope = self.envelope();
+        let envelope = envelope.as_ref();
+        #[cfg(all(target_os = "linux", feature = "journald"))]
+        let journal = match self.options.output_format {
+            OutputFormat::Journald => Some(match &self.journal_path {
+                Some(path) => Journal::with_path(path)?,
+                None => Journal::new()?,
+            }),
+            _ => None,
+        };
+        #[cfg(all(target_os = "linux", feature = "journald"))]
+        let journal = journal.as_ref();
+        // prepare shared budget of records to explain
+        let explain = self.options.explain.map(AtomicUsize::new);
+        // prepare pipeline profile
+        let start = Instant::now();
+        let profile = if self.options.profile {
+            Some(Profile::default())
+        } else {
+            None
+        };
+        let profile = profile.as_ref();
         thread::scope(|scope| -> Result<()> {
             // prepare receive/transmit channels for input data
             let (txi, rxi): (Vec<_>, Vec<_>) = (0..n).map(|_| channel::bounded(1)).unzip();
             // prepare receive/transmit channels for output data
             let (txo, rxo): (Vec<_>, Vec<_>) = (0..n)
                 .into_iter()
-                .map(|_| channel::bounded::<Vec<u8>>(1))
+                .map(|_| channel::bounded::<(Vec<u8>, Vec<RecordMark>)>(1))
                 .unzip();
             // spawn reader thread
             let reader = scope.spawn(closure!(clone sfi, |_| -> Result<()> {
                 let mut sn: usize = 0;
                 let scanner = Scanner::new(sfi, "\n".to_string());
-                for item in scanner.items(input).with_max_segment_size(self.options.max_message_size) {
-                    if let Err(_) = txi[sn % n].send(item?) {
+                // in line mode each read returns a single line, so each line becomes a separate segment
+                let mut lines;
+                let input: &mut dyn Read = if self.options.line_mode {
+                    lines = LineReader::new(&mut input);
+                    &mut lines
+                } else {
+                    &mut input
+                };
+                let mut items = scanner.items(input).with_max_segment_size(self.options.max_message_size);
+                let mut offset = 0;
+                while let Some(item) = measured(profile, Stage::Reading, || items.next()) {
+                    let item = item?;
+                    let size = match &item {
+                        Segment::Complete(segment) | Segment::Incomplete(segment, _) => segment.data().len(),
+                    };
+                    if let Err(_) = txi[sn % n].send((offset, item)) {
                         break;
                     }
+                    offset += size as u64;
                     sn += 1;
                 }
                 Ok(())
             }));
+            // prepare channel for metrics collected by processing threads
+            let (txm, rxm) = channel::unbounded::<Metrics>();
             // spawn processing threads
             for (rxi, txo) in izip!(rxi, txo) {
-                scope.spawn(closure!(ref bfo, ref parser, ref sfi, |_| {
-                    let mut formatter = RecordFormatter::new(
-                        self.options.theme.clone(),
-                        DateTimeFormatter::new(
-                            self.options.time_format.clone(),
-                            self.options.time_zone,
-                        ),
-                        self.options.hide_empty_fields,
-                        self.options.fields.filter.clone(),
-                    )
-                    .with_field_unescaping(!self.options.raw_fields);
-                    let mut processor = SegmentProcesor::new(&parser, &mut formatter, &self.options.filter);
-                    for segment in rxi.iter() {
+                scope.spawn(closure!(ref bfo, ref parser, ref sfi, ref explain, ref w3c_fields, clone txm, |_| {
+                    let mut formatter = self.formatter();
+                    let processor = SegmentProcesor::new(&parser, &mut formatter, &self.options.filter)
+                        .with_quiet(self.options.quiet || self.options.output_format == OutputFormat::Csv)
+                        .with_parse_errors(self.options.show_parse_errors)
+                        .with_input_format(self.options.input_format)
+                        .with_w3c_fields(w3c_fields.clone())
+                        .with_output_format(self.options.output_format)
+                        .with_time_normalization(self.options.normalize_time)
+                        .with_metrics(self.options.metrics || self.options.count)
+                        .with_record_marking(
+                            self.options.sample.is_some()
+                                || self.options.max_rate.is_some()
+                                || self.options.reverse
+                                || split
+                                || !self.options.line_prefix.is_empty()
+                                || !self.options.line_suffix.is_empty(),
+                        )
+                        .with_record_timestamps(self.options.time_separator.is_some() || self.options.time_delta)
+                        .with_split_field(split_field)
+                        .with_explanation(explain.as_ref())
+                        .with_raw_record_output(self.options.debug_raw)
+                        .with_byte_offsets(self.options.byte_offsets)
+                        .with_input_map(self.inputs.as_deref())
+                        .with_max_line_len(self.options.max_line_len)
+                        .with_envelope(envelope)
+                        .with_csv_formatter(csv);
+                    // journal entries are sent by the writer thread to keep the order of records
+                    #[cfg(all(target_os = "linux", feature = "journald"))]
+                    let processor = if journal.is_some() { processor.with_record_marking(true) } else { processor };
+                    let mut processor = processor;
+                    for (offset, segment) in rxi.iter() {
                         match segment {
                             Segment::Complete(segment) => {
                                 let mut buf = bfo.new_buf();
-                                processor.run(segment.data(), &mut buf);
+                                measured(profile, Stage::Processing, || processor.run_at(segment.data(), &mut buf, offset));
+                                if let Some(profile) = profile {
+                                    profile.add_bytes(segment.data().len());
+                                }
                                 sfi.recycle(segment);
-                                if let Err(_) = txo.send(buf) {
+                                if let Err(_) = txo.send((buf, processor.take_record_marks())) {
                                     break;
                                 };
                             }
-                            Segment::Incomplete(segment, _) => {
-                                if let Err(_) = txo.send(segment.to_vec()) {
+                            Segment::Incomplete(segment, placement) => {
+                                if let Some(profile) = profile {
+                                    profile.add_bytes(segment.data().len());
+                                }
+                                let mut buf = bfo.new_buf();
+                                measured(profile, Stage::Processing, || processor.run_partial_at(segment.data(), placement, &mut buf, offset));
+                                sfi.recycle(segment);
+                                if let Err(_) = txo.send((buf, processor.take_record_marks())) {
                                     break;
                                 }
                             }
                         }
                     }
+                    if let Some(profile) = profile {
+                        profile.add_records(processor.records_processed());
+                    }
+                    if let Some(metrics) = processor.metrics {
+                        txm.send(metrics).ok();
+                    }
                 }));
             }
+            // metrics are complete once all processing threads drop their senders
+            drop(txm);
             // spawn writer thread
-            let writer = scope.spawn(closure!(ref bfo, |_| -> Result<()> {
+            let writer = scope.spawn(closure!(ref bfo, ref exhausted, |_| -> Result<()> {
                 let mut sn = 0;
+                let mut index = 0;
+                // all output is kept in memory until the end of input if it is reversed
+                let mut reversed = if self.options.reverse { Some(Vec::new()) } else { None };
+                let mut reversed_errors = reversed.clone();
+                let mut unreversed = None;
+                let mut bucket = None;
+                let mut separator = Vec::new();
+                let mut prev_ts = None;
+                let mut delta_buf = Vec::new();
+                let mut decorated = Vec::new();
+                let decorate = self.options.time_delta
+                    || !self.options.line_prefix.is_empty()
+                    || !self.options.line_suffix.is_empty();
+                let mut limiter = self.options.max_rate.map(RateLimiter::new);
+                let mut dropped = 0;
                 loop {
                     match rxo[sn % n].recv() {
-                        Ok(buf) => {
-                            output.write_all(&buf[..])?;
+                        Ok((buf, marks)) => {
+                            measured(profile, Stage::Writing, || -> std::io::Result<()> {
+                                let mut pos = 0;
+                                for mark in marks {
+                                    emit_lines(output, &mut reversed, &buf[pos..mark.range.start])?;
+                                    let mut keep = self.options.sample.map(|rate| rate.keeps(index)).unwrap_or(true);
+                                    if let (true, Some(limiter)) = (keep, limiter.as_mut()) {
+                                        if !limiter.try_acquire() {
+                                            match self.options.rate_overflow {
+                                                RateOverflow::Drop => {
+                                                    keep = false;
+                                                    dropped += 1;
+                                                }
+                                                RateOverflow::Queue => {
+                                                    output.flush()?;
+                                                    limiter.acquire();
+                                                }
+                                            }
+                                        }
+                                    }
+                                    if keep {
+                                        #[cfg(all(target_os = "linux", feature = "journald"))]
+                                        if let Some(journal) = journal {
+                                            journal.send(&buf[mark.range.clone()])?;
+                                            index += 1;
+                                            pos = mark.range.end;
+                                            continue;
+                                        }
+                                        let (output, reversed): (&mut (dyn Write + Send + Sync), _) = match (files.as_deref_mut(), split, mark.level) {
+                                            (Some(files), _, _) => {
+                                                let name = match &self.options.split_by {
+                                                    SplitBy::Level => mark.level.map(|level| level.as_str()),
+                                                    SplitBy::Field(_) => mark.split_key.as_deref(),
+                                                };
+                                                (files.output(name)?, &mut unreversed)
+                                            }
+                                            (None, true, Some(level)) if level <= self.options.split_level => {
+                                                (errors.as_deref_mut().unwrap(), &mut reversed_errors)
+                                            }
+                                            _ => (&mut *output, &mut reversed),
+                                        };
+                                        if let (Some(unit), Some(ts)) = (self.options.time_separator, &mark.ts) {
+                                            let tz = self.options.time_zone;
+                                            let current = Some(unit.index(ts, tz));
+                                            if bucket.is_some() && bucket != current {
+                                                separator.clear();
+                                                format_separator(&self.options.theme, &mut separator, &unit.label(ts, tz), self.options.ascii);
+                                                emit(output, reversed, &separator)?;
+                                            }
+                                            bucket = current;
+                                        }
+                                        if decorate {
+                                            delta_buf.clear();
+                                            if self.options.time_delta {
+                                                let delta = mark.ts.map(|ts| ts - prev_ts.unwrap_or(ts));
+                                                prev_ts = mark.ts.or(prev_ts);
+                                                format_time_delta(&self.options.theme, &mut delta_buf, delta);
+                                            }
+                                            decorated.clear();
+                                            decorate_record(
+                                                &mut decorated,
+                                                &buf[mark.range.clone()],
+                                                self.options.line_prefix.as_bytes(),
+                                                self.options.line_suffix.as_bytes(),
+                                                &delta_buf,
+                                            );
+                                            emit(output, reversed, &decorated)?;
+                                        } else {
+                                            emit(output, reversed, &buf[mark.range.clone()])?;
+                                        }
+                                    }
+                                    index += 1;
+                                    pos = mark.range.end;
+                                }
+                                emit_lines(output, &mut reversed, &buf[pos..])
+                            })?;
                             bfo.recycle(buf);
+                            if self.options.line_mode {
+                                output.flush()?;
+                                if let Some(errors) = errors.as_mut() {
+                                    errors.flush()?;
+                                }
+                                if let Some(files) = files.as_mut() {
+                                    files.flush()?;
+                                }
+                            }
+                            // stop the pipeline once the output budget is exhausted
+                            if exhausted.load(Ordering::Relaxed) {
+                                break;
+                            }
                         }
                         Err(RecvError) => {
                             break;
@@ -126,6 +476,38 @@ impl App {
                     }
                     sn += 1;
                 }
+                if dropped != 0 {
+                    eprintln!("dropped {} records exceeding the maximum output rate", dropped);
+                }
+                if let Some(items) = reversed {
+                    measured(profile, Stage::Writing, || -> std::io::Result<()> {
+                        for item in items.iter().rev() {
+                            write_all(output, item)?;
+                        }
+                        Ok(())
+                    })?;
+                }
+                if let (Some(items), Some(errors)) = (reversed_errors, errors.as_mut()) {
+                    measured(profile, Stage::Writing, || -> std::io::Result<()> {
+                        for item in items.iter().rev() {
+                            write_all(*errors, item)?;
+                        }
+                        Ok(())
+                    })?;
+                }
+                // release processing threads still sending output, then wait for all of them to report metrics
+                drop(rxo);
+                if self.options.metrics || self.options.count {
+                    let mut metrics = Metrics::default();
+                    for m in rxm.iter() {
+                        metrics.merge(&m);
+                    }
+                    if self.options.count {
+                        writeln!(output, "{}", metrics.records)?;
+                    } else {
+                        metrics.write(output)?;
+                    }
+                }
                 Ok(())
             }));
             // collect errors from reader and writer threads
@@ -135,8 +517,392 @@ impl App {
         })
         .unwrap()?;
 
+        if let Some(profile) = profile {
+            profile.write(&mut std::io::stderr(), start.elapsed())?;
+        }
+
         return Ok(());
     }
+
+    /// Prints statistics of field keys collected over up to `limit` first records of the input.
+    pub fn list_fields(
+        &self,
+        input: &mut (dyn Read + Send + Sync),
+        output: &mut (dyn Write + Send + Sync),
+        limit: usize,
+    ) -> Result<()> {
+        let input = BufReader::with_capacity(self.options.buffer_size, input);
+        let mut stats = FieldStats::default();
+        'lines: for line in input.split(b'\n') {
+            let line = line?;
+            let stream = json::Deserializer::from_slice(&line).into_iter::<RawRecord>();
+            for record in stream {
+                if stats.records() as usize >= limit {
+                    break 'lines;
+                }
+                match record {
+                    Ok(record) => stats.add_record(&record),
+                    Err(_) => break,
+                }
+            }
+        }
+        match self.options.output_format {
+            OutputFormat::Json => stats.write_json(output)?,
+            _ => stats.write_table(output)?,
+        }
+        Ok(())
+    }
+
+    /// Calls `visit` for each record of the input matching the filter, stops early if it returns false.
+    /// Records borrow the line they are parsed from, so they cannot outlive the call and the needed values must be copied.
+    /// Unlike `run`, it is single-threaded and expects JSON input, `--input-format` and `--unwrap` are not applied.
+    pub fn visit_records<F>(&self, input: &mut (dyn Read + Send + Sync), mut visit: F) -> Result<()>
+    where
+        F: FnMut(&Record) -> bool,
+    {
+        let mut input = BufReader::with_capacity(self.options.buffer_size, input);
+        let parser = self.parser(&mut input)?;
+        for line in input.split(b'\n') {
+            let line = line?;
+            let stream = json::Deserializer::from_slice(&line).into_iter::<RawRecord>();
+            for record in stream {
+                let record = match record {
+                    Ok(record) => parser.parse(record),
+                    Err(_) => break,
+                };
+                if record.matches(&self.options.filter) && !visit(&record) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that records are ordered by their timestamps, records without timestamps are skipped.
+    /// Fails with an error describing the first out-of-order record.
+    /// Lines are numbered within each input if the map of concatenated inputs is set.
+    pub fn check_sorted(&self, input: &mut (dyn Read + Send + Sync)) -> Result<()> {
+        let mut input = BufReader::with_capacity(self.options.buffer_size, input);
+        let envelope = self.envelope();
+        let converter = LineConverter::new(self.options.input_format)
+            .with_w3c_fields(self.w3c_fields(&mut input)?)
+            .with_envelope(envelope.as_ref());
+        check_sorted(
+            &self.time_parser(),
+            converter,
+            input,
+            self.inputs.as_deref(),
+        )
+    }
+
+    /// Returns the earliest and the latest timestamps of records in the input, records without timestamps are skipped.
+    pub fn time_range(
+        &self,
+        input: &mut (dyn Read + Send + Sync),
+    ) -> Result<Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>> {
+        let mut input = BufReader::with_capacity(self.options.buffer_size, input);
+        let envelope = self.envelope();
+        let converter = LineConverter::new(self.options.input_format)
+            .with_w3c_fields(self.w3c_fields(&mut input)?)
+            .with_envelope(envelope.as_ref());
+        time_range(&self.time_parser(), converter, input)
+    }
+
+    /// Sets bounds of the time filter, `None` keeps the corresponding bound as is.
+    pub fn with_time_filter(
+        mut self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Self {
+        self.options.filter.since = since.or(self.options.filter.since);
+        self.options.filter.until = until.or(self.options.filter.until);
+        self
+    }
+
+    /// Sets the path of the socket used for journald output instead of the default one.
+    #[cfg(all(target_os = "linux", feature = "journald"))]
+    pub fn with_journal_path(mut self, path: std::path::PathBuf) -> Self {
+        self.journal_path = Some(path);
+        self
+    }
+
+    /// Sets the map of concatenated inputs used to report byte offsets within each input.
+    pub fn with_input_map(mut self, inputs: Arc<InputMap>) -> Self {
+        self.inputs = Some(inputs);
+        self
+    }
+
+    /// Formats already parsed records in the human-readable format, bypassing scanning, parsing and filtering.
+    /// Unlike `run`, it is single-threaded and synchronous. Aligned fields are aligned across all given records.
+    pub fn format_records(&self, records: &[Record], output: &mut impl Write) -> Result<()> {
+        let parser = self.time_parser();
+        let mut formatter = self.formatter();
+        let mut processor = SegmentProcesor::new(&parser, &mut formatter, &self.options.filter);
+        let mut buf = Vec::new();
+        processor.run_records(records, &mut buf);
+        output.write_all(&buf)?;
+        Ok(())
+    }
+
+    fn parser<R: BufRead>(&self, input: &mut R) -> Result<Parser> {
+        let mut parser = Parser::new(
+            ParserSettings::new(
+                &self.options.fields.settings,
+                self.options.filter.filters_time(),
+            )
+            .with_level_inference(self.options.infer_level)
+            .with_status_field(self.options.status_field.clone())
+            .with_duplicate_keys(self.options.duplicate_keys)
+            .with_level_mapping(self.options.level_mapping.clone())
+            .with_time_format(self.options.time_input_format.clone()),
+        );
+        if self.options.auto_time {
+            if let Some((name, format)) = parser.detect_time_field(input.fill_buf()?) {
+                if self.options.verbose {
+                    eprintln!("detected time field {:?} ({})", name, format);
+                }
+                parser = parser.with_time_field(&name);
+            }
+        }
+        Ok(parser)
+    }
+
+    fn w3c_fields<R: BufRead>(&self, input: &mut R) -> Result<W3cFields> {
+        Ok(match self.options.input_format {
+            InputFormat::W3c => W3cFields::detect(input.fill_buf()?).unwrap_or_default(),
+            _ => W3cFields::default(),
+        })
+    }
+
+    fn envelope(&self) -> Option<Envelope> {
+        self.options.unwrap.as_ref().map(|field| {
+            Envelope::new(field.clone())
+                .with_time_fields(self.options.fields.settings.predefined.time.names.clone())
+        })
+    }
+
+    fn time_parser(&self) -> Parser {
+        Parser::new(
+            ParserSettings::new(&self.options.fields.settings, false)
+                .with_time_format(self.options.time_input_format.clone()),
+        )
+    }
+
+    fn formatter(&self) -> RecordFormatter {
+        RecordFormatter::new(
+            self.options.theme.clone(),
+            DateTimeFormatter::new(self.options.time_format.clone(), self.options.time_zone),
+            self.options.hide_empty_fields,
+            self.options.fields.filter.clone(),
+        )
+        .with_time_zone_field(self.options.time_zone_field.clone())
+        .with_secondary_time_formatter(self.options.secondary_time_zone.map(|tz| {
+            DateTimeFormatter::new(LinuxDateFormat::new(SECONDARY_TIME_FORMAT).compile(), tz)
+        }))
+        .with_field_unescaping(!self.options.raw_fields)
+        .with_raw_fields(&self.options.raw_field_keys)
+        .with_nested_fields(&self.options.nested_fields)
+        .with_anonymized_fields(
+            &self.options.anonymized_fields,
+            self.options.anonymizer.clone(),
+        )
+        .with_empty_values(self.options.fields.settings.empty.clone())
+        .with_key_prefix_stripping(self.options.strip_key_prefixes.clone())
+        .with_object_flattening(self.options.flatten_objects)
+        .with_array_flattening(self.options.flatten_arrays)
+        .with_path_delimiter(self.options.path_delimiter)
+        .with_field_alignment(self.options.align_fields)
+        .with_field_sorting(self.options.sort_fields)
+        .with_ellipsis(self.options.ellipsis.clone())
+        .with_null_text(self.options.null_text.clone())
+        .with_hidden_count(self.options.show_hidden_count)
+        .with_max_value_len(self.options.max_value_len)
+        .with_ascii(self.options.ascii)
+        .with_message_level_highlighting(self.options.highlight_levels_in_message)
+        .with_message_key_value_highlighting(self.options.highlight_kv)
+        .with_message_only(self.options.message_only)
+        .with_missing_message_skipping(self.options.skip_missing_messages)
+        .with_max_fields(self.options.max_fields)
+        .with_trailing_reset(self.options.trailing_reset)
+        .with_field_folding(self.options.fold_fields)
+        .with_layout(self.options.layout)
+        .with_status_fields(
+            self.options.status_fields.clone(),
+            self.options.status_width,
+        )
+    }
+}
+
+// ---
+
+fn check_sorted<R: BufRead>(
+    parser: &Parser,
+    mut converter: LineConverter,
+    input: R,
+    inputs: Option<&InputMap>,
+) -> Result<()> {
+    let mut last: Option<DateTime<FixedOffset>> = None;
+    let mut pos = 0;
+    let mut start = None;
+    let mut n = 0;
+    let (mut converted, mut unwrapped) = (Vec::new(), Vec::new());
+    for line in input.split(b'\n') {
+        let line = line?;
+        let location = inputs.and_then(|inputs| inputs.locate(pos));
+        // restart numbering at the first line of each input
+        if let Some((_, offset)) = location {
+            if start != Some(pos - offset) {
+                start = Some(pos - offset);
+                n = if offset == 0 { 0 } else { 1 };
+            }
+        }
+        n += 1;
+        pos += line.len() as u64 + 1;
+        let data = match converter.convert(&line, &mut converted, &mut unwrapped) {
+            Some(data) => data,
+            None => continue,
+        };
+        let stream = json::Deserializer::from_slice(data).into_iter::<RawRecord>();
+        for record in stream {
+            let ts = match record {
+                Ok(record) => parser.parse(record).ts.and_then(|ts| ts.parse()),
+                Err(_) => break,
+            };
+            if let Some(ts) = ts {
+                if let Some(prev) = last.filter(|prev| *prev > ts) {
+                    return Err(Error::UnsortedRecords {
+                        location: match location {
+                            Some((name, _)) => format!("line {} of {}", n, name),
+                            None => format!("line {}", n),
+                        },
+                        prev: prev.to_rfc3339(),
+                        next: ts.to_rfc3339(),
+                    });
+                }
+                last = Some(ts);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn time_range<R: BufRead>(
+    parser: &Parser,
+    mut converter: LineConverter,
+    input: R,
+) -> Result<Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>> {
+    let mut range: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> = None;
+    let (mut converted, mut unwrapped) = (Vec::new(), Vec::new());
+    for line in input.split(b'\n') {
+        let line = line?;
+        let data = match converter.convert(&line, &mut converted, &mut unwrapped) {
+            Some(data) => data,
+            None => continue,
+        };
+        let stream = json::Deserializer::from_slice(data).into_iter::<RawRecord>();
+        for record in stream {
+            let ts = match record {
+                Ok(record) => parser.parse(record).ts.and_then(|ts| ts.parse()),
+                Err(_) => break,
+            };
+            if let Some(ts) = ts {
+                range = Some(match range {
+                    Some((min, max)) => (min.min(ts), max.max(ts)),
+                    None => (ts, ts),
+                });
+            }
+        }
+    }
+    Ok(range)
+}
+
+// ---
+
+/// Converts input lines to JSON according to the input format and unwraps enveloped records.
+pub struct LineConverter<'a> {
+    format: InputFormat,
+    w3c_fields: W3cFields,
+    envelope: Option<&'a Envelope>,
+}
+
+impl<'a> LineConverter<'a> {
+    pub fn new(format: InputFormat) -> Self {
+        Self {
+            format,
+            w3c_fields: W3cFields::default(),
+            envelope: None,
+        }
+    }
+
+    /// Sets columns of W3C Extended Log Format lines until a `#Fields:` directive is met.
+    pub fn with_w3c_fields(mut self, value: W3cFields) -> Self {
+        self.w3c_fields = value;
+        self
+    }
+
+    /// Sets the envelope used to unwrap records embedded as a string field of outer records.
+    pub fn with_envelope(mut self, envelope: Option<&'a Envelope>) -> Self {
+        self.envelope = envelope;
+        self
+    }
+
+    /// Returns the line converted using the given buffers, or the line as is if it cannot be converted.
+    /// Returns `None` for W3C directives, which contain no records.
+    pub fn convert<'b>(
+        &mut self,
+        data: &'b [u8],
+        converted: &'b mut Vec<u8>,
+        unwrapped: &'b mut Vec<u8>,
+    ) -> Option<&'b [u8]> {
+        let data = match self.format {
+            InputFormat::Json => data,
+            format => {
+                converted.clear();
+                let ok = match format {
+                    InputFormat::Syslog => syslog::rfc5424_to_json(data, converted),
+                    InputFormat::Apache => weblog::combined_to_json(data, converted),
+                    InputFormat::W3c => {
+                        if W3cFields::is_directive(data) {
+                            if let Some(fields) = W3cFields::parse(data) {
+                                self.w3c_fields = fields;
+                            }
+                            return None;
+                        }
+                        self.w3c_fields.to_json(data, converted)
+                    }
+                    _ => syslog::cef_to_json(data, converted),
+                };
+                if ok {
+                    &converted[..]
+                } else {
+                    data
+                }
+            }
+        };
+        Some(match self.envelope {
+            Some(envelope) => {
+                unwrapped.clear();
+                if envelope.unwrap(data, unwrapped) {
+                    &unwrapped[..]
+                } else {
+                    data
+                }
+            }
+            None => data,
+        })
+    }
+}
+
+// ---
+
+/// Output range occupied by a formatted record along with its level and its timestamp if requested.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordMark {
+    pub range: Range<usize>,
+    pub level: Option<Level>,
+    pub ts: Option<DateTime<FixedOffset>>,
+    /// Value of the split field if it is requested and present in the record.
+    pub split_key: Option<String>,
 }
 
 // ---
@@ -145,6 +911,22 @@ pub struct SegmentProcesor<'a> {
     parser: &'a Parser,
     formatter: &'a mut RecordFormatter,
     filter: &'a Filter,
+    quiet: bool,
+    show_parse_errors: bool,
+    metrics: Option<Metrics>,
+    marks: Option<Vec<RecordMark>>,
+    mark_timestamps: bool,
+    split_field: Option<&'a str>,
+    explain: Option<&'a AtomicUsize>,
+    debug_raw: bool,
+    offsets: Option<u64>,
+    inputs: Option<&'a InputMap>,
+    max_line_len: Option<usize>,
+    csv: Option<&'a CsvFormatter>,
+    records: u64,
+    converter: LineConverter<'a>,
+    output_format: OutputFormat,
+    ts_normalizer: Option<DateTimeFormatter>,
 }
 
 impl<'a> SegmentProcesor<'a> {
@@ -153,22 +935,360 @@ impl<'a> SegmentProcesor<'a> {
             parser,
             formatter,
             filter,
+            quiet: false,
+            show_parse_errors: false,
+            metrics: None,
+            marks: None,
+            mark_timestamps: false,
+            split_field: None,
+            explain: None,
+            debug_raw: false,
+            offsets: None,
+            inputs: None,
+            max_line_len: None,
+            csv: None,
+            records: 0,
+            converter: LineConverter::new(InputFormat::default()),
+            output_format: OutputFormat::default(),
+            ts_normalizer: None,
+        }
+    }
+
+    /// Suppresses passthrough of lines that cannot be parsed as records, even if no filter is active.
+    pub fn with_quiet(mut self, value: bool) -> Self {
+        self.quiet = value;
+        self
+    }
+
+    /// Enables output of lines that cannot be parsed along with the reason, even if a filter is active.
+    pub fn with_parse_errors(mut self, value: bool) -> Self {
+        self.show_parse_errors = value;
+        self
+    }
+
+    /// Enables collection of metrics instead of producing output.
+    pub fn with_metrics(mut self, value: bool) -> Self {
+        self.metrics = if value {
+            Some(Metrics::default())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Returns metrics collected so far if metrics collection is enabled.
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+
+    /// Enables tracking of output ranges occupied by each formatted record.
+    pub fn with_record_marking(mut self, value: bool) -> Self {
+        self.marks = if value { Some(Vec::new()) } else { None };
+        self
+    }
+
+    /// Enables tracking of parsed timestamps of marked records.
+    pub fn with_record_timestamps(mut self, value: bool) -> Self {
+        self.mark_timestamps = value;
+        if value && self.marks.is_none() {
+            self.marks = Some(Vec::new());
         }
+        self
     }
 
+    /// Enables tracking of values of the field with the given key in marked records.
+    pub fn with_split_field(mut self, key: Option<&'a str>) -> Self {
+        self.split_field = key;
+        if key.is_some() && self.marks.is_none() {
+            self.marks = Some(Vec::new());
+        }
+        self
+    }
+
+    /// Returns marks of records formatted since the previous call if record marking is enabled.
+    pub fn take_record_marks(&mut self) -> Vec<RecordMark> {
+        self.marks.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Enables printing to stderr of filter predicate results for each record
+    /// while the given shared budget of records to explain is not exhausted.
+    pub fn with_explanation(mut self, budget: Option<&'a AtomicUsize>) -> Self {
+        self.explain = budget;
+        self
+    }
+
+    /// Enables output of the raw source of each record on the next line after the formatted record.
+    pub fn with_raw_record_output(mut self, value: bool) -> Self {
+        self.debug_raw = value;
+        self
+    }
+
+    /// Returns the number of records parsed so far.
+    pub fn records_processed(&self) -> u64 {
+        self.records
+    }
+
+    /// Sets the formatter used for csv output format.
+    pub fn with_csv_formatter(mut self, csv: Option<&'a CsvFormatter>) -> Self {
+        self.csv = csv;
+        self
+    }
+
+    /// Truncates lines exceeding the maximum message size to the given number of bytes instead of passing them through.
+    /// The limit is effectively capped by the size of the first part of such a line.
+    pub fn with_max_line_len(mut self, value: Option<usize>) -> Self {
+        self.max_line_len = value;
+        self
+    }
+
+    /// Sets the format of the input lines.
+    pub fn with_input_format(mut self, value: InputFormat) -> Self {
+        self.converter.format = value;
+        self
+    }
+
+    /// Sets columns of W3C Extended Log Format lines until a `#Fields:` directive is met.
+    pub fn with_w3c_fields(mut self, value: W3cFields) -> Self {
+        self.converter.w3c_fields = value;
+        self
+    }
+
+    /// Sets the envelope used to unwrap records embedded as a string field of outer records.
+    pub fn with_envelope(mut self, envelope: Option<&'a Envelope>) -> Self {
+        self.converter.envelope = envelope;
+        self
+    }
+
+    pub fn with_output_format(mut self, value: OutputFormat) -> Self {
+        self.output_format = value;
+        self
+    }
+
+    /// Enables normalization of timestamps to RFC3339 UTC for JSON output.
+    pub fn with_time_normalization(mut self, value: bool) -> Self {
+        self.ts_normalizer = if value {
+            Some(DateTimeFormatter::new(
+                LinuxDateFormat::new("%Y-%m-%dT%T.%NZ").compile(),
+                FixedOffset::east(0),
+            ))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Enables prefixing of each formatted record with its byte offset in the input, applies to human output format only.
+    pub fn with_byte_offsets(mut self, value: bool) -> Self {
+        self.offsets = if value { Some(0) } else { None };
+        self
+    }
+
+    /// Reports byte offsets within each of the concatenated inputs prefixed with the input name.
+    pub fn with_input_map(mut self, inputs: Option<&'a InputMap>) -> Self {
+        self.inputs = inputs;
+        self
+    }
+
+    /// Processes the segment and writes the output to the buffer.
+    ///
+    /// If the formatter aligns fields, the segment is formatted twice,
+    /// first to collect field widths and then to produce the output.
     pub fn run(&mut self, data: &[u8], buf: &mut Vec<u8>) {
+        self.run_at(data, buf, 0)
+    }
+
+    /// Same as [`run`](Self::run) for the segment starting at the given byte offset in the input.
+    pub fn run_at(&mut self, data: &[u8], buf: &mut Vec<u8>, offset: u64) {
+        if let Some(base) = self.offsets.as_mut() {
+            *base = offset;
+        }
+        self.run_passes(buf, |processor, buf| processor.process(data, buf));
+    }
+
+    /// Formats already parsed records in the human-readable format, bypassing filtering.
+    /// Fields are aligned the same way as in [`run`](Self::run).
+    pub fn run_records(&mut self, records: &[Record], buf: &mut Vec<u8>) {
+        self.run_passes(buf, |processor, buf| {
+            for record in records {
+                processor.formatter.format_record(buf, record);
+            }
+        });
+    }
+
+    fn run_passes<F: FnMut(&mut Self, &mut Vec<u8>)>(&mut self, buf: &mut Vec<u8>, mut pass: F) {
+        if self.formatter.aligns_fields()
+            && self.output_format == OutputFormat::Human
+            && self.metrics.is_none()
+        {
+            self.formatter.reset_field_widths();
+            let n = buf.len();
+            let explain = self.explain.take();
+            let records = self.records;
+            let folded = self.formatter.folded_fields();
+            pass(self, buf);
+            self.explain = explain;
+            self.records = records;
+            self.formatter.set_folded_fields(folded);
+            buf.truncate(n);
+            self.take_record_marks();
+        }
+        pass(self, buf);
+    }
+
+    /// Processes a part of a line exceeding the maximum message size, such lines cannot be parsed as records.
+    /// The last part may be followed by complete lines which are processed as usual.
+    pub fn run_partial(&mut self, data: &[u8], placement: PartialPlacement, buf: &mut Vec<u8>) {
+        self.run_partial_at(data, placement, buf, 0)
+    }
+
+    /// Same as [`run_partial`](Self::run_partial) for the segment starting at the given byte offset in the input.
+    pub fn run_partial_at(
+        &mut self,
+        data: &[u8],
+        placement: PartialPlacement,
+        buf: &mut Vec<u8>,
+        offset: u64,
+    ) {
+        if placement == PartialPlacement::Last {
+            let end = data
+                .iter()
+                .position(|&c| c == b'\n')
+                .map_or(data.len(), |i| i + 1);
+            self.run_partial_line(&data[..end], placement, buf, offset);
+            if end < data.len() {
+                if let Some(base) = self.offsets.as_mut() {
+                    *base = offset + end as u64;
+                }
+                self.process(&data[end..], buf);
+            }
+        } else {
+            self.run_partial_line(data, placement, buf, offset);
+        }
+    }
+
+    fn run_partial_line(
+        &mut self,
+        data: &[u8],
+        placement: PartialPlacement,
+        buf: &mut Vec<u8>,
+        offset: u64,
+    ) {
+        let first = placement == PartialPlacement::First;
+        if let Some(metrics) = self.metrics.as_mut() {
+            if first {
+                metrics.add_oversized_line();
+            }
+            return;
+        }
+        match self.max_line_len {
+            Some(max) => {
+                if first {
+                    let mut end = max.min(data.len());
+                    while end < data.len() && end > 0 && data[end] & 0xc0 == 0x80 {
+                        end -= 1;
+                    }
+                    if self.offsets.is_some() && self.output_format == OutputFormat::Human {
+                        self.format_offset(buf, offset);
+                    }
+                    self.formatter.format_truncated_line(buf, &data[..end]);
+                }
+            }
+            None => buf.extend_from_slice(data),
+        }
+    }
+
+    fn process(&mut self, data: &[u8], buf: &mut Vec<u8>) {
+        let segment = data.as_ptr() as usize;
         for data in rtrim(data, b'\n').split(|c| *c == b'\n') {
-            if data.len() == 0 {
-                buf.push(b'\n');
+            // treat lines consisting of a sole carriage return left from CRLF line endings as empty lines
+            if rtrim(data, b'\r').len() == 0 {
+                if self.metrics.is_none() && self.output_format != OutputFormat::Csv {
+                    buf.push(b'\n');
+                }
                 continue;
             }
+            let line = data.as_ptr() as usize - segment;
+            let (mut converted, mut unwrapped) = (Vec::new(), Vec::new());
+            let data = match self.converter.convert(data, &mut converted, &mut unwrapped) {
+                Some(data) => data,
+                None => continue,
+            };
             let mut stream = json::Deserializer::from_slice(data).into_iter::<RawRecord>();
             let mut some = false;
-            while let Some(Ok(record)) = stream.next() {
+            let mut offset = 0;
+            let mut error = None;
+            loop {
+                let record = match stream.next() {
+                    Some(Ok(record)) => record,
+                    Some(Err(err)) => {
+                        error = Some(err);
+                        break;
+                    }
+                    None => break,
+                };
                 some = true;
+                self.records += 1;
+                let raw = &data[offset..stream.byte_offset()];
+                offset = stream.byte_offset();
                 let record = self.parser.parse(record);
+                if let Some(budget) = self.explain {
+                    let take = |n: usize| n.checked_sub(1);
+                    if budget
+                        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, take)
+                        .is_ok()
+                    {
+                        let mut text = Vec::new();
+                        format_explanation(&mut text, ltrim(raw), &record.explain(self.filter));
+                        std::io::stderr().write_all(&text).ok();
+                    }
+                }
                 if record.matches(self.filter) {
-                    self.formatter.format_record(buf, &record);
+                    if let Some(metrics) = self.metrics.as_mut() {
+                        metrics.add_record(record.level);
+                        continue;
+                    }
+                    let start = buf.len();
+                    match self.output_format {
+                        OutputFormat::Human => {
+                            if let Some(base) = self.offsets {
+                                let start =
+                                    line + (ltrim(raw).as_ptr() as usize - data.as_ptr() as usize);
+                                self.format_offset(buf, base + start as u64);
+                            }
+                            self.formatter.format_record(buf, &record);
+                            if self.debug_raw {
+                                self.formatter.format_raw(buf, ltrim(raw));
+                            }
+                        }
+                        OutputFormat::Json => self.format_json(buf, raw, &record),
+                        OutputFormat::Csv => {
+                            if let Some(csv) = self.csv {
+                                csv.format_record(buf, &record);
+                            }
+                        }
+                        #[cfg(all(target_os = "linux", feature = "journald"))]
+                        OutputFormat::Journald => journald::serialize(buf, &record),
+                    }
+                    if let Some(marks) = self.marks.as_mut() {
+                        let ts = if self.mark_timestamps {
+                            record.ts.as_ref().and_then(|ts| ts.parse())
+                        } else {
+                            None
+                        };
+                        let split_key = self.split_field.and_then(|key| {
+                            let (_, value) = record.fields().find(|(k, _)| *k == key)?;
+                            Some(
+                                json::from_str::<String>(value.get())
+                                    .unwrap_or_else(|_| value.get().into()),
+                            )
+                        });
+                        marks.push(RecordMark {
+                            range: start..buf.len(),
+                            level: record.level,
+                            ts,
+                            split_key,
+                        });
+                    }
                 }
             }
             let remainder = if some {
@@ -176,16 +1296,87 @@ impl<'a> SegmentProcesor<'a> {
             } else {
                 data
             };
-            if remainder.len() != 0 && self.filter.is_empty() {
-                buf.extend_from_slice(remainder);
-                buf.push(b'\n');
+            if remainder.len() != 0 {
+                if let Some(metrics) = self.metrics.as_mut() {
+                    metrics.add_invalid_line();
+                } else if self.show_parse_errors {
+                    buf.extend_from_slice(remainder);
+                    buf.push(b'\n');
+                    if let Some(err) = error {
+                        self.formatter.format_parse_error(buf, &err.to_string());
+                    }
+                } else if self.filter.is_empty() && !self.quiet {
+                    buf.extend_from_slice(remainder);
+                    buf.push(b'\n');
+                }
+            }
+        }
+    }
+
+    fn format_offset(&self, buf: &mut Vec<u8>, offset: u64) {
+        match self.inputs.and_then(|inputs| inputs.locate(offset)) {
+            Some((input, offset)) => self.formatter.format_offset(buf, Some(input), offset),
+            None => self.formatter.format_offset(buf, None, offset),
+        }
+    }
+
+    fn format_json(&self, buf: &mut Vec<u8>, raw: &[u8], record: &Record) {
+        let raw = ltrim(raw);
+        if let (Some(normalizer), Some(ts)) = (&self.ts_normalizer, &record.ts) {
+            let value = ts.raw().as_bytes();
+            let start = (value.as_ptr() as usize).wrapping_sub(raw.as_ptr() as usize);
+            if start < raw.len() {
+                let mut normalized = Vec::new();
+                if ts
+                    .as_rfc3339()
+                    .and_then(|ts| normalizer.reformat_rfc3339(&mut normalized, ts))
+                    .is_none()
+                {
+                    if let Some(ts) = ts.parse() {
+                        normalizer.format(&mut normalized, ts);
+                    }
+                }
+                if !normalized.is_empty() {
+                    let end = start + value.len();
+                    let quoted = start > 0 && raw[start - 1] == b'"';
+                    buf.extend_from_slice(&raw[..start]);
+                    if !quoted {
+                        buf.push(b'"');
+                    }
+                    buf.extend_from_slice(&normalized);
+                    if !quoted {
+                        buf.push(b'"');
+                    }
+                    buf.extend_from_slice(&raw[end..]);
+                    buf.push(b'\n');
+                    return;
+                }
             }
         }
+        buf.extend_from_slice(raw);
+        buf.push(b'\n');
     }
 }
 
 // ---
 
+fn ltrim(s: &[u8]) -> &[u8] {
+    let n = s.iter().take_while(|c| c.is_ascii_whitespace()).count();
+    &s[n..]
+}
+
+fn format_explanation(buf: &mut Vec<u8>, raw: &[u8], results: &[(String, bool)]) {
+    let kept = results.iter().all(|(_, passed)| *passed);
+    buf.extend_from_slice(if kept { b"kept: " } else { b"dropped: " });
+    buf.extend_from_slice(raw);
+    buf.push(b'\n');
+    for (predicate, passed) in results {
+        buf.extend_from_slice(if *passed { b"  pass: " } else { b"  fail: " });
+        buf.extend_from_slice(predicate.as_bytes());
+        buf.push(b'\n');
+    }
+}
+
 fn rtrim<'a>(s: &'a [u8], c: u8) -> &'a [u8] {
     if s.len() > 0 && s[s.len() - 1] == c {
         &s[..s.len() - 1]
@@ -193,3 +1384,971 @@ fn rtrim<'a>(s: &'a [u8], c: u8) -> &'a [u8] {
         s
     }
 }
+
+/// Runs the given function measuring its duration as a part of the given stage if profiling is enabled.
+fn measured<T, F: FnOnce() -> T>(profile: Option<&Profile>, stage: Stage, f: F) -> T {
+    match profile {
+        Some(profile) => profile.measure(stage, f),
+        None => f(),
+    }
+}
+
+/// Appends the formatted record to the buffer wrapping each of its lines with the prefix and suffix,
+/// which are placed outside of styles of the line. The column goes right after the prefix of the first line.
+fn decorate_record(buf: &mut Vec<u8>, record: &[u8], prefix: &[u8], suffix: &[u8], column: &[u8]) {
+    for (i, line) in record.split_inclusive(|c| *c == b'\n').enumerate() {
+        buf.extend_from_slice(prefix);
+        if i == 0 {
+            buf.extend_from_slice(column);
+        }
+        match line.strip_suffix(b"\n") {
+            Some(line) => {
+                buf.extend_from_slice(line);
+                buf.extend_from_slice(suffix);
+                buf.push(b'\n');
+            }
+            None => {
+                buf.extend_from_slice(line);
+                buf.extend_from_slice(suffix);
+            }
+        }
+    }
+}
+
+/// Writes data to the output or keeps it as a single item if the output is reversed.
+fn emit<W: Write + ?Sized>(
+    output: &mut W,
+    reversed: &mut Option<Vec<Vec<u8>>>,
+    data: &[u8],
+) -> std::io::Result<()> {
+    match reversed {
+        Some(items) => {
+            if !data.is_empty() {
+                items.push(data.to_vec());
+            }
+            Ok(())
+        }
+        None => write_all(output, data),
+    }
+}
+
+/// Same as [`emit`] but keeps each line as a separate item if the output is reversed.
+fn emit_lines<W: Write + ?Sized>(
+    output: &mut W,
+    reversed: &mut Option<Vec<Vec<u8>>>,
+    data: &[u8],
+) -> std::io::Result<()> {
+    match reversed {
+        Some(items) => {
+            items.extend(
+                data.split_inclusive(|c| *c == b'\n')
+                    .map(|line| line.to_vec()),
+            );
+            Ok(())
+        }
+        None => write_all(output, data),
+    }
+}
+
+/// Writes all data to the output retrying with a short backoff on transient errors
+/// such as `WouldBlock` and `Interrupted` caused by a slow consumer.
+fn write_all<W: Write + ?Sized>(output: &mut W, mut data: &[u8]) -> std::io::Result<()> {
+    let mut delay = WRITE_RETRY_MIN_DELAY;
+    while !data.is_empty() {
+        match output.write(data) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                data = &data[n..];
+                delay = WRITE_RETRY_MIN_DELAY;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(delay);
+                delay = std::cmp::min(delay * 2, WRITE_RETRY_MAX_DELAY);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+const SECONDARY_TIME_FORMAT: &str = "%T";
+const WRITE_RETRY_MIN_DELAY: Duration = Duration::from_millis(1);
+const WRITE_RETRY_MAX_DELAY: Duration = Duration::from_millis(100);
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::FixedOffset;
+
+    use crate::datefmt::LinuxDateFormat;
+    use crate::input::{ConcatReader, Input};
+    use crate::model::Level;
+    use crate::settings::Settings;
+
+    fn formatter() -> RecordFormatter {
+        RecordFormatter::new(
+            Arc::new(Theme::none()),
+            DateTimeFormatter::new(
+                LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                FixedOffset::east(0),
+            ),
+            false,
+            Arc::new(IncludeExcludeKeyFilter::default()),
+        )
+    }
+
+    fn options() -> Options {
+        Options {
+            theme: Arc::new(Theme::none()),
+            time_format: LinuxDateFormat::new("%b %d %T.%3N").compile(),
+            raw_fields: false,
+            raw_field_keys: Vec::new(),
+            nested_fields: Vec::new(),
+            anonymized_fields: Vec::new(),
+            anonymizer: Arc::new(Anonymizer::new(None)),
+            buffer_size: 4096,
+            line_mode: false,
+            max_message_size: 4096,
+            concurrency: 1,
+            filter: Filter::default(),
+            fields: FieldOptions {
+                filter: Arc::new(IncludeExcludeKeyFilter::default()),
+                settings: Settings::default().fields,
+            },
+            time_zone: FixedOffset::east(0),
+            secondary_time_zone: None,
+            time_zone_field: None,
+            hide_empty_fields: false,
+            quiet: false,
+            show_parse_errors: false,
+            input_format: InputFormat::default(),
+            unwrap: None,
+            output_format: OutputFormat::default(),
+            normalize_time: false,
+            flatten_objects: false,
+            flatten_arrays: false,
+            path_delimiter: b'.',
+            align_fields: false,
+            sort_fields: false,
+            ellipsis: None,
+            null_text: None,
+            show_hidden_count: false,
+            max_value_len: None,
+            max_line_len: None,
+            max_output_bytes: None,
+            ascii: false,
+            highlight_levels_in_message: false,
+            highlight_kv: false,
+            message_only: false,
+            skip_missing_messages: false,
+            max_fields: None,
+            trailing_reset: true,
+            status_fields: Vec::new(),
+            status_width: 0,
+            infer_level: false,
+            status_field: None,
+            duplicate_keys: DuplicateKeys::default(),
+            auto_time: false,
+            time_input_format: None,
+            verbose: false,
+            level_mapping: LevelMapping::default(),
+            metrics: false,
+            count: false,
+            sample: None,
+            max_rate: None,
+            rate_overflow: RateOverflow::default(),
+            explain: None,
+            debug_raw: false,
+            byte_offsets: false,
+            reverse: false,
+            strip_key_prefixes: Vec::new(),
+            csv_columns: Vec::new(),
+            profile: false,
+            time_separator: None,
+            time_delta: false,
+            line_prefix: String::new(),
+            line_suffix: String::new(),
+            fold_fields: false,
+            layout: Layout::default(),
+            split_level: Level::Warning,
+            split_by: SplitBy::default(),
+        }
+    }
+
+    fn run(options: Options, data: &str) -> String {
+        let mut output = Vec::new();
+        App::new(options)
+            .run(&mut data.as_bytes(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_count_with_high_concurrency() {
+        let data: String = (0..2000)
+            .map(|i| format!("{{\"msg\":\"record {}\",\"level\":\"info\"}}\n", i))
+            .collect();
+        for _ in 0..20 {
+            let options = Options {
+                count: true,
+                concurrency: 32,
+                buffer_size: 1024,
+                ..options()
+            };
+            assert_eq!(run(options, &data), "2000\n");
+        }
+    }
+
+    #[test]
+    fn test_count_with_filter() {
+        let data: String = (0..1000)
+            .map(|i| {
+                let level = if i % 4 == 0 { "error" } else { "info" };
+                format!("{{\"msg\":\"record {}\",\"level\":\"{}\"}}\n", i, level)
+            })
+            .collect();
+        let options = Options {
+            count: true,
+            concurrency: 8,
+            buffer_size: 512,
+            filter: Filter {
+                level: Some(Level::Error),
+                ..Default::default()
+            },
+            ..options()
+        };
+        assert_eq!(run(options, &data), "250\n");
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "journald"))]
+    fn test_sample_journald() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = std::env::temp_dir().join(format!(
+            "hl-test-sample-journal-{}.sock",
+            std::process::id()
+        ));
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket.set_nonblocking(true).unwrap();
+        let data: String = (0..4).map(|i| format!("{{\"msg\":\"{}\"}}\n", i)).collect();
+        let app = App::new(Options {
+            output_format: OutputFormat::Journald,
+            sample: Some("1/2".parse().unwrap()),
+            ..options()
+        })
+        .with_journal_path(path.clone());
+        app.run(&mut data.as_bytes(), &mut Vec::new()).unwrap();
+        let mut buf = [0; 256];
+        let mut messages = Vec::new();
+        while let Ok(n) = socket.recv(&mut buf) {
+            messages.push(buf[..n].to_vec());
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with(b"MESSAGE=0\n"));
+        assert!(messages[1].starts_with(b"MESSAGE=2\n"));
+    }
+
+    #[test]
+    fn test_visit_records() {
+        let data = concat!(
+            r#"{"msg":"a","level":"error"}"#,
+            "\nnot a json\n",
+            r#"{"msg":"b","level":"info"} {"msg":"c","level":"error"}"#,
+            "\n",
+            r#"{"msg":"d","level":"error"}"#,
+            "\n",
+        );
+        let app = App::new(Options {
+            filter: Filter {
+                level: Some(Level::Error),
+                ..Default::default()
+            },
+            ..options()
+        });
+        let visit = |limit: usize| {
+            let mut messages = Vec::new();
+            app.visit_records(&mut data.as_bytes(), |record| {
+                messages.push(
+                    record
+                        .message
+                        .map(|x| x.get().to_owned())
+                        .unwrap_or_default(),
+                );
+                messages.len() < limit
+            })
+            .unwrap();
+            messages
+        };
+        assert_eq!(visit(10), [r#""a""#, r#""c""#, r#""d""#]);
+        assert_eq!(visit(2), [r#""a""#, r#""c""#]);
+    }
+
+    #[test]
+    fn test_format_records() {
+        let data = [r#"{"msg":"a","x":1,"y":2}"#, r#"{"msg":"b","x":100,"y":3}"#];
+        let aligned = || Options {
+            align_fields: true,
+            ..options()
+        };
+        let expected = run(aligned(), &(data.join("\n") + "\n"));
+        let parser = Parser::new(ParserSettings::new(&Settings::default().fields, false));
+        let records: Vec<_> = data
+            .iter()
+            .map(|x| parser.parse(json::from_str::<RawRecord>(x).unwrap()))
+            .collect();
+        let mut output = Vec::new();
+        App::new(aligned())
+            .format_records(&records, &mut output)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        assert_eq!(
+            expected,
+            concat!(
+                "        ---         |(?)| a x=1   y=2\n",
+                "        ---         |(?)| b x=100 y=3\n",
+            )
+        );
+    }
+
+    fn process<F>(data: &[u8], filter: &Filter, configure: F) -> String
+    where
+        F: FnOnce(SegmentProcesor) -> SegmentProcesor,
+    {
+        process_with(data, filter, formatter(), configure)
+    }
+
+    fn process_with<F>(
+        data: &[u8],
+        filter: &Filter,
+        mut formatter: RecordFormatter,
+        configure: F,
+    ) -> String
+    where
+        F: FnOnce(SegmentProcesor) -> SegmentProcesor,
+    {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut processor = configure(SegmentProcesor::new(&parser, &mut formatter, filter));
+        let mut buf = Vec::new();
+        processor.run(data, &mut buf);
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_oversized_lines() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let filter = Filter::default();
+        let parts = [
+            ("{\"msg\":\"äää".as_bytes(), PartialPlacement::First),
+            (b"aaaa", PartialPlacement::Next),
+            (b"\"}\n{\"msg\":\"ok\"}\n", PartialPlacement::Last),
+        ];
+        let run = |max_line_len, metrics| {
+            let mut formatter = formatter();
+            let mut processor = SegmentProcesor::new(&parser, &mut formatter, &filter)
+                .with_max_line_len(max_line_len)
+                .with_metrics(metrics);
+            let mut buf = Vec::new();
+            for (data, placement) in parts {
+                processor.run_partial(data, placement, &mut buf);
+            }
+            (
+                String::from_utf8(buf).unwrap(),
+                processor.metrics().cloned(),
+            )
+        };
+        let ok = "        ---         |(?)| ok\n";
+        assert_eq!(
+            run(None, false).0,
+            format!("{{\"msg\":\"äääaaaa\"}}\n{}", ok)
+        );
+        assert_eq!(
+            run(Some(11), false).0,
+            format!("{{\"msg\":\"ä …(truncated)\n{}", ok)
+        );
+        let metrics = run(Some(10), true).1.unwrap();
+        assert_eq!((metrics.invalid_lines, metrics.oversized_lines), (1, 1));
+        assert_eq!(metrics.records, 1);
+    }
+
+    #[test]
+    fn test_unparsed_lines_passthrough() {
+        let data = br#"not a json
+{"ts":"2021-06-20T00:00:00.393Z","msg":"hello"}
+"#;
+        let result = process(data, &Filter::default(), |p| p);
+        assert_eq!(result, "not a json\nJun 20 00:00:00.393 |(?)| hello\n");
+    }
+
+    #[test]
+    fn test_multiple_objects_per_line() {
+        let data = concat!(
+            r#"{"a":1}{"a":2}"#,
+            "\n",
+            r#"{"a":3} {"a":4} "#,
+            "\n",
+            r#"{"a":5}{"a":"#,
+            "\n",
+        );
+        let result = process(data.as_bytes(), &Filter::default(), |p| p);
+        assert_eq!(
+            result,
+            concat!(
+                "        ---         |(?)| a=1\n",
+                "        ---         |(?)| a=2\n",
+                "        ---         |(?)| a=3\n",
+                "        ---         |(?)| a=4\n",
+                "        ---         |(?)| a=5\n",
+                "{\"a\":\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_unparsed_lines_quiet() {
+        let data = br#"not a json
+{"ts":"2021-06-20T00:00:00.393Z","msg":"hello"}
+"#;
+        let result = process(data, &Filter::default(), |p| p.with_quiet(true));
+        assert_eq!(result, "Jun 20 00:00:00.393 |(?)| hello\n");
+    }
+
+    #[test]
+    fn test_missing_final_newline() {
+        let data = b"not a json\n{\"ts\":\"2021-06-20T00:00:00.393Z\",\"msg\":\"hello\"}";
+        let result = process(data, &Filter::default(), |p| p);
+        assert_eq!(result, "not a json\nJun 20 00:00:00.393 |(?)| hello\n");
+    }
+
+    #[test]
+    fn test_mixed_newlines() {
+        let data = b"{\"msg\":\"a\"}\r\n\r\n{\"msg\":\"b\"}\r{\"msg\":\"c\"}\ntext\rmore\n";
+        let result = process(data, &Filter::default(), |p| p);
+        assert_eq!(
+            result,
+            concat!(
+                "        ---         |(?)| a\n",
+                "\n",
+                "        ---         |(?)| b\n",
+                "        ---         |(?)| c\n",
+                "text\rmore\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_json_output() {
+        let data = br#"{"ts":1624147200393,"msg":"hello"}
+  {"time":"2021-06-20T03:00:00.393+03:00","msg":"hello"}
+{"msg":"no time"}
+"#;
+        let result = process(data, &Filter::default(), |p| {
+            p.with_output_format(OutputFormat::Json)
+        });
+        assert_eq!(
+            result,
+            r#"{"ts":1624147200393,"msg":"hello"}
+{"time":"2021-06-20T03:00:00.393+03:00","msg":"hello"}
+{"msg":"no time"}
+"#
+        );
+    }
+
+    #[test]
+    fn test_json_output_round_trip() {
+        let data = concat!(
+            r#"{"ts":"2021-06-20T00:00:00Z","level":"info","msg":"a\"b","Request_ID":"X_1","Nested":{"Inner_Key":[1,{"K_k":null}]}}"#,
+            "\n",
+            r#"{"msg":"second","UPPER_case":true,"with-dash":1.5e3}"#,
+            "\n",
+        );
+        let result = process(data.as_bytes(), &Filter::default(), |p| {
+            p.with_output_format(OutputFormat::Json)
+        });
+        let parse = |text: &str| {
+            text.lines()
+                .map(|line| json::from_str::<json::Value>(line).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(parse(&result), parse(data));
+        assert!(result.contains(r#""Request_ID":"X_1""#));
+    }
+
+    #[test]
+    fn test_json_output_normalized_time() {
+        let data = br#"{"ts":1624147200393,"msg":"hello"}
+{"time":"2021-06-20T03:00:00.393+03:00","msg":"hello"}
+{"time":"yesterday","msg":"hello"}
+"#;
+        let result = process(data, &Filter::default(), |p| {
+            p.with_output_format(OutputFormat::Json)
+                .with_time_normalization(true)
+        });
+        assert_eq!(
+            result,
+            r#"{"ts":"2021-06-20T00:00:00.393000000Z","msg":"hello"}
+{"time":"2021-06-20T00:00:00.393000000Z","msg":"hello"}
+{"time":"yesterday","msg":"hello"}
+"#
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let data = br#"{"ts":"2021-06-20T00:00:00.393Z","msg":"hello"} {"msg":}
+"#;
+        let result = process(data, &Filter::default(), |p| p.with_parse_errors(true));
+        assert_eq!(
+            result,
+            concat!(
+                "Jun 20 00:00:00.393 |(?)| hello\n",
+                r#"{"msg":}"#,
+                "\nparse error: expected value at line 1 column 56\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_decorate_record() {
+        let mut buf = Vec::new();
+        decorate_record(
+            &mut buf,
+            b"\x1b[0mrecord\x1b[0m\nraw\n",
+            b"> ",
+            b" <",
+            b"+0.1s ",
+        );
+        assert_eq!(buf, b"> +0.1s \x1b[0mrecord\x1b[0m <\n> raw <\n");
+    }
+
+    #[test]
+    fn test_reversed_emit() {
+        let mut output = Vec::new();
+        let mut reversed = Some(Vec::new());
+        emit_lines(&mut output, &mut reversed, b"a\nb\n").unwrap();
+        emit(&mut output, &mut reversed, b"record\nraw\n").unwrap();
+        emit_lines(&mut output, &mut reversed, b"c").unwrap();
+        assert!(output.is_empty());
+        let items: Vec<_> = reversed.unwrap().into_iter().rev().collect();
+        assert_eq!(items.concat(), b"crecord\nraw\nb\na\n");
+    }
+
+    #[test]
+    fn test_write_all_retry() {
+        struct Flaky {
+            data: Vec<u8>,
+            calls: usize,
+        }
+        impl Write for Flaky {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.calls += 1;
+                match self.calls % 3 {
+                    1 => Err(std::io::ErrorKind::WouldBlock.into()),
+                    2 => Err(std::io::ErrorKind::Interrupted.into()),
+                    _ => {
+                        let n = std::cmp::min(buf.len(), 2);
+                        self.data.extend_from_slice(&buf[..n]);
+                        Ok(n)
+                    }
+                }
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut output = Flaky {
+            data: Vec::new(),
+            calls: 0,
+        };
+        write_all(&mut output, b"hello").unwrap();
+        assert_eq!(output.data, b"hello");
+    }
+
+    #[test]
+    fn test_field_alignment() {
+        let data = concat!(
+            r#"{"ts":"2021-06-20T00:00:00.393Z","msg":"a","x":1,"y":2}"#,
+            "\n",
+            r#"{"ts":"2021-06-20T00:00:00.393Z","msg":"b","x":1000,"y":2}"#,
+            "\n",
+        );
+        let formatter = formatter().with_field_alignment(true);
+        let result = process_with(data.as_bytes(), &Filter::default(), formatter, |p| p);
+        assert_eq!(
+            result,
+            concat!(
+                "Jun 20 00:00:00.393 |(?)| a x=1    y=2\n",
+                "Jun 20 00:00:00.393 |(?)| b x=1000 y=2\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_check_sorted() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let check = |data: &str| {
+            check_sorted(
+                &parser,
+                LineConverter::new(InputFormat::Json),
+                data.as_bytes(),
+                None,
+            )
+        };
+        assert!(check(concat!(
+            r#"{"ts":"2021-06-20T00:00:00Z"}"#,
+            "\n",
+            r#"{"msg":"no time"}"#,
+            "\n",
+            r#"{"ts":"2021-06-20T03:00:01+03:00"}"#,
+            "\n",
+        ))
+        .is_ok());
+        match check(concat!(
+            r#"{"ts":"2021-06-20T00:00:02Z"}"#,
+            "\n",
+            "garbage\n",
+            r#"{"ts":"2021-06-20T00:00:01Z"}"#,
+            "\n",
+        )) {
+            Err(Error::UnsortedRecords {
+                location,
+                prev,
+                next,
+            }) => {
+                assert_eq!(location, "line 3");
+                assert_eq!(prev, "2021-06-20T00:00:02+00:00");
+                assert_eq!(next, "2021-06-20T00:00:01+00:00");
+            }
+            _ => panic!("expected unsorted records error"),
+        }
+    }
+
+    #[test]
+    fn test_check_sorted_inputs() {
+        let input = |name: &str, data: &'static [u8]| Ok(Input::new(name.into(), Box::new(data)));
+        let inputs = vec![
+            input(
+                "a",
+                b"{\"ts\":\"2021-06-20T00:00:01Z\"}\n{\"ts\":\"2021-06-20T00:00:02Z\"}\n",
+            ),
+            input(
+                "b",
+                b"{\"ts\":\"2021-06-20T00:00:03Z\"}\n{\"ts\":\"2021-06-20T00:00:00Z\"}\n",
+            ),
+        ];
+        let map = Arc::new(InputMap::new(vec!["a".into(), "b".into()]));
+        let mut input = ConcatReader::new(inputs.into_iter()).with_input_map(Some(map.clone()));
+        let app = App::new(options()).with_input_map(map);
+        match app.check_sorted(&mut input) {
+            Err(Error::UnsortedRecords { location, .. }) => assert_eq!(location, "line 2 of b"),
+            _ => panic!("expected unsorted records error"),
+        }
+    }
+
+    #[test]
+    fn test_check_sorted_unwrapped() {
+        let data = concat!(
+            r#"{"log":"{\"ts\":\"2021-06-20T00:00:02Z\"}"}"#,
+            "\n",
+            r#"{"log":"{\"ts\":\"2021-06-20T00:00:01Z\"}"}"#,
+            "\n",
+        );
+        let app = App::new(options());
+        assert!(app.check_sorted(&mut data.as_bytes()).is_ok());
+        let app = App::new(Options {
+            unwrap: Some("log".into()),
+            ..options()
+        });
+        assert!(matches!(
+            app.check_sorted(&mut data.as_bytes()),
+            Err(Error::UnsortedRecords { .. })
+        ));
+    }
+
+    #[test]
+    fn test_time_range() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let range = |data: &str| {
+            time_range(
+                &parser,
+                LineConverter::new(InputFormat::Json),
+                data.as_bytes(),
+            )
+            .unwrap()
+            .map(|(min, max)| (min.to_rfc3339(), max.to_rfc3339()))
+        };
+        assert_eq!(
+            range(concat!(
+                r#"{"ts":"2021-06-20T00:00:02Z"}"#,
+                "\n",
+                r#"{"msg":"no time"}"#,
+                "\n",
+                r#"{"ts":"2021-06-20T00:00:01Z"}{"ts":"2021-06-20T00:00:03Z"}"#,
+                "\n",
+            )),
+            Some((
+                "2021-06-20T00:00:01+00:00".into(),
+                "2021-06-20T00:00:03+00:00".into()
+            ))
+        );
+        assert_eq!(range("{\"msg\":\"no time\"}\n"), None);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let data = concat!(
+            r#"{"level":"error","msg":"a"}"#,
+            "\n\nnot a json\n",
+            r#"{"level":"info","msg":"b"} {"msg":"c"}"#,
+            "\n",
+        );
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut formatter = formatter();
+        let filter = Filter::default();
+        let mut processor =
+            SegmentProcesor::new(&parser, &mut formatter, &filter).with_metrics(true);
+        let mut buf = Vec::new();
+        processor.run(data.as_bytes(), &mut buf);
+        assert!(buf.is_empty());
+        let metrics = processor.metrics().unwrap();
+        assert_eq!(metrics.records, 3);
+        assert_eq!(metrics.records_by_level[Level::Error], 1);
+        assert_eq!(metrics.records_by_level[Level::Info], 1);
+        assert_eq!(metrics.invalid_lines, 1);
+    }
+
+    #[test]
+    fn test_crlf_only_lines() {
+        let data = concat!(r#"{"msg":"a"}"#, "\r\n\r\n\r\n", r#"{"msg":"b"}"#, "\r\n");
+        let result = process(data.as_bytes(), &Filter::default(), |p| p);
+        assert_eq!(
+            result,
+            "        ---         |(?)| a\n\n\n        ---         |(?)| b\n"
+        );
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut formatter = formatter();
+        let filter = Filter::default();
+        let mut processor =
+            SegmentProcesor::new(&parser, &mut formatter, &filter).with_metrics(true);
+        let mut buf = Vec::new();
+        processor.run(data.as_bytes(), &mut buf);
+        assert!(buf.is_empty());
+        let metrics = processor.metrics().unwrap();
+        assert_eq!(metrics.records, 2);
+        assert_eq!(metrics.invalid_lines, 0);
+    }
+
+    #[test]
+    fn test_raw_record_output() {
+        let data = b"{\"msg\":\"a\"} {\"msg\":\"b\"}\r\n";
+        let result = process(data, &Filter::default(), |p| p.with_raw_record_output(true));
+        assert_eq!(
+            result,
+            concat!(
+                "        ---         |(?)| a\n",
+                "{\"msg\":\"a\"}\n",
+                "        ---         |(?)| b\n",
+                "{\"msg\":\"b\"}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_explanation() {
+        let mut buf = Vec::new();
+        let results = [
+            ("level<=info".to_string(), true),
+            ("a=1".to_string(), false),
+        ];
+        format_explanation(&mut buf, br#"{"a":2}"#, &results);
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "dropped: {\"a\":2}\n  pass: level<=info\n  fail: a=1\n"
+        );
+    }
+
+    #[test]
+    fn test_byte_offsets() {
+        let data = concat!(
+            r#"{"msg":"a"}"#,
+            "\r\nnot a json\n\n",
+            r#"  {"msg":"b"} {"msg":"c"}"#,
+            "\n",
+        );
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut formatter = formatter();
+        let filter = Filter::default();
+        let mut processor =
+            SegmentProcesor::new(&parser, &mut formatter, &filter).with_byte_offsets(true);
+        let mut buf = Vec::new();
+        processor.run_at(data.as_bytes(), &mut buf, 100);
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            concat!(
+                "100: ",
+                "        ---         |(?)| a\n",
+                "not a json\n",
+                "\n",
+                "127:         ---         |(?)| b\n",
+                "139:         ---         |(?)| c\n",
+            )
+        );
+        let parts = [
+            (&b"{\"msg\":\"aa"[..], PartialPlacement::First, 200),
+            (b"aaaa", PartialPlacement::Next, 210),
+            (b"\"}\n{\"msg\":\"ok\"}\n", PartialPlacement::Last, 214),
+        ];
+        let mut processor = SegmentProcesor::new(&parser, &mut formatter, &filter)
+            .with_byte_offsets(true)
+            .with_max_line_len(Some(7));
+        let mut buf = Vec::new();
+        for (data, placement, offset) in parts {
+            processor.run_partial_at(data, placement, &mut buf, offset);
+        }
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            "200: {\"msg\": …(truncated)\n217:         ---         |(?)| ok\n",
+        );
+    }
+
+    #[test]
+    fn test_byte_offsets_of_inputs() {
+        let input = |name: &str, data: &'static [u8]| Ok(Input::new(name.into(), Box::new(data)));
+        let data = b"{\"msg\":\"a\"}\n";
+        let inputs = vec![input("a", data), input("b", data)];
+        let map = Arc::new(InputMap::new(vec!["a".into(), "b".into()]));
+        let mut input = ConcatReader::new(inputs.into_iter()).with_input_map(Some(map.clone()));
+        let mut data = Vec::new();
+        input.read_to_end(&mut data).unwrap();
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut formatter = formatter();
+        let filter = Filter::default();
+        let mut processor = SegmentProcesor::new(&parser, &mut formatter, &filter)
+            .with_byte_offsets(true)
+            .with_input_map(Some(&map));
+        let mut buf = Vec::new();
+        processor.run_at(&data[..], &mut buf, 0);
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            "a:0:         ---         |(?)| a\nb:0:         ---         |(?)| a\n",
+        );
+    }
+
+    #[test]
+    fn test_record_marks() {
+        let data = concat!(
+            r#"{"ts":"2021-06-20T00:00:00.393Z","msg":"a"}"#,
+            "\nnot a json\n",
+            r#"{"ts":"2021-06-20T00:00:00.393Z","msg":"b"}"#,
+            "\n",
+        );
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut formatter = formatter();
+        let filter = Filter::default();
+        let mut processor =
+            SegmentProcesor::new(&parser, &mut formatter, &filter).with_record_marking(true);
+        let mut buf = Vec::new();
+        processor.run(data.as_bytes(), &mut buf);
+        let marks = processor.take_record_marks();
+        let records: Vec<_> = marks
+            .into_iter()
+            .map(|m| std::str::from_utf8(&buf[m.range]).unwrap())
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                "Jun 20 00:00:00.393 |(?)| a\n",
+                "Jun 20 00:00:00.393 |(?)| b\n"
+            ]
+        );
+        assert!(processor.take_record_marks().is_empty());
+    }
+
+    #[test]
+    fn test_record_timestamps() {
+        let data = concat!(
+            r#"{"ts":"2021-06-20T00:00:00.393Z","msg":"a"}"#,
+            "\n",
+            r#"{"msg":"b"}"#,
+            "\n",
+        );
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut formatter = formatter();
+        let filter = Filter::default();
+        let mut processor =
+            SegmentProcesor::new(&parser, &mut formatter, &filter).with_record_timestamps(true);
+        let mut buf = Vec::new();
+        processor.run(data.as_bytes(), &mut buf);
+        let timestamps: Vec<_> = processor
+            .take_record_marks()
+            .into_iter()
+            .map(|m| m.ts.map(|ts| ts.timestamp_millis()))
+            .collect();
+        assert_eq!(timestamps, vec![Some(1624147200393), None]);
+    }
+
+    #[test]
+    fn test_record_levels() {
+        let data = concat!(
+            r#"{"level":"error","msg":"a"}"#,
+            "\n",
+            r#"{"msg":"b"}"#,
+            "\n",
+        );
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut formatter = formatter();
+        let filter = Filter::default();
+        let mut processor =
+            SegmentProcesor::new(&parser, &mut formatter, &filter).with_record_marking(true);
+        let mut buf = Vec::new();
+        processor.run(data.as_bytes(), &mut buf);
+        let levels: Vec<_> = processor
+            .take_record_marks()
+            .into_iter()
+            .map(|m| m.level)
+            .collect();
+        assert_eq!(levels, vec![Some(Level::Error), None]);
+    }
+
+    #[test]
+    fn test_split_keys() {
+        let data = concat!(
+            r#"{"msg":"a","tenant":"acme"}"#,
+            "\n",
+            r#"{"msg":"b","tenant":42}"#,
+            "\n",
+            r#"{"msg":"c"}"#,
+            "\n",
+        );
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut formatter = formatter();
+        let filter = Filter::default();
+        let mut processor =
+            SegmentProcesor::new(&parser, &mut formatter, &filter).with_split_field(Some("tenant"));
+        let mut buf = Vec::new();
+        processor.run(data.as_bytes(), &mut buf);
+        let keys: Vec<_> = processor
+            .take_record_marks()
+            .into_iter()
+            .map(|m| m.split_key)
+            .collect();
+        assert_eq!(keys, vec![Some("acme".into()), Some("42".into()), None]);
+    }
+}
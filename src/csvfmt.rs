@@ -0,0 +1,163 @@
+// third-party imports
+use serde_json::{self as json, value::RawValue};
+
+// local imports
+use crate::model::{Object, Record};
+
+// ---
+
+/// Formats records as CSV rows according to RFC 4180, one column per selected field.
+///
+/// Columns `ts`/`time`, `level`, `msg`/`message`, `logger` and `caller` refer to the predefined fields,
+/// other columns refer to regular fields with nested fields addressed by dotted paths.
+/// Missing fields and null values become empty cells.
+pub struct CsvFormatter {
+    columns: Vec<String>,
+}
+
+impl CsvFormatter {
+    pub fn new(columns: Vec<String>) -> Self {
+        Self { columns }
+    }
+
+    /// Formats the header row containing column names.
+    pub fn format_header(&self, buf: &mut Vec<u8>) {
+        for (i, column) in self.columns.iter().enumerate() {
+            if i != 0 {
+                buf.push(b',');
+            }
+            push_cell(buf, column.as_bytes());
+        }
+        buf.extend_from_slice(LINE_END);
+    }
+
+    /// Formats a row with values of the selected fields of the record.
+    pub fn format_record(&self, buf: &mut Vec<u8>, rec: &Record) {
+        for (i, column) in self.columns.iter().enumerate() {
+            if i != 0 {
+                buf.push(b',');
+            }
+            match &column[..] {
+                "ts" | "time" => {
+                    if let Some(ts) = &rec.ts {
+                        push_cell(buf, ts.raw().as_bytes());
+                    }
+                }
+                "level" => {
+                    if let Some(level) = rec.level {
+                        buf.extend_from_slice(level.as_str().as_bytes());
+                    }
+                }
+                "msg" | "message" => {
+                    if let Some(message) = rec.message {
+                        push_value(buf, message);
+                    }
+                }
+                "logger" => {
+                    if let Some(logger) = rec.logger {
+                        push_cell(buf, logger.as_bytes());
+                    }
+                }
+                "caller" => {
+                    if let Some(caller) = rec.caller {
+                        push_cell(buf, caller.as_bytes());
+                    }
+                }
+                path => {
+                    if let Some(value) = lookup(&mut rec.fields().map(|(k, v)| (*k, *v)), path) {
+                        push_value(buf, value);
+                    }
+                }
+            }
+        }
+        buf.extend_from_slice(LINE_END);
+    }
+}
+
+// ---
+
+fn lookup<'a>(
+    fields: &mut dyn Iterator<Item = (&'a str, &'a RawValue)>,
+    path: &str,
+) -> Option<&'a RawValue> {
+    for (key, value) in fields {
+        if key == path {
+            return Some(value);
+        }
+        if let Some(rest) = path.strip_prefix(key).and_then(|x| x.strip_prefix('.')) {
+            if value.get().starts_with('{') {
+                let object = json::from_str::<Object>(value.get()).ok()?;
+                if let Some(value) = lookup(&mut object.fields.iter().map(|(k, v)| (*k, *v)), rest)
+                {
+                    return Some(value);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn push_value(buf: &mut Vec<u8>, value: &RawValue) {
+    let text = value.get();
+    match text.as_bytes()[0] {
+        b'"' => match json::from_str::<String>(text) {
+            Ok(text) => push_cell(buf, text.as_bytes()),
+            Err(_) => push_cell(buf, text.as_bytes()),
+        },
+        b'n' => {}
+        _ => push_cell(buf, text.as_bytes()),
+    }
+}
+
+fn push_cell(buf: &mut Vec<u8>, text: &[u8]) {
+    if !text
+        .iter()
+        .any(|c| matches!(c, b',' | b'"' | b'\r' | b'\n'))
+    {
+        buf.extend_from_slice(text);
+        return;
+    }
+    buf.push(b'"');
+    for &c in text {
+        if c == b'"' {
+            buf.push(b'"');
+        }
+        buf.push(c);
+    }
+    buf.push(b'"');
+}
+
+// ---
+
+const LINE_END: &[u8] = b"\r\n";
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::{Parser, ParserSettings, RawRecord};
+    use crate::settings::Settings;
+
+    #[test]
+    fn test_format() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","level":"info","msg":"say \"hi\", bye","req":{"ms":12},"n":null}"#;
+        let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+        let formatter = CsvFormatter::new(
+            ["ts", "level", "msg", "req.ms", "n", "missing"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+        );
+        let mut buf = Vec::new();
+        formatter.format_header(&mut buf);
+        formatter.format_record(&mut buf, &record);
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            "ts,level,msg,req.ms,n,missing\r\n2021-06-20T00:00:00Z,info,\"say \"\"hi\"\", bye\",12,,\r\n"
+        );
+    }
+}
@@ -27,6 +27,14 @@ impl DateTimeFormatter {
         format_date(buf, dt.with_timezone(&self.tz), &self.format)
     }
 
+    /// Formats the timestamp in the given time zone instead of the configured one.
+    pub fn format_in<B>(&self, buf: &mut B, dt: DateTime<FixedOffset>, tz: FixedOffset)
+    where
+        B: Push<u8>,
+    {
+        format_date(buf, dt.with_timezone(&tz), &self.format)
+    }
+
     pub fn reformat_rfc3339<'a, B>(&self, buf: &mut B, ts: rfc3339::Timestamp<'a>) -> Option<()>
     where
         B: Push<u8>,
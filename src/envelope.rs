@@ -0,0 +1,140 @@
+// third-party imports
+use serde_json as json;
+
+// local imports
+use crate::model::RawRecord;
+
+// ---
+
+/// Unwraps JSON records embedded as a string field of an outer JSON record,
+/// like `{"log":"{\"level\":\"info\"}\n","stream":"stdout","time":"..."}` produced by Docker and Kubernetes.
+pub struct Envelope {
+    field: String,
+    time_fields: Vec<String>,
+}
+
+impl Envelope {
+    pub fn new(field: String) -> Self {
+        Self {
+            field,
+            time_fields: Vec::new(),
+        }
+    }
+
+    /// Sets names of time fields, the outer time is added to the inner record if it has none of them.
+    pub fn with_time_fields(mut self, names: Vec<String>) -> Self {
+        self.time_fields = names;
+        self
+    }
+
+    /// Writes the unwrapped record to the buffer, returns false if the line is not a JSON object
+    /// having the envelope field with a string value.
+    /// If the inner string is not a JSON object, the outer record is written with the string in place of `msg`.
+    pub fn unwrap(&self, line: &[u8], buf: &mut Vec<u8>) -> bool {
+        let outer = match json::from_slice::<RawRecord>(line) {
+            Ok(outer) => outer,
+            Err(_) => return false,
+        };
+        let inner = match outer.fields().find(|(k, _)| *k == self.field) {
+            Some((_, v)) if v.get().starts_with('"') => match json::from_str::<String>(v.get()) {
+                Ok(inner) => inner,
+                Err(_) => return false,
+            },
+            _ => return false,
+        };
+        let inner = inner.trim();
+        let time = outer
+            .fields()
+            .find(|(k, _)| *k != self.field && self.time_fields.iter().any(|name| name == k));
+
+        if let Some(rest) = inner.strip_prefix('{') {
+            if let Ok(record) = json::from_str::<RawRecord>(inner) {
+                let has_time = record
+                    .fields()
+                    .any(|(k, _)| self.time_fields.iter().any(|name| name == k));
+                match time {
+                    Some((key, value)) if !has_time => {
+                        buf.push(b'{');
+                        push_field(buf, key, value.get());
+                        if record.fields().next().is_some() {
+                            buf.push(b',');
+                        }
+                        buf.extend_from_slice(rest.as_bytes());
+                    }
+                    _ => buf.extend_from_slice(inner.as_bytes()),
+                }
+                return true;
+            }
+        }
+
+        buf.push(b'{');
+        for (i, (key, value)) in outer.fields().enumerate() {
+            if i != 0 {
+                buf.push(b',');
+            }
+            if *key == self.field {
+                push_field(buf, "msg", &json::to_string(inner).unwrap());
+            } else {
+                push_field(buf, key, value.get());
+            }
+        }
+        buf.push(b'}');
+        true
+    }
+}
+
+// ---
+
+fn push_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    json::to_writer(&mut *buf, key).unwrap();
+    buf.push(b':');
+    buf.extend_from_slice(value.as_bytes());
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unwrap(line: &str) -> Option<String> {
+        let envelope =
+            Envelope::new("log".into()).with_time_fields(vec!["ts".into(), "time".into()]);
+        let mut buf = Vec::new();
+        if envelope.unwrap(line.as_bytes(), &mut buf) {
+            Some(String::from_utf8(buf).unwrap())
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_unwrap() {
+        assert_eq!(
+            unwrap(
+                r#"{"log":"{\"ts\":\"2021-06-20T00:00:00Z\",\"msg\":\"hi\"}\n","time":"2021-06-20T00:00:01Z"}"#
+            ),
+            Some(r#"{"ts":"2021-06-20T00:00:00Z","msg":"hi"}"#.into())
+        );
+        assert_eq!(
+            unwrap(
+                r#"{"log":"{\"msg\":\"hi\"}\n","stream":"stdout","time":"2021-06-20T00:00:01Z"}"#
+            ),
+            Some(r#"{"time":"2021-06-20T00:00:01Z","msg":"hi"}"#.into())
+        );
+        assert_eq!(
+            unwrap(r#"{"log":"{}","time":"2021-06-20T00:00:01Z"}"#),
+            Some(r#"{"time":"2021-06-20T00:00:01Z"}"#.into())
+        );
+        assert_eq!(
+            unwrap(r#"{"log":"plain \"text\"\n","stream":"stderr","time":"2021-06-20T00:00:01Z"}"#),
+            Some(
+                r#"{"msg":"plain \"text\"","stream":"stderr","time":"2021-06-20T00:00:01Z"}"#
+                    .into()
+            )
+        );
+        assert_eq!(unwrap(r#"{"log":1,"time":"2021-06-20T00:00:01Z"}"#), None);
+        assert_eq!(unwrap(r#"{"msg":"hi"}"#), None);
+        assert_eq!(unwrap("not json"), None);
+    }
+}
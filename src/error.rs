@@ -45,12 +45,40 @@ pub enum Error {
     UnknownTheme { name: String, known: Vec<String> },
     #[error("zero size")]
     ZeroSize,
+    #[error("zero rate")]
+    ZeroRate,
     #[error("failed to parse utf-8 string: {0}")]
     Utf8Error(#[from] std::str::Utf8Error),
     #[error("failed to parse yaml: {0}")]
     YamlError(#[from] serde_yaml::Error),
     #[error("wrong field filter format: {0}")]
     WrongFieldFilter(String),
+    #[error("invalid sample rate {0:?}, use <keep>/<every> or a fraction between 0 and 1")]
+    InvalidSampleRate(String),
+    #[error("invalid path delimiter {0:?}, use a single ASCII character")]
+    InvalidPathDelimiter(String),
+    #[error("record at {location} is out of order, {next} follows {prev}")]
+    UnsortedRecords {
+        location: String,
+        prev: String,
+        next: String,
+    },
+    #[error("failed to parse {0} environment variable: {1}")]
+    InvalidEnvironmentVariable(String, String),
+    #[error("stdin cannot be used both for the list of files and as an input file")]
+    StdinUsedTwice,
+    #[error("times relative to the time range of the input can only be used with input files")]
+    RelativeTimeRequiresFiles,
+    #[error("times relative to the time range of the input cannot be resolved as the input has no timestamps")]
+    RelativeTimeWithoutTimestamps,
+    #[error("wrong time range format: {0:?}, use <start>..<end>")]
+    WrongTimeRange(String),
+    #[error("wrong level mapping format: {0:?}, use <value>=<level>")]
+    WrongLevelMapping(String),
+    #[error("anonymization is supported with human readable output format only")]
+    AnonymizationNotSupported,
+    #[error("output format {0:?} is not supported by this build")]
+    UnsupportedOutputFormat(String),
     #[error("wrong regular expression: {0}")]
     WrongRegularExpression(#[from] regex::Error),
 }
@@ -0,0 +1,204 @@
+// std imports
+use std::collections::HashMap;
+use std::io::{Result, Write};
+
+// third-party imports
+use enum_map::{Enum, EnumMap};
+use serde_json::{self as json, value::RawValue};
+
+// local imports
+use crate::model::RawRecord;
+
+// ---
+
+/// Kind of a JSON value observed in a field.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Enum)]
+pub enum ValueKind {
+    String,
+    Number,
+    Bool,
+    Null,
+    Object,
+    Array,
+}
+
+impl ValueKind {
+    /// Returns kind of the given raw JSON value.
+    pub fn of(value: &RawValue) -> Self {
+        match value.get().as_bytes().first() {
+            Some(b'"') => Self::String,
+            Some(b'{') => Self::Object,
+            Some(b'[') => Self::Array,
+            Some(b't') | Some(b'f') => Self::Bool,
+            Some(b'n') => Self::Null,
+            _ => Self::Number,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Number => "number",
+            Self::Bool => "bool",
+            Self::Null => "null",
+            Self::Object => "object",
+            Self::Array => "array",
+        }
+    }
+}
+
+// ---
+
+/// Statistics of a single field.
+#[derive(Clone, Debug, Default)]
+pub struct FieldStat {
+    pub count: u64,
+    pub kinds: EnumMap<ValueKind, u64>,
+}
+
+/// Statistics of field keys collected over a sample of records.
+/// Keys of nested objects are joined with a dot.
+#[derive(Clone, Debug, Default)]
+pub struct FieldStats {
+    records: u64,
+    fields: HashMap<String, FieldStat>,
+}
+
+impl FieldStats {
+    /// Accounts all fields of the given record.
+    pub fn add_record(&mut self, record: &RawRecord) {
+        self.records += 1;
+        self.add_fields("", record);
+    }
+
+    /// Returns the number of accounted records.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Returns field statistics sorted by frequency in descending order and then by key.
+    pub fn sorted(&self) -> Vec<(&str, &FieldStat)> {
+        let mut result: Vec<_> = self.fields.iter().map(|(k, v)| (k.as_str(), v)).collect();
+        result.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+        result
+    }
+
+    /// Writes statistics as a table with key, observed types, count and frequency columns.
+    pub fn write_table<W: Write + ?Sized>(&self, out: &mut W) -> Result<()> {
+        let fields = self.sorted();
+        let width = fields
+            .iter()
+            .map(|(k, _)| k.len())
+            .max()
+            .unwrap_or(0)
+            .max(3);
+        writeln!(
+            out,
+            "{:width$}  {:24}  {:>10}  {:>6}",
+            "KEY",
+            "TYPES",
+            "COUNT",
+            "FREQ",
+            width = width
+        )?;
+        for (key, stat) in fields {
+            writeln!(
+                out,
+                "{:width$}  {:24}  {:>10}  {:>5.1}%",
+                key,
+                kinds(stat).join(","),
+                stat.count,
+                self.frequency(stat) * 100.0,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes statistics as JSON lines, one object per field.
+    pub fn write_json<W: Write + ?Sized>(&self, out: &mut W) -> Result<()> {
+        for (key, stat) in self.sorted() {
+            writeln!(
+                out,
+                r#"{{"key":{},"types":{},"count":{},"frequency":{}}}"#,
+                json::to_string(key)?,
+                json::to_string(&kinds(stat))?,
+                stat.count,
+                self.frequency(stat),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn add_fields(&mut self, prefix: &str, record: &RawRecord) {
+        for (key, value) in record.fields() {
+            let key = if prefix.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            let kind = ValueKind::of(value);
+            let stat = self.fields.entry(key.clone()).or_default();
+            stat.count += 1;
+            stat.kinds[kind] += 1;
+            if kind == ValueKind::Object {
+                if let Ok(nested) = json::from_str::<RawRecord>(value.get()) {
+                    self.add_fields(&key, &nested);
+                }
+            }
+        }
+    }
+
+    fn frequency(&self, stat: &FieldStat) -> f64 {
+        if self.records == 0 {
+            0.0
+        } else {
+            stat.count as f64 / self.records as f64
+        }
+    }
+}
+
+// ---
+
+fn kinds(stat: &FieldStat) -> Vec<&'static str> {
+    stat.kinds
+        .iter()
+        .filter(|(_, &n)| n != 0)
+        .map(|(kind, _)| kind.name())
+        .collect()
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_stats() {
+        let mut stats = FieldStats::default();
+        for data in [
+            r#"{"msg":"a","user":{"id":1,"name":"x"}}"#,
+            r#"{"msg":"b","user":{"id":"2"},"tags":[]}"#,
+        ] {
+            stats.add_record(&json::from_str(data).unwrap());
+        }
+        let mut buf = Vec::new();
+        stats.write_json(&mut buf).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            concat!(
+                r#"{"key":"msg","types":["string"],"count":2,"frequency":1}"#,
+                "\n",
+                r#"{"key":"user","types":["object"],"count":2,"frequency":1}"#,
+                "\n",
+                r#"{"key":"user.id","types":["string","number"],"count":2,"frequency":1}"#,
+                "\n",
+                r#"{"key":"tags","types":["array"],"count":1,"frequency":0.5}"#,
+                "\n",
+                r#"{"key":"user.name","types":["string"],"count":1,"frequency":0.5}"#,
+                "\n",
+            )
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use std::cmp::min;
 
+use unicode_width::UnicodeWidthChar;
+
 // ---
 
 pub trait Push<T> {
@@ -343,6 +345,101 @@ where
 
 // ---
 
+/// Left aligner for UTF-8 text measuring its width in terminal display columns instead of bytes.
+pub struct DisplayWidthAligner<'a, O>
+where
+    O: Push<u8>,
+{
+    out: &'a mut O,
+    padding: Padding<u8>,
+    cur: usize,
+    pending: [u8; 4],
+    pending_len: usize,
+    truncated: bool,
+}
+
+impl<'a, O> DisplayWidthAligner<'a, O>
+where
+    O: Push<u8>,
+{
+    pub fn new(out: &'a mut O, padding: Padding<u8>) -> Self {
+        Self {
+            out,
+            padding,
+            cur: 0,
+            pending: [0; 4],
+            pending_len: 0,
+            truncated: false,
+        }
+    }
+
+    pub fn push(&mut self, value: u8) {
+        if value < 0x80 && self.pending_len == 0 {
+            if self.cur < self.padding.width && !self.truncated {
+                self.out.push(value);
+                self.cur += 1;
+            }
+            return;
+        }
+        self.pending[self.pending_len] = value;
+        self.pending_len += 1;
+        let expected = match self.pending[0] {
+            0xf0..=0xff => 4,
+            0xe0..=0xef => 3,
+            0xc0..=0xdf => 2,
+            _ => 1,
+        };
+        if self.pending_len < expected {
+            return;
+        }
+        let pending = &self.pending[..self.pending_len];
+        self.pending_len = 0;
+        let width = std::str::from_utf8(pending)
+            .ok()
+            .and_then(|s| s.chars().next())
+            .map(|c| c.width().unwrap_or(0))
+            .unwrap_or(1);
+        if self.cur + width <= self.padding.width && !self.truncated {
+            self.out.extend_from_slice(pending);
+            self.cur += width;
+        } else {
+            self.truncated = true;
+        }
+    }
+
+    pub fn extend_from_slice(&mut self, values: &[u8]) {
+        for &value in values {
+            self.push(value);
+        }
+    }
+}
+
+impl<'a, O> Push<u8> for DisplayWidthAligner<'a, O>
+where
+    O: Push<u8>,
+{
+    fn push(&mut self, value: u8) {
+        DisplayWidthAligner::push(self, value)
+    }
+
+    fn extend_from_slice(&mut self, values: &[u8]) {
+        DisplayWidthAligner::extend_from_slice(self, values)
+    }
+}
+
+impl<'a, O> Drop for DisplayWidthAligner<'a, O>
+where
+    O: Push<u8>,
+{
+    fn drop(&mut self) {
+        for _ in self.cur..self.padding.width {
+            self.out.push(self.padding.pad);
+        }
+    }
+}
+
+// ---
+
 enum AlignerBuffer<T> {
     Static(heapless::Vec<T, 64>),
     Dynamic(Vec<T>),
@@ -380,3 +477,16 @@ where
         Alignment::Center,
     ));
 }
+
+pub fn aligned_left_by_display_width<'a, O, F>(out: &'a mut O, width: usize, pad: u8, f: F)
+where
+    O: Push<u8>,
+    F: FnOnce(DisplayWidthAligner<'a, O>),
+{
+    f(DisplayWidthAligner::new(out, Padding::new(pad, width)));
+}
+
+/// Returns width of the given UTF-8 text in terminal display columns.
+pub fn display_width(text: &[u8]) -> usize {
+    unicode_width::UnicodeWidthStr::width(String::from_utf8_lossy(text).as_ref())
+}
@@ -1,37 +1,100 @@
 // std imports
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 // third-party imports
 use chrono::prelude::*;
+use chrono_tz::Tz;
+use itertools::Either;
 use json::{de::Read, de::StrRead, value::RawValue};
 use serde_json as json;
+use wildmatch::WildMatch;
 
 // local imports
+use crate::anonymize::Anonymizer;
 use crate::datefmt;
+use crate::eseq::Sequence;
 use crate::filtering::IncludeExcludeSetting;
 use crate::fmtx;
 use crate::model;
+use crate::settings::EmptyValues;
 use crate::theme;
+use crate::timestamp::Timestamp;
 use crate::IncludeExcludeKeyFilter;
 
 use datefmt::DateTimeFormatter;
-use fmtx::{aligned_left, centered, Counter};
+use fmtx::{aligned_left_by_display_width, centered, display_width, Push};
 use model::Level;
-use theme::{Element, StylingPush, Theme};
+use theme::{Element, StylingPush, StylingTransact, Theme};
 
 // ---
 
 type Buf = Vec<u8>;
 
+/// Keys and raw values of record fields.
+pub type FieldSet = Vec<(Vec<u8>, Vec<u8>)>;
+
+// ---
+
+/// Order of the time and level columns.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Layout {
+    #[default]
+    TimeFirst,
+    LevelFirst,
+}
+
+impl std::str::FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "time-first" => Ok(Self::TimeFirst),
+            "level-first" => Ok(Self::LevelFirst),
+            _ => Err(format!("unknown layout {:?}", s)),
+        }
+    }
+}
+
 // ---
 
 pub struct RecordFormatter {
     theme: Arc<Theme>,
     unescape_fields: bool,
+    raw_fields: HashSet<Vec<u8>>,
+    nested_fields: Vec<WildMatch>,
+    anonymized_fields: HashSet<Vec<u8>>,
+    anonymizer: Option<Arc<Anonymizer>>,
+    key_prefixes: Vec<String>,
+    path_delimiter: u8,
     ts_formatter: DateTimeFormatter,
     ts_width: usize,
+    secondary_ts_formatter: Option<(DateTimeFormatter, usize)>,
     hide_empty_fields: bool,
+    empty_values: EmptyValues,
     fields: Arc<IncludeExcludeKeyFilter>,
+    flatten_objects: bool,
+    flatten_arrays: bool,
+    align_fields: bool,
+    sort_fields: bool,
+    ellipsis: Option<String>,
+    show_hidden_count: bool,
+    max_value_len: Option<usize>,
+    ascii: bool,
+    highlight_levels: bool,
+    highlight_key_values: bool,
+    message_only: bool,
+    skip_missing_messages: bool,
+    null_text: Option<String>,
+    max_fields: Option<usize>,
+    trailing_reset: bool,
+    status_fields: Vec<String>,
+    status_width: usize,
+    field_widths: RefCell<HashMap<Vec<u8>, usize>>,
+    folded_fields: Option<FieldSet>,
+    layout: Layout,
+    time_zone_field: Option<String>,
 }
 
 impl RecordFormatter {
@@ -41,74 +104,301 @@ impl RecordFormatter {
         hide_empty_fields: bool,
         fields: Arc<IncludeExcludeKeyFilter>,
     ) -> Self {
-        let mut counter = Counter::new();
-        let tts = Utc.ymd(2020, 12, 30).and_hms_nano(23, 59, 49, 999_999_999);
-        ts_formatter.format(&mut counter, tts.into());
-        let ts_width = counter.result();
+        let ts_width = timestamp_width(&ts_formatter);
         RecordFormatter {
             theme,
             unescape_fields: true,
+            raw_fields: HashSet::new(),
+            nested_fields: Vec::new(),
+            anonymized_fields: HashSet::new(),
+            anonymizer: None,
+            key_prefixes: Vec::new(),
+            path_delimiter: b'.',
             ts_formatter,
             ts_width,
+            secondary_ts_formatter: None,
             hide_empty_fields,
+            empty_values: EmptyValues::default(),
             fields,
+            flatten_objects: false,
+            flatten_arrays: false,
+            align_fields: false,
+            sort_fields: false,
+            ellipsis: Some("...".into()),
+            show_hidden_count: false,
+            max_value_len: None,
+            ascii: false,
+            highlight_levels: false,
+            highlight_key_values: false,
+            message_only: false,
+            skip_missing_messages: false,
+            null_text: None,
+            max_fields: None,
+            trailing_reset: true,
+            status_fields: Vec::new(),
+            status_width: 0,
+            field_widths: RefCell::new(HashMap::new()),
+            folded_fields: None,
+            layout: Layout::default(),
+            time_zone_field: None,
         }
     }
 
+    /// Enables rendering of a second, parenthesized timestamp after the primary one using the given formatter.
+    pub fn with_secondary_time_formatter(mut self, formatter: Option<DateTimeFormatter>) -> Self {
+        self.secondary_ts_formatter = formatter.map(|f| {
+            let width = timestamp_width(&f);
+            (f, width)
+        });
+        self
+    }
+
     pub fn with_field_unescaping(mut self, value: bool) -> Self {
         self.unescape_fields = value;
         self
     }
 
+    /// Sets prefixes removed from rendered field keys.
+    pub fn with_key_prefix_stripping(mut self, prefixes: Vec<String>) -> Self {
+        self.key_prefixes = prefixes;
+        self
+    }
+
+    /// Sets delimiter joining keys of nested fields in flattened keys and raw field paths, `.` by default.
+    pub fn with_path_delimiter(mut self, delimiter: u8) -> Self {
+        self.path_delimiter = delimiter;
+        self
+    }
+
+    /// Sets field values considered empty and hidden if hiding of empty fields is enabled.
+    pub fn with_empty_values(mut self, values: EmptyValues) -> Self {
+        self.empty_values = values;
+        self
+    }
+
+    /// Disables unescaping for fields with the given keys, nested keys are matched by dotted paths, i.e. `a.b`.
+    pub fn with_raw_fields<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, keys: I) -> Self {
+        self.raw_fields = keys
+            .into_iter()
+            .map(|key| {
+                let mut path = Vec::new();
+                push_key(&mut path, key.as_ref());
+                path
+            })
+            .collect();
+        self
+    }
+
+    /// Enables rendering of string values containing JSON objects or arrays as structured values
+    /// for fields with the given keys, nested keys are matched by dotted paths and may contain wildcards, i.e. `*.payload`.
+    pub fn with_nested_fields<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, keys: I) -> Self {
+        self.nested_fields = keys
+            .into_iter()
+            .map(|key| {
+                let mut path = Vec::new();
+                push_key(&mut path, key.as_ref());
+                WildMatch::new(&String::from_utf8_lossy(&path))
+            })
+            .collect();
+        self
+    }
+
+    /// Replaces values of fields with the given keys with tokens produced by the anonymizer,
+    /// nested keys are matched by dotted paths, i.e. `user.id`.
+    pub fn with_anonymized_fields<I: IntoIterator<Item = S>, S: AsRef<str>>(
+        mut self,
+        keys: I,
+        anonymizer: Arc<Anonymizer>,
+    ) -> Self {
+        self.anonymized_fields = keys
+            .into_iter()
+            .map(|key| {
+                let mut path = Vec::new();
+                push_key(&mut path, key.as_ref());
+                path
+            })
+            .collect();
+        self.anonymizer = Some(anonymizer);
+        self
+    }
+
+    /// Enables rendering of nested object fields as separate fields with dotted keys, i.e. `a.b=1`.
+    pub fn with_object_flattening(mut self, value: bool) -> Self {
+        self.flatten_objects = value;
+        self
+    }
+
+    /// Enables rendering of array items as separate fields with indexed keys, i.e. `a.0=1`.
+    pub fn with_array_flattening(mut self, value: bool) -> Self {
+        self.flatten_arrays = value;
+        self
+    }
+
+    /// Enables padding of top-level field values to the maximum width seen for the same key
+    /// since the last call to [`reset_field_widths`](Self::reset_field_widths).
+    pub fn with_field_alignment(mut self, value: bool) -> Self {
+        self.align_fields = value;
+        self
+    }
+
+    /// Enables rendering of fields sorted by key instead of in their original order.
+    pub fn with_field_sorting(mut self, value: bool) -> Self {
+        self.sort_fields = value;
+        self
+    }
+
+    /// Sets the text appended to a record or an object having some fields hidden, `None` disables it.
+    pub fn with_ellipsis(mut self, value: Option<String>) -> Self {
+        self.ellipsis = value;
+        self
+    }
+
+    /// Shows the number of hidden fields as `(+N hidden)` in place of the ellipsis.
+    pub fn with_hidden_count(mut self, value: bool) -> Self {
+        self.show_hidden_count = value;
+        self
+    }
+
+    /// Truncates string field values longer than the given number of characters, raw fields are not truncated.
+    pub fn with_max_value_len(mut self, value: Option<usize>) -> Self {
+        self.max_value_len = value;
+        self
+    }
+
+    /// Replaces decorative glyphs with ASCII equivalents and escapes non-ASCII characters as `\uXXXX`.
+    pub fn with_ascii(mut self, value: bool) -> Self {
+        self.ascii = value;
+        self
+    }
+
+    /// Enables styling of level words like `error` or `warning` in message text with the style of the corresponding level.
+    pub fn with_message_level_highlighting(mut self, value: bool) -> Self {
+        self.highlight_levels = value;
+        self
+    }
+
+    /// Enables styling of `key=value` pairs in message text with styles of field keys and values.
+    pub fn with_message_key_value_highlighting(mut self, value: bool) -> Self {
+        self.highlight_key_values = value;
+        self
+    }
+
+    /// Enables rendering of the message text only, without time, level, logger, fields and caller.
+    pub fn with_message_only(mut self, value: bool) -> Self {
+        self.message_only = value;
+        self
+    }
+
+    /// Skips records having no message in message-only mode instead of rendering empty lines for them.
+    pub fn with_missing_message_skipping(mut self, value: bool) -> Self {
+        self.skip_missing_messages = value;
+        self
+    }
+
+    /// Sets the text shown in place of `null` values, `None` keeps them as is.
+    pub fn with_null_text(mut self, value: Option<String>) -> Self {
+        self.null_text = value;
+        self
+    }
+
+    /// Limits the number of rendered fields per record, predefined fields such as time, level and message are not counted.
+    /// Fields hidden by include/exclude filters or as empty ones are not counted either.
+    pub fn with_max_fields(mut self, value: Option<usize>) -> Self {
+        self.max_fields = value;
+        self
+    }
+
+    /// Sets the order of the time and level columns.
+    pub fn with_layout(mut self, value: Layout) -> Self {
+        self.layout = value;
+        self
+    }
+
+    /// Enables folding of fields having the same values as in the previous record.
+    /// All fields are shown again once the set of field keys differs from the previous record.
+    /// The formatter must see records in their output order for folding to make sense.
+    pub fn with_field_folding(mut self, value: bool) -> Self {
+        self.folded_fields = if value { Some(Vec::new()) } else { None };
+        self
+    }
+
+    /// Returns the field set of the previous record used for folding.
+    pub fn folded_fields(&self) -> Option<FieldSet> {
+        self.folded_fields.clone()
+    }
+
+    /// Restores the field set of the previous record saved by [`folded_fields`](Self::folded_fields).
+    pub fn set_folded_fields(&mut self, value: Option<FieldSet>) {
+        self.folded_fields = value;
+    }
+
+    /// Controls whether styles are reset before the newline ending each record, enabled by default.
+    /// If disabled, the style of the last element of a record is kept across the newline.
+    pub fn with_trailing_reset(mut self, value: bool) -> Self {
+        self.trailing_reset = value;
+        self
+    }
+
+    /// Sets the field holding a UTC offset or a time zone name used to display the timestamp of each record,
+    /// records without the field or with an unrecognized value use the configured time zone.
+    pub fn with_time_zone_field(mut self, key: Option<String>) -> Self {
+        self.time_zone_field = key;
+        self
+    }
+
+    /// Moves the given fields out of the regular field list into a right-aligned column
+    /// of the given width at the end of the line.
+    pub fn with_status_fields(mut self, fields: Vec<String>, width: usize) -> Self {
+        self.status_fields = fields;
+        self.status_width = width;
+        self
+    }
+
+    /// Returns true if field alignment is enabled.
+    pub fn aligns_fields(&self) -> bool {
+        self.align_fields
+    }
+
+    /// Forgets field widths collected so far, starting a new alignment window.
+    pub fn reset_field_widths(&mut self) {
+        self.field_widths.get_mut().clear();
+    }
+
     pub fn format_record(&mut self, buf: &mut Buf, rec: &model::Record) {
+        if self.message_only && self.skip_missing_messages && rec.message.is_none() {
+            return;
+        }
+        let start = buf.len();
+        let folded = self.fold(rec);
         self.theme.apply(buf, &rec.level, |s| {
+            if self.message_only {
+                if let Some(text) = rec.message {
+                    s.element(Element::Message, |s| {
+                        self.format_message(s, text, &rec.level)
+                    });
+                }
+                if self.trailing_reset {
+                    s.batch(|buf| buf.push(b'\n'));
+                } else {
+                    s.raw(|buf| buf.push(b'\n'));
+                }
+                return;
+            }
             //
-            // time
-            //
-            s.element(Element::Time, |s| {
-                s.batch(|buf| {
-                    if let Some(ts) = &rec.ts {
-                        aligned_left(buf, self.ts_width, b' ', |mut buf| {
-                            if ts
-                                .as_rfc3339()
-                                .and_then(|ts| self.ts_formatter.reformat_rfc3339(&mut buf, ts))
-                                .is_none()
-                            {
-                                if let Some(ts) = ts.parse() {
-                                    self.ts_formatter.format(&mut buf, ts);
-                                } else {
-                                    buf.extend_from_slice(ts.raw().as_bytes());
-                                }
-                            }
-                        });
-                    } else {
-                        centered(buf, self.ts_width, b' ', |mut buf| {
-                            buf.extend_from_slice(b"---");
-                        });
-                    }
-                })
-            });
-            //
-            // level
+            // time and level
             //
-            s.space();
-            s.element(Element::Level, |s| {
-                s.batch(|buf| {
-                    buf.push(b'|');
-                });
-                s.element(Element::LevelInner, |s| {
-                    s.batch(|buf| {
-                        buf.extend_from_slice(match rec.level {
-                            Some(Level::Debug) => b"DBG",
-                            Some(Level::Info) => b"INF",
-                            Some(Level::Warning) => b"WRN",
-                            Some(Level::Error) => b"ERR",
-                            _ => b"(?)",
-                        })
-                    })
-                });
-                s.batch(|buf| buf.push(b'|'));
-            });
+            match self.layout {
+                Layout::TimeFirst => {
+                    self.format_time(s, rec);
+                    s.space();
+                    self.format_level(s, rec);
+                }
+                Layout::LevelFirst => {
+                    self.format_level(s, rec);
+                    s.space();
+                    self.format_time(s, rec);
+                }
+            }
             //
             // logger
             //
@@ -126,68 +416,457 @@ impl RecordFormatter {
             //
             if let Some(text) = rec.message {
                 s.batch(|buf| buf.push(b' '));
-                s.element(Element::Message, |s| self.format_message(s, text));
+                s.element(Element::Message, |s| {
+                    self.format_message(s, text, &rec.level)
+                });
             }
             //
             // fields
             //
-            let mut some_fields_hidden = false;
-            for (k, v) in rec.fields() {
-                if !self.hide_empty_fields
-                    || match v.get() {
-                        r#""""# | "null" | "{}" | "[]" => false,
-                        _ => true,
+            let mut hidden = 0;
+            let mut shown = 0;
+            let mut omitted = 0;
+            let fields = if self.sort_fields {
+                let mut fields: Vec<_> = rec.fields().collect();
+                fields.sort_by_key(|(k, _)| *k);
+                Either::Left(fields.into_iter())
+            } else {
+                Either::Right(rec.fields())
+            };
+            for (k, v) in fields {
+                if self.status_fields.iter().any(|f| f == k) {
+                    continue;
+                }
+                if folded.contains(k) {
+                    continue;
+                }
+                // excluded fields are checked first, so their values are never parsed
+                if self.field_excluded(k) {
+                    if !self.hide_empty_fields || !self.empty_values.contains(v.get()) {
+                        hidden += 1;
                     }
-                {
-                    some_fields_hidden |= !self.format_field(s, k, v, Some(&self.fields));
+                    continue;
+                }
+                if self.hide_empty_fields && self.empty_values.contains(v.get()) {
+                    continue;
+                }
+                if self.max_fields.map(|max| shown >= max).unwrap_or(false) {
+                    omitted += 1;
+                    continue;
+                }
+                if let Some(n) = self.format_field(s, k, v, Some(&self.fields), self.align_fields) {
+                    shown += 1;
+                    hidden += n;
                 }
             }
-            if some_fields_hidden {
+            if omitted != 0 {
                 s.element(Element::Ellipsis, |s| {
-                    s.batch(|buf| buf.extend_from_slice(b" ..."))
+                    s.batch(|buf| {
+                        buf.push(b' ');
+                        buf.extend_from_slice(self.ellipsis_glyph().as_bytes());
+                        buf.extend_from_slice(b"(+");
+                        buf.extend_from_slice(omitted.to_string().as_bytes());
+                        buf.extend_from_slice(if omitted == 1 {
+                            b" field)"
+                        } else {
+                            b" fields)"
+                        });
+                    })
                 });
             }
+            if hidden != 0 {
+                self.format_ellipsis(s, hidden);
+            }
             //
             // caller
             //
-            if let Some(text) = rec.caller {
+            if rec.caller.is_some() || rec.caller_function.is_some() {
                 s.element(Element::Caller, |s| {
                     s.batch(|buf| buf.extend_from_slice(b" @ "));
-                    s.element(Element::CallerInner, |s| {
-                        s.batch(|buf| buf.extend_from_slice(text.as_bytes()))
-                    });
+                    match (rec.caller_function, rec.caller) {
+                        (Some(function), Some(caller)) => {
+                            s.element(Element::CallerInner, |s| {
+                                s.batch(|buf| buf.extend_from_slice(function.as_bytes()))
+                            });
+                            s.batch(|buf| buf.extend_from_slice(b" ("));
+                            s.element(Element::CallerInner, |s| {
+                                s.batch(|buf| buf.extend_from_slice(caller.as_bytes()))
+                            });
+                            s.batch(|buf| buf.push(b')'));
+                        }
+                        (Some(text), None) | (None, Some(text)) => {
+                            s.element(Element::CallerInner, |s| {
+                                s.batch(|buf| buf.extend_from_slice(text.as_bytes()))
+                            });
+                        }
+                        (None, None) => {}
+                    }
                 });
             };
             //
+            // status
+            //
+            if !self.status_fields.is_empty() {
+                let mut start = 0;
+                s.batch(|buf| start = buf.len());
+                for name in &self.status_fields {
+                    if self.field_excluded(name) {
+                        continue;
+                    }
+                    if let Some((k, v)) = rec.fields().find(|(k, _)| k == name) {
+                        self.format_field(s, k, v, Some(&self.fields), false);
+                    }
+                }
+                s.batch(|buf| {
+                    let width = self.visible_width(&buf[start..]);
+                    if width < self.status_width {
+                        buf.splice(start..start, vec![b' '; self.status_width - width]);
+                    }
+                });
+            }
+            //
             // eol
             //
+            if self.trailing_reset {
+                s.batch(|buf| buf.push(b'\n'));
+            } else {
+                s.raw(|buf| buf.push(b'\n'));
+            }
+        });
+        if self.ascii {
+            escape_non_ascii(buf, start);
+        }
+    }
+
+    pub fn format_parse_error(&self, buf: &mut Buf, error: &str) {
+        let start = buf.len();
+        self.theme.apply(buf, &Some(Level::Error), |s| {
+            s.element(Element::LevelInner, |s| {
+                s.batch(|buf| buf.extend_from_slice(b"parse error:"))
+            });
+            s.space();
+            s.element(Element::Message, |s| {
+                s.batch(|buf| buf.extend_from_slice(error.as_bytes()))
+            });
+            s.batch(|buf| buf.push(b'\n'));
+        });
+        if self.ascii {
+            escape_non_ascii(buf, start);
+        }
+    }
+
+    /// Formats the beginning of a line truncated because of its size, followed by a truncation marker.
+    pub fn format_truncated_line(&self, buf: &mut Buf, head: &[u8]) {
+        let start = buf.len();
+        buf.extend_from_slice(head);
+        self.theme.apply(buf, &None, |s| {
+            s.element(Element::Ellipsis, |s| {
+                s.batch(|buf| {
+                    buf.push(b' ');
+                    buf.extend_from_slice(self.ellipsis_glyph().as_bytes());
+                    buf.extend_from_slice(b"(truncated)");
+                })
+            });
+            s.batch(|buf| buf.push(b'\n'));
+        });
+        if self.ascii {
+            escape_non_ascii(buf, start);
+        }
+    }
+
+    /// Returns keys of fields having the same values as in the previous record if folding is enabled
+    /// and the record has the same field keys in the same order, and remembers the fields of the record.
+    fn fold<'a>(&mut self, rec: &model::Record<'a>) -> Vec<&'a str> {
+        let prev = match self.folded_fields.as_mut() {
+            Some(prev) => prev,
+            None => return Vec::new(),
+        };
+        let same_keys = rec.fields().count() == prev.len()
+            && rec
+                .fields()
+                .zip(prev.iter())
+                .all(|((k, _), (pk, _))| k.as_bytes() == &pk[..]);
+        let folded = if same_keys {
+            rec.fields()
+                .zip(prev.iter())
+                .filter(|((_, v), (_, pv))| v.get().as_bytes() == &pv[..])
+                .map(|((k, _), _)| *k)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        prev.clear();
+        prev.extend(
+            rec.fields()
+                .map(|(k, v)| (k.as_bytes().to_vec(), v.get().as_bytes().to_vec())),
+        );
+        folded
+    }
+
+    /// Formats the byte offset of a record, prefixed with the input name if there are multiple inputs.
+    pub fn format_offset(&self, buf: &mut Buf, input: Option<&str>, offset: u64) {
+        self.theme.apply(buf, &None, |s| {
+            s.element(Element::Offset, |s| {
+                s.batch(|buf| {
+                    if let Some(input) = input {
+                        buf.extend_from_slice(input.as_bytes());
+                        buf.push(b':');
+                    }
+                    buf.extend_from_slice(offset.to_string().as_bytes());
+                    buf.push(b':');
+                })
+            });
+            s.space();
+        });
+    }
+
+    /// Formats the raw source of a record on a separate line, using the ellipsis style that is dimmed in most themes.
+    pub fn format_raw(&self, buf: &mut Buf, raw: &[u8]) {
+        let start = buf.len();
+        self.theme.apply(buf, &None, |s| {
+            s.element(Element::Ellipsis, |s| {
+                s.batch(|buf| buf.extend_from_slice(raw))
+            });
             s.batch(|buf| buf.push(b'\n'));
         });
+        if self.ascii {
+            escape_non_ascii(buf, start);
+        }
     }
 
-    fn format_field<S: StylingPush<Buf>>(
+    /// Formats the field and returns the number of its hidden nested fields, or None if the field was hidden as empty.
+    fn format_field<S: StylingTransact<Buf>>(
         &self,
         s: &mut S,
         key: &str,
         value: &RawValue,
         filter: Option<&IncludeExcludeKeyFilter>,
-    ) -> bool {
+        align: bool,
+    ) -> Option<usize> {
         let mut fv = FieldFormatter::new(self);
-        s.element(Element::Field, |s| {
+        fv.align = align;
+        let hidden = s.element(Element::Field, |s| {
             fv.format(s, key, value, filter, IncludeExcludeSetting::Unspecified)
-        })
+        });
+        if hidden == 0 && fv.shown == 0 {
+            return None;
+        }
+        Some(hidden)
+    }
+
+    /// Returns true if the top-level field with the given key is completely hidden by include/exclude filters.
+    fn field_excluded(&self, key: &str) -> bool {
+        let setting = IncludeExcludeSetting::Unspecified.apply(self.fields.setting());
+        match self.fields.get(key) {
+            Some(filter) => {
+                setting.apply(filter.setting()) == IncludeExcludeSetting::Exclude && filter.leaf()
+            }
+            None => setting == IncludeExcludeSetting::Exclude,
+        }
+    }
+
+    fn ellipsis_glyph(&self) -> &'static str {
+        if self.ascii {
+            "..."
+        } else {
+            "…"
+        }
+    }
+
+    /// Returns the width of the given text as it is output, taking escaping of non-ASCII characters into account.
+    fn visible_width(&self, text: &[u8]) -> usize {
+        if self.ascii {
+            escaped_width(text)
+        } else {
+            visible_width(text)
+        }
+    }
+
+    /// Styles level words and `key=value` pairs in the message text starting at the given position,
+    /// restoring the message style after each of them.
+    /// A key is a run of ASCII alphanumerics and underscores followed by `=` and a value, which spans up to the next whitespace,
+    /// or up to the closing unescaped double quote if it starts with one. Numeric values are styled as numbers, others as strings.
+    /// Text following an escape sequence already present in the message is considered styled and copied as is.
+    fn highlight_message(&self, buf: &mut Buf, start: usize, level: &Option<Level>) {
+        let text = buf.split_off(start);
+        let restore = match self.theme.sequence_for(level, Element::Message) {
+            b"" => Sequence::reset().data().to_vec(),
+            seq => seq.to_vec(),
+        };
+        let styled = |buf: &mut Buf, seq: &[u8], text: &[u8]| {
+            if seq.is_empty() {
+                buf.extend_from_slice(text);
+            } else {
+                buf.extend_from_slice(seq);
+                buf.extend_from_slice(text);
+                buf.extend_from_slice(&restore);
+            }
+        };
+        let mut i = 0;
+        while i < text.len() {
+            if text[i] == 0x1b {
+                buf.extend_from_slice(&text[i..]);
+                break;
+            } else if text[i].is_ascii_alphanumeric() || text[i] == b'_' {
+                let end = text[i..]
+                    .iter()
+                    .position(|b| !b.is_ascii_alphanumeric() && *b != b'_')
+                    .map_or(text.len(), |n| i + n);
+                let word = &text[i..end];
+                if self.highlight_key_values {
+                    if let Some(value_end) = key_value_end(&text, end) {
+                        let value = &text[end + 1..value_end];
+                        let element = match json::from_slice::<f64>(value) {
+                            Ok(_) => Element::Number,
+                            Err(_) => Element::String,
+                        };
+                        styled(buf, self.theme.sequence_for(level, Element::Key), word);
+                        buf.push(b'=');
+                        styled(buf, self.theme.sequence_for(level, element), value);
+                        i = value_end;
+                        continue;
+                    }
+                }
+                if !self.highlight_levels {
+                    buf.extend_from_slice(word);
+                    i = end;
+                    continue;
+                }
+                let seq = match level_word(word) {
+                    Some(level) => {
+                        let seq = self.theme.sequence_for(&Some(level), Element::LevelInner);
+                        if seq.is_empty() {
+                            self.theme.sequence_for(&Some(level), Element::Level)
+                        } else {
+                            seq
+                        }
+                    }
+                    None => b"",
+                };
+                if seq.is_empty() {
+                    buf.extend_from_slice(word);
+                } else {
+                    buf.extend_from_slice(seq);
+                    buf.extend_from_slice(word);
+                    buf.extend_from_slice(&restore);
+                }
+                i = end;
+            } else {
+                buf.push(text[i]);
+                i += 1;
+            }
+        }
+    }
+
+    /// Returns the time zone of the record taken from the time zone field, if it is configured and recognized.
+    fn record_time_zone(&self, rec: &model::Record, ts: &Timestamp) -> Option<FixedOffset> {
+        let key = self.time_zone_field.as_deref()?;
+        let (_, value) = rec.fields().find(|(k, _)| *k == key)?;
+        let value = json::from_str::<&str>(value.get()).ok()?;
+        parse_time_zone(value, ts.parse()?.with_timezone(&Utc))
+    }
+
+    fn null_text<'v>(&'v self, value: &'v RawValue) -> &'v str {
+        self.null_text.as_deref().unwrap_or(value.get())
+    }
+
+    fn format_time<S: StylingPush<Buf>>(&self, s: &mut S, rec: &model::Record) {
+        s.element(Element::Time, |s| {
+            s.batch(|buf| {
+                if let Some(ts) = &rec.ts {
+                    let tz = self.record_time_zone(rec, ts);
+                    aligned_left_by_display_width(buf, self.ts_width, b' ', |mut buf| {
+                        let formatted = match (tz, ts.parse()) {
+                            (Some(tz), Some(ts)) => {
+                                self.ts_formatter.format_in(&mut buf, ts, tz);
+                                true
+                            }
+                            _ => format_timestamp(&mut buf, &self.ts_formatter, ts),
+                        };
+                        if !formatted {
+                            buf.extend_from_slice(ts.raw().as_bytes());
+                        }
+                    });
+                    if let Some((formatter, width)) = &self.secondary_ts_formatter {
+                        buf.extend_from_slice(b" (");
+                        aligned_left_by_display_width(buf, *width, b' ', |mut buf| {
+                            format_timestamp(&mut buf, formatter, ts);
+                        });
+                        buf.push(b')');
+                    }
+                } else {
+                    let width = match &self.secondary_ts_formatter {
+                        Some((_, width)) => self.ts_width + width + 3,
+                        None => self.ts_width,
+                    };
+                    centered(buf, width, b' ', |mut buf| {
+                        buf.extend_from_slice(b"---");
+                    });
+                }
+            })
+        });
+    }
+
+    fn format_level<S: StylingPush<Buf>>(&self, s: &mut S, rec: &model::Record) {
+        s.element(Element::Level, |s| {
+            s.batch(|buf| {
+                buf.push(b'|');
+            });
+            s.element(Element::LevelInner, |s| {
+                s.batch(|buf| {
+                    buf.extend_from_slice(match rec.level {
+                        Some(Level::Debug) => b"DBG",
+                        Some(Level::Info) => b"INF",
+                        Some(Level::Warning) => b"WRN",
+                        Some(Level::Error) => b"ERR",
+                        _ => b"(?)",
+                    })
+                })
+            });
+            s.batch(|buf| buf.push(b'|'));
+        });
     }
 
-    fn format_value<S: StylingPush<Buf>>(&self, s: &mut S, value: &RawValue) {
+    fn format_ellipsis<S: StylingPush<Buf>>(&self, s: &mut S, hidden: usize) {
+        if self.show_hidden_count {
+            s.element(Element::Ellipsis, |s| {
+                s.batch(|buf| {
+                    buf.extend_from_slice(b" (+");
+                    buf.extend_from_slice(hidden.to_string().as_bytes());
+                    buf.extend_from_slice(b" hidden)");
+                })
+            });
+        } else if let Some(ellipsis) = &self.ellipsis {
+            s.element(Element::Ellipsis, |s| {
+                s.batch(|buf| {
+                    buf.push(b' ');
+                    buf.extend_from_slice(ellipsis.as_bytes());
+                })
+            });
+        }
+    }
+
+    fn format_value<S: StylingTransact<Buf>>(&self, s: &mut S, value: &RawValue) {
         let mut fv = FieldFormatter::new(self);
         fv.format_value(s, value, None, IncludeExcludeSetting::Unspecified);
     }
 
-    fn format_message<S: StylingPush<Buf>>(&self, s: &mut S, value: &RawValue) {
+    fn format_message<S: StylingTransact<Buf>>(
+        &self,
+        s: &mut S,
+        value: &RawValue,
+        level: &Option<Level>,
+    ) {
         match value.get().as_bytes()[0] {
             b'"' => {
                 s.element(Element::Message, |s| {
-                    s.batch(|buf| format_str_unescaped(buf, value.get()))
+                    s.batch(|buf| {
+                        let start = buf.len();
+                        format_str_unescaped(buf, value.get());
+                        if self.highlight_levels || self.highlight_key_values {
+                            self.highlight_message(buf, start, level);
+                        }
+                    })
                 });
             }
             b'0'..=b'9' | b'-' | b'+' | b'.' => {
@@ -202,19 +881,18 @@ impl RecordFormatter {
             }
             b'n' => {
                 s.element(Element::Null, |s| {
-                    s.batch(|buf| buf.extend_from_slice(value.get().as_bytes()))
+                    s.batch(|buf| buf.extend_from_slice(self.null_text(value).as_bytes()))
                 });
             }
             b'{' => {
                 s.element(Element::Object, |s| {
                     let item = json::from_str::<model::Object>(value.get()).unwrap();
                     s.batch(|buf| buf.push(b'{'));
-                    let mut has_some = false;
                     for (k, v) in item.fields.iter() {
-                        has_some |= self.format_field(s, k, v, None)
+                        self.format_field(s, k, v, None, false);
                     }
                     s.batch(|buf| {
-                        if has_some {
+                        if !item.fields.is_empty() {
                             buf.push(b' ');
                         }
                         buf.push(b'}');
@@ -236,7 +914,7 @@ impl RecordFormatter {
                     s.element(Element::Message, |s| {
                         for item in item.iter() {
                             let b = atoi::atoi::<u8>(item.get().as_bytes()).unwrap();
-                            if b >= 32 {
+                            if b >= 32 && (b < 128 || !self.ascii) {
                                 s.batch(|buf| buf.push(b));
                             } else {
                                 s.element(Element::String, |s| {
@@ -276,27 +954,79 @@ impl RecordFormatter {
 }
 
 fn format_str_unescaped(buf: &mut Buf, s: &str) {
+    let n = buf.len();
     let mut reader = StrRead::new(&s[1..]);
-    reader.parse_str_raw(buf).unwrap();
+    let result = if n == 0 {
+        // strings without escapes are returned borrowed instead of being copied to an empty scratch buffer
+        let mut scratch = Vec::new();
+        reader
+            .parse_str_raw(&mut scratch)
+            .map(|text| buf.extend_from_slice(&text))
+    } else {
+        reader.parse_str_raw(buf).map(|_| ())
+    };
+    if result.is_err() {
+        buf.truncate(n);
+        let s = &s[1..];
+        buf.extend_from_slice(s.strip_suffix('"').unwrap_or(s).as_bytes());
+    }
 }
 
 struct FieldFormatter<'a> {
     rf: &'a RecordFormatter,
+    prefix: Vec<u8>,
+    path: Vec<u8>,
+    align: bool,
+    depth: usize,
+    shown: usize,
 }
 
 impl<'a> FieldFormatter<'a> {
     fn new(rf: &'a RecordFormatter) -> Self {
-        Self { rf }
+        Self {
+            rf,
+            prefix: Vec::new(),
+            path: Vec::new(),
+            align: false,
+            depth: 0,
+            shown: 0,
+        }
     }
 
-    fn format<S: StylingPush<Buf>>(
+    fn format<S: StylingTransact<Buf>>(
         &mut self,
         s: &mut S,
         key: &str,
         value: &'a RawValue,
         filter: Option<&IncludeExcludeKeyFilter>,
         setting: IncludeExcludeSetting,
-    ) -> bool {
+    ) -> usize {
+        if self.rf.raw_fields.is_empty()
+            && self.rf.nested_fields.is_empty()
+            && self.rf.anonymized_fields.is_empty()
+        {
+            return self.format_key_value(s, key, value, filter, setting, self.rf.unescape_fields);
+        }
+        let n = self.path.len();
+        if n != 0 {
+            self.path.push(self.rf.path_delimiter);
+        }
+        push_key(&mut self.path, key);
+        let unescape = self.rf.unescape_fields && !self.rf.raw_fields.contains(&self.path);
+        let result = self.format_key_value(s, key, value, filter, setting, unescape);
+        self.path.truncate(n);
+        result
+    }
+
+    fn format_key_value<S: StylingTransact<Buf>>(
+        &mut self,
+        s: &mut S,
+        key: &str,
+        value: &'a RawValue,
+        filter: Option<&IncludeExcludeKeyFilter>,
+        setting: IncludeExcludeSetting,
+        unescape: bool,
+    ) -> usize {
         let (filter, setting, leaf) = match filter {
             Some(filter) => {
                 let setting = setting.apply(filter.setting());
@@ -308,41 +1038,204 @@ impl<'a> FieldFormatter<'a> {
             None => (None, setting, true),
         };
         if setting == IncludeExcludeSetting::Exclude && leaf {
-            return false;
+            return 1;
         }
-        s.space();
-        s.element(Element::Key, |s| {
-            for b in key.as_bytes() {
-                let b = if *b == b'_' { b'-' } else { *b };
-                s.batch(|buf| buf.push(b.to_ascii_lowercase()));
+        if self.rf.hide_empty_fields && self.rf.empty_values.contains(value.get()) {
+            return 0;
+        }
+        let anonymizer = match &self.rf.anonymizer {
+            Some(anonymizer) if self.rf.anonymized_fields.contains(&self.path) => Some(anonymizer),
+            _ => None,
+        };
+        let unescape = unescape && anonymizer.is_none();
+        if unescape && self.depth < MAX_NESTED_DEPTH && self.nested() {
+            if let Some(nested) = parse_nested(value) {
+                let mut fv = FieldFormatter {
+                    rf: self.rf,
+                    prefix: self.prefix.clone(),
+                    path: self.path.clone(),
+                    align: self.align,
+                    depth: self.depth + 1,
+                    shown: 0,
+                };
+                let hidden = fv.format_key_value(s, key, &nested, filter, setting, unescape);
+                self.shown += fv.shown;
+                return hidden;
             }
-        });
-        s.batch(|buf| buf.push(b'='));
-        if self.rf.unescape_fields {
-            self.format_value(s, value, filter, setting);
-        } else {
-            s.element(Element::String, |s| {
-                s.batch(|buf| buf.extend_from_slice(value.get().as_bytes()))
+        }
+        // with unescaping disabled for all fields, structure is still flattened and leaf values are kept raw
+        if unescape || (!self.rf.unescape_fields && anonymizer.is_none()) {
+            match value.get().as_bytes()[0] {
+                b'{' if self.rf.flatten_objects => {
+                    let item = json::from_str::<model::Object>(value.get()).unwrap();
+                    if !item.fields.is_empty() {
+                        return self.format_flattened(
+                            s,
+                            key,
+                            item.fields.iter().map(|(k, v)| (*k, *v)),
+                            filter,
+                            setting,
+                        );
+                    }
+                }
+                b'[' if self.rf.flatten_arrays => {
+                    let item = json::from_str::<model::Array<32>>(value.get()).unwrap();
+                    if item.iter().next().is_some() {
+                        let items: Vec<_> = item
+                            .iter()
+                            .enumerate()
+                            .map(|(i, v)| (i.to_string(), *v))
+                            .collect();
+                        return self.format_flattened(
+                            s,
+                            key,
+                            items.iter().map(|(k, v)| (&k[..], *v)),
+                            filter,
+                            setting,
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+        // objects are checked for emptiness while being formatted and rolled back if all their fields were hidden
+        let hide_empty = self.rf.hide_empty_fields && unescape && value.get().starts_with('{');
+        let mut start = 0;
+        let mut format = |s: &mut S| {
+            s.space();
+            s.element(Element::Key, |s| {
+                s.batch(|buf| buf.extend_from_slice(&self.prefix));
+                for b in model::strip_key_prefix(key, &self.rf.key_prefixes).as_bytes() {
+                    let b = if *b == b'_' { b'-' } else { *b };
+                    s.batch(|buf| buf.push(b.to_ascii_lowercase()));
+                }
             });
+            s.batch(|buf| {
+                buf.push(b'=');
+                start = buf.len();
+            });
+            let mut empty = false;
+            if let Some(anonymizer) = anonymizer {
+                s.element(Element::String, |s| {
+                    s.batch(|buf| {
+                        let text = match value.get().as_bytes()[0] {
+                            b'"' => json::from_str::<String>(value.get()).ok(),
+                            _ => None,
+                        };
+                        let text = text.as_deref().unwrap_or(value.get());
+                        let mut prefix = Vec::new();
+                        push_key(&mut prefix, key);
+                        let prefix = String::from_utf8_lossy(&prefix);
+                        let prefix = prefix
+                            .strip_suffix("-id")
+                            .filter(|p| !p.is_empty())
+                            .unwrap_or(&prefix);
+                        anonymizer.token(buf, prefix, text.as_bytes());
+                    })
+                });
+            } else if unescape {
+                empty = self.format_value(s, value, filter, setting);
+            } else {
+                s.element(Element::String, |s| {
+                    s.batch(|buf| buf.extend_from_slice(value.get().as_bytes()))
+                });
+            }
+            !empty
+        };
+        let kept = if hide_empty {
+            s.transact(format)
+        } else {
+            format(s)
+        };
+        if !kept {
+            return 0;
+        }
+        self.shown += 1;
+        if self.align {
+            s.batch(|buf| self.pad(buf, key, start));
         }
-        true
+        0
     }
 
-    fn format_value<S: StylingPush<Buf>>(
+    /// Returns true if string values of the current field should be parsed as nested JSON.
+    fn nested(&self) -> bool {
+        if self.rf.nested_fields.is_empty() {
+            return false;
+        }
+        let path = String::from_utf8_lossy(&self.path);
+        self.rf
+            .nested_fields
+            .iter()
+            .any(|pattern| pattern.matches(&path))
+    }
+
+    fn pad(&self, buf: &mut Buf, key: &str, start: usize) {
+        let mut name = self.prefix.clone();
+        push_key(&mut name, key);
+        let width = self.rf.visible_width(&buf[start..]);
+        let mut widths = self.rf.field_widths.borrow_mut();
+        let max = widths.entry(name).or_insert(0);
+        if *max < width {
+            *max = width;
+        }
+        buf.resize(buf.len() + (*max - width), b' ');
+    }
+
+    fn format_flattened<'k, S, I>(
+        &mut self,
+        s: &mut S,
+        key: &str,
+        items: I,
+        filter: Option<&IncludeExcludeKeyFilter>,
+        setting: IncludeExcludeSetting,
+    ) -> usize
+    where
+        S: StylingTransact<Buf>,
+        I: Iterator<Item = (&'k str, &'a RawValue)>,
+    {
+        let n = self.prefix.len();
+        for b in model::strip_key_prefix(key, &self.rf.key_prefixes).as_bytes() {
+            let b = if *b == b'_' { b'-' } else { *b };
+            self.prefix.push(b.to_ascii_lowercase());
+        }
+        self.prefix.push(self.rf.path_delimiter);
+        let mut hidden = 0;
+        for (k, v) in items {
+            hidden += self.format(s, k, v, filter, setting);
+        }
+        self.prefix.truncate(n);
+        hidden
+    }
+
+    /// Formats the value and returns true if it is a non-empty object with all fields hidden as empty.
+    fn format_value<S: StylingTransact<Buf>>(
         &mut self,
         s: &mut S,
         value: &'a RawValue,
         filter: Option<&IncludeExcludeKeyFilter>,
         setting: IncludeExcludeSetting,
-    ) {
+    ) -> bool {
+        let mut empty = false;
         match value.get().as_bytes()[0] {
             b'"' => {
                 s.element(Element::String, |s| {
+                    let mut truncated = false;
                     s.batch(|buf| {
                         buf.push(b'\'');
+                        let start = buf.len();
                         format_str_unescaped(buf, value.get());
-                        buf.push(b'\'');
-                    })
+                        if let Some(max) = self.rf.max_value_len {
+                            truncated = truncate_chars(buf, start, max);
+                        }
+                    });
+                    if truncated {
+                        s.element(Element::Ellipsis, |s| {
+                            s.batch(|buf| {
+                                buf.extend_from_slice(self.rf.ellipsis_glyph().as_bytes())
+                            })
+                        });
+                    }
+                    s.batch(|buf| buf.push(b'\''));
                 });
             }
             b'0'..=b'9' | b'-' | b'+' | b'.' => {
@@ -357,21 +1250,25 @@ impl<'a> FieldFormatter<'a> {
             }
             b'n' => {
                 s.element(Element::Null, |s| {
-                    s.batch(|buf| buf.extend_from_slice(value.get().as_bytes()))
+                    s.batch(|buf| buf.extend_from_slice(self.rf.null_text(value).as_bytes()))
                 });
             }
             b'{' => {
                 let item = json::from_str::<model::Object>(value.get()).unwrap();
                 s.element(Element::Object, |s| {
                     s.batch(|buf| buf.push(b'{'));
-                    let mut some_fields_hidden = false;
+                    let prefix = std::mem::take(&mut self.prefix);
+                    let align = std::mem::replace(&mut self.align, false);
+                    let shown = self.shown;
+                    let mut hidden = 0;
                     for (k, v) in item.fields.iter() {
-                        some_fields_hidden |= !self.format(s, k, v, filter, setting);
+                        hidden += self.format(s, k, v, filter, setting);
                     }
-                    if some_fields_hidden {
-                        s.element(Element::Ellipsis, |s| {
-                            s.batch(|buf| buf.extend_from_slice(b" ..."))
-                        });
+                    empty = hidden == 0 && self.shown == shown && !item.fields.is_empty();
+                    self.prefix = prefix;
+                    self.align = align;
+                    if hidden != 0 {
+                        self.rf.format_ellipsis(s, hidden);
                     }
                     s.batch(|buf| {
                         if item.fields.len() != 0 {
@@ -403,13 +1300,1126 @@ impl<'a> FieldFormatter<'a> {
                 });
             }
         };
+        empty
+    }
+}
+
+/// Formats a faint rule line with the given label separating groups of records.
+/// The rule is drawn with `-` instead of box-drawing characters in ASCII mode.
+pub fn format_separator(theme: &Theme, buf: &mut Buf, label: &str, ascii: bool) {
+    let (head, rule) = if ascii {
+        (ASCII_SEPARATOR_HEAD, ASCII_SEPARATOR_RULE)
+    } else {
+        (SEPARATOR_HEAD, SEPARATOR_RULE)
+    };
+    theme.apply(buf, &None, |s| {
+        s.element(Element::Ellipsis, |s| {
+            s.batch(|buf| {
+                buf.extend_from_slice(head.as_bytes());
+                buf.push(b' ');
+                buf.extend_from_slice(label.as_bytes());
+                buf.push(b' ');
+                buf.extend_from_slice(rule.as_bytes());
+            })
+        });
+        s.batch(|buf| buf.push(b'\n'));
+    });
+}
+
+/// Formats the time elapsed since the previous record as a right-aligned column like `+0.012s`,
+/// or `--` if the record has no timestamp.
+pub fn format_time_delta(theme: &Theme, buf: &mut Buf, delta: Option<chrono::Duration>) {
+    let text = match delta {
+        Some(delta) => {
+            let ms = delta.num_milliseconds();
+            let sign = if ms < 0 { '-' } else { '+' };
+            format!("{}{}.{:03}s", sign, ms.abs() / 1000, ms.abs() % 1000)
+        }
+        None => "--".into(),
+    };
+    theme.apply(buf, &None, |s| {
+        s.element(Element::Time, |s| {
+            s.batch(|buf| {
+                let pad = TIME_DELTA_WIDTH.saturating_sub(text.len());
+                buf.resize(buf.len() + pad, b' ');
+                buf.extend_from_slice(text.as_bytes());
+            })
+        });
+        s.space();
+    });
+}
+
+fn timestamp_width(formatter: &DateTimeFormatter) -> usize {
+    let mut buf = Vec::new();
+    let tts = Utc.ymd(2020, 12, 30).and_hms_nano(23, 59, 49, 999_999_999);
+    formatter.format(&mut buf, tts.into());
+    display_width(&buf)
+}
+
+fn format_timestamp<B: Push<u8>>(
+    buf: &mut B,
+    formatter: &DateTimeFormatter,
+    ts: &Timestamp,
+) -> bool {
+    if ts
+        .as_rfc3339()
+        .and_then(|ts| formatter.reformat_rfc3339(buf, ts))
+        .is_some()
+    {
+        return true;
+    }
+    if let Some(ts) = ts.parse() {
+        formatter.format(buf, ts);
+        return true;
+    }
+    false
+}
+
+/// Parses a UTC offset like `+05:30`, `-0800`, `+03` or `Z`, or a time zone name like `Europe/Berlin`,
+/// returning the offset in effect at the given time.
+fn parse_time_zone(value: &str, at: DateTime<Utc>) -> Option<FixedOffset> {
+    let value = value.trim();
+    if value == "Z" {
+        return Some(FixedOffset::east(0));
+    }
+    let (sign, offset) = match value.as_bytes().first() {
+        Some(b'+') => (1, &value[1..]),
+        Some(b'-') => (-1, &value[1..]),
+        _ => {
+            let tz = value.parse::<Tz>().ok()?;
+            return Some(tz.offset_from_utc_datetime(&at.naive_utc()).fix());
+        }
+    };
+    let digits = offset.replace(':', "");
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (&digits[..], "0"),
+        4 => (&digits[..2], &digits[2..]),
+        _ => return None,
+    };
+    let (hours, minutes) = (hours.parse::<i32>().ok()?, minutes.parse::<i32>().ok()?);
+    if hours > 23 || minutes > 59 {
+        return None;
     }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Returns the display width of the given UTF-8 text excluding ANSI escape sequences.
+fn visible_width(text: &[u8]) -> usize {
+    let mut width = 0;
+    let mut escape = false;
+    let mut start = 0;
+    for (i, &b) in text.iter().enumerate() {
+        if escape {
+            if b.is_ascii_alphabetic() {
+                escape = false;
+                start = i + 1;
+            }
+        } else if b == 0x1b {
+            width += display_width(&text[start..i]);
+            escape = true;
+        }
+    }
+    if !escape {
+        width += display_width(&text[start..]);
+    }
+    width
+}
+
+/// Returns the width of the given UTF-8 text excluding ANSI escape sequences
+/// after escaping of non-ASCII characters by [`escape_non_ascii`].
+fn escaped_width(text: &[u8]) -> usize {
+    let mut width = 0;
+    let mut escape = false;
+    for &b in text {
+        if escape {
+            escape = !b.is_ascii_alphabetic();
+        } else if b == 0x1b {
+            escape = true;
+        } else if b < 0x80 {
+            width += 1;
+        } else if b >= 0xf0 {
+            width += 12;
+        } else if b >= 0xc0 {
+            width += 6;
+        }
+    }
+    width
+}
+
+/// Replaces non-ASCII characters in the text starting at the given position with `\uXXXX` escapes,
+/// characters outside of the basic multilingual plane are escaped as surrogate pairs.
+fn escape_non_ascii(buf: &mut Buf, start: usize) {
+    if buf[start..].is_ascii() {
+        return;
+    }
+    let text = String::from_utf8_lossy(&buf[start..]).into_owned();
+    buf.truncate(start);
+    let mut units = [0; 2];
+    for c in text.chars() {
+        if c.is_ascii() {
+            buf.push(c as u8);
+        } else {
+            for unit in c.encode_utf16(&mut units) {
+                buf.extend_from_slice(format!("\\u{:04x}", unit).as_bytes());
+            }
+        }
+    }
+}
+
+/// Truncates UTF-8 text starting at the given position to the given number of characters.
+/// Returns true if the text was truncated.
+fn truncate_chars(buf: &mut Buf, start: usize, max: usize) -> bool {
+    let mut chars = 0;
+    for i in start..buf.len() {
+        if buf[i] & 0xc0 != 0x80 {
+            if chars == max {
+                buf.truncate(i);
+                return true;
+            }
+            chars += 1;
+        }
+    }
+    false
+}
+
+/// Returns the end of the value if the text at the given position is `=` followed by a value of a `key=value` pair.
+fn key_value_end(text: &[u8], pos: usize) -> Option<usize> {
+    if text.get(pos) != Some(&b'=') {
+        return None;
+    }
+    let start = pos + 1;
+    match text.get(start) {
+        None | Some(b'=') => None,
+        Some(b) if b.is_ascii_whitespace() => None,
+        Some(b'"') => {
+            let mut i = start + 1;
+            while i < text.len() {
+                match text[i] {
+                    b'\\' => i += 2,
+                    b'"' => return Some(i + 1),
+                    _ => i += 1,
+                }
+            }
+            Some(text.len())
+        }
+        Some(_) => Some(
+            text[start..]
+                .iter()
+                .position(|b| b.is_ascii_whitespace())
+                .map_or(text.len(), |n| start + n),
+        ),
+    }
+}
+
+/// Returns the level named by the word, if any.
+fn level_word(word: &[u8]) -> Option<Level> {
+    if word.len() > 7 {
+        return None;
+    }
+    match &word.to_ascii_lowercase()[..] {
+        b"debug" => Some(Level::Debug),
+        b"info" => Some(Level::Info),
+        b"warn" | b"warning" => Some(Level::Warning),
+        b"error" | b"fatal" => Some(Level::Error),
+        _ => None,
+    }
+}
+
+/// Parses a JSON string value containing a JSON object or array, returns `None` if it contains anything else.
+fn parse_nested(value: &RawValue) -> Option<Box<RawValue>> {
+    if !value.get().starts_with('"') {
+        return None;
+    }
+    let text = json::from_str::<String>(value.get()).ok()?;
+    let text = text.trim();
+    if !text.starts_with('{') && !text.starts_with('[') {
+        return None;
+    }
+    json::from_str(text).ok()
 }
 
 fn only_digits(b: &[u8]) -> bool {
     b.iter().position(|&b| !b.is_ascii_digit()).is_none()
 }
 
+fn push_key(buf: &mut Buf, key: &str) {
+    buf.extend(key.bytes().map(|b| {
+        if b == b'_' {
+            b'-'
+        } else {
+            b.to_ascii_lowercase()
+        }
+    }));
+}
+
 const HEXDIGIT: [u8; 16] = [
     b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'a', b'b', b'c', b'd', b'e', b'f',
 ];
+
+const MAX_NESTED_DEPTH: usize = 8;
+
+const SEPARATOR_HEAD: &str = "───";
+const SEPARATOR_RULE: &str = "────────────────────────────────────────";
+const ASCII_SEPARATOR_HEAD: &str = "---";
+const ASCII_SEPARATOR_RULE: &str = "----------------------------------------";
+const TIME_DELTA_WIDTH: usize = 9;
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::datefmt::LinuxDateFormat;
+    use crate::model::{Parser, ParserSettings, RawRecord};
+    use crate::settings::Settings;
+    use crate::themecfg;
+
+    fn formatter() -> RecordFormatter {
+        RecordFormatter::new(
+            Arc::new(Theme::none()),
+            DateTimeFormatter::new(
+                LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                FixedOffset::east(0),
+            ),
+            false,
+            Arc::new(IncludeExcludeKeyFilter::default()),
+        )
+    }
+
+    fn format_with(settings: &Settings, mut formatter: RecordFormatter, data: &str) -> String {
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+        let mut buf = Vec::new();
+        formatter.format_record(&mut buf, &record);
+        String::from_utf8(buf).unwrap()
+    }
+
+    fn format(data: &str) -> String {
+        format_with(&Settings::default(), formatter(), data)
+    }
+
+    #[test]
+    fn test_caller() {
+        assert_eq!(
+            format(r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","caller":"main.go:42"}"#),
+            "Jun 20 00:00:00.000 |(?)| m @ main.go:42\n",
+        );
+    }
+
+    #[test]
+    fn test_caller_function() {
+        let mut settings = Settings::default();
+        settings.fields.predefined.caller.function_names = vec!["func".into()];
+        assert_eq!(
+            format_with(
+                &settings,
+                formatter(),
+                r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","caller":"main.go:42","func":"main"}"#
+            ),
+            "Jun 20 00:00:00.000 |(?)| m @ main (main.go:42)\n",
+        );
+        assert_eq!(
+            format_with(
+                &settings,
+                formatter(),
+                r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","func":"main"}"#
+            ),
+            "Jun 20 00:00:00.000 |(?)| m @ main\n",
+        );
+    }
+
+    #[test]
+    fn test_format_str_unescaped() {
+        let mut buf = Vec::new();
+        format_str_unescaped(&mut buf, r#""some \"quoted\" text""#);
+        assert_eq!(buf, br#"some "quoted" text"#);
+    }
+
+    #[test]
+    fn test_format_str_unescaped_invalid_escape() {
+        let mut buf = b"prefix ".to_vec();
+        format_str_unescaped(&mut buf, r#""bad \x escape""#);
+        assert_eq!(buf, br#"prefix bad \x escape"#);
+    }
+
+    #[test]
+    fn test_format_str_unescaped_unterminated() {
+        let mut buf = Vec::new();
+        format_str_unescaped(&mut buf, r#""unterminated \"#);
+        assert_eq!(buf, br#"unterminated \"#);
+    }
+
+    #[test]
+    fn test_unicode_time_width() {
+        let formatter = RecordFormatter::new(
+            Arc::new(Theme::none()),
+            DateTimeFormatter::new(
+                LinuxDateFormat::new("%m月%d日 %T").compile(),
+                FixedOffset::east(0),
+            ),
+            false,
+            Arc::new(IncludeExcludeKeyFilter::default()),
+        );
+        let settings = Settings::default();
+        assert_eq!(
+            format_with(
+                &settings,
+                formatter,
+                r#"{"ts":"2021-06-20T00:00:00Z","msg":"m"}"#
+            ),
+            "06月20日 00:00:00 |(?)| m\n",
+        );
+        let formatter = RecordFormatter::new(
+            Arc::new(Theme::none()),
+            DateTimeFormatter::new(
+                LinuxDateFormat::new("%m月%d日 %T").compile(),
+                FixedOffset::east(0),
+            ),
+            false,
+            Arc::new(IncludeExcludeKeyFilter::default()),
+        );
+        assert_eq!(
+            format_with(&settings, formatter, r#"{"msg":"m"}"#),
+            "       ---        |(?)| m\n",
+        );
+    }
+
+    #[test]
+    fn test_empty_values() {
+        let data =
+            r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","a":"","b":0,"c":" ","d":false,"e":1}"#;
+        let formatter = || {
+            RecordFormatter::new(
+                Arc::new(Theme::none()),
+                DateTimeFormatter::new(
+                    LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                    FixedOffset::east(0),
+                ),
+                true,
+                Arc::new(IncludeExcludeKeyFilter::default()),
+            )
+        };
+        let settings = Settings::default();
+        assert_eq!(
+            format_with(&settings, formatter(), data),
+            "Jun 20 00:00:00.000 |(?)| m b=0 c=' ' d=false e=1\n",
+        );
+        let mut empty = EmptyValues::default();
+        empty.values.extend(["0".to_string(), "false".to_string()]);
+        empty.blank_strings = true;
+        assert_eq!(
+            format_with(&settings, formatter().with_empty_values(empty), data),
+            "Jun 20 00:00:00.000 |(?)| m e=1\n",
+        );
+    }
+
+    #[test]
+    fn test_nested_empty_values() {
+        let data =
+            r#"{"msg":"m","a":{"b":"","c":null,"d":1},"e":{"f":"","g":{"h":[]}},"i":{"j":{}}}"#;
+        let mut fields = IncludeExcludeKeyFilter::default();
+        fields.entry("a.d").exclude();
+        let formatter = |fields| {
+            RecordFormatter::new(
+                Arc::new(Theme::none()),
+                DateTimeFormatter::new(
+                    LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                    FixedOffset::east(0),
+                ),
+                true,
+                Arc::new(fields),
+            )
+        };
+        let settings = Settings::default();
+        assert_eq!(
+            format_with(
+                &settings,
+                formatter(IncludeExcludeKeyFilter::default()),
+                data
+            ),
+            "        ---         |(?)| m a={ d=1 }\n",
+        );
+        assert_eq!(
+            format_with(&settings, formatter(fields), data),
+            "        ---         |(?)| m a={ ... }\n",
+        );
+        assert_eq!(
+            format_with(
+                &settings,
+                formatter(IncludeExcludeKeyFilter::default()).with_object_flattening(true),
+                data
+            ),
+            "        ---         |(?)| m a.d=1\n",
+        );
+        assert_eq!(
+            format_with(
+                &settings,
+                formatter(IncludeExcludeKeyFilter::default()),
+                r#"{"msg":"m","a":{"b":{"c":{"d":""},"e":{}}},"f":{"g":{"h":null,"i":2}}}"#
+            ),
+            "        ---         |(?)| m f={ g={ i=2 } }\n",
+        );
+    }
+
+    #[test]
+    fn test_key_prefix_stripping() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","app_user_id":1,"app_":2,"app_req":{"app_id":3}}"#;
+        let formatter = formatter().with_key_prefix_stripping(vec!["app_".into()]);
+        assert_eq!(
+            format_with(&Settings::default(), formatter, data),
+            "Jun 20 00:00:00.000 |(?)| m user-id=1 app-=2 req={ id=3 }\n",
+        );
+    }
+
+    #[test]
+    fn test_raw_fields() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","query":"a\tb","req":{"query":"c\td","path":"e\tf"}}"#;
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_raw_fields(["query", "req.path"]),
+                data
+            ),
+            "Jun 20 00:00:00.000 |(?)| m query=\"a\\tb\" req={ query='c\td' path=\"e\\tf\" }\n",
+        );
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter()
+                    .with_object_flattening(true)
+                    .with_raw_fields(["req.query"]),
+                data
+            ),
+            "Jun 20 00:00:00.000 |(?)| m query='a\tb' req.query=\"c\\td\" req.path='e\tf'\n",
+        );
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter()
+                    .with_object_flattening(true)
+                    .with_field_unescaping(false),
+                data
+            ),
+            "Jun 20 00:00:00.000 |(?)| m query=\"a\\tb\" req.query=\"c\\td\" req.path=\"e\\tf\"\n",
+        );
+    }
+
+    #[test]
+    fn test_nested_fields() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","payload":"{\"a\":1,\"b\":\"[2]\"}","req":{"body":" [1,\"x\"] ","text":"{not json"}}"#;
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_nested_fields(["payload", "req.*"]),
+                data
+            ),
+            "Jun 20 00:00:00.000 |(?)| m payload={ a=1 b='[2]' } req={ body=[1,'x'] text='{not json' }\n",
+        );
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_nested_fields(["*"]).with_object_flattening(true),
+                data
+            ),
+            "Jun 20 00:00:00.000 |(?)| m payload.a=1 payload.b=[2] req.body=[1,'x'] req.text='{not json'\n",
+        );
+    }
+
+    #[test]
+    fn test_message_only() {
+        let data =
+            r#"{"ts":"2021-06-20T00:00:00Z","level":"error","msg":"failed to connect","a":1}"#;
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_message_only(true),
+                data
+            ),
+            "failed to connect\n",
+        );
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","a":1}"#;
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_message_only(true),
+                data
+            ),
+            "\n",
+        );
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter()
+                    .with_message_only(true)
+                    .with_missing_message_skipping(true),
+                data
+            ),
+            "",
+        );
+    }
+
+    #[test]
+    fn test_anonymized_fields() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","user_id":"alice","req":{"email":"a@b.c","size":1},"n":42}"#;
+        let anonymizer = Arc::new(Anonymizer::new(Some("salt".into())));
+        let token = |prefix, value: &str| {
+            let mut buf = Vec::new();
+            anonymizer.token(&mut buf, prefix, value.as_bytes());
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter()
+                    .with_anonymized_fields(["user_id", "req.email", "n"], anonymizer.clone()),
+                data
+            ),
+            format!(
+                "Jun 20 00:00:00.000 |(?)| m user-id={} req={{ email={} size=1 }} n={}\n",
+                token("user", "alice"),
+                token("email", "a@b.c"),
+                token("n", "42"),
+            ),
+        );
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter()
+                    .with_object_flattening(true)
+                    .with_anonymized_fields(["req"], anonymizer.clone()),
+                data
+            ),
+            format!(
+                "Jun 20 00:00:00.000 |(?)| m user-id='alice' req={} n=42\n",
+                token("req", r#"{"email":"a@b.c","size":1}"#),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_message_level_highlighting() {
+        let cfg: themecfg::Theme = serde_yaml::from_str(
+            "elements: {message: {foreground: white}}\nlevels: {error: {level-inner: {foreground: red}}, warning: {level: {foreground: yellow}}}",
+        )
+        .unwrap();
+        let formatter = RecordFormatter::new(
+            Arc::new(Theme::from(cfg)),
+            DateTimeFormatter::new(LinuxDateFormat::new("%T").compile(), FixedOffset::east(0)),
+            false,
+            Arc::new(IncludeExcludeKeyFilter::default()),
+        )
+        .with_message_only(true)
+        .with_message_level_highlighting(true);
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter,
+                r#"{"msg":"Retrying after ERROR, no warnings: warn\u001b[1merror"}"#
+            ),
+            "\x1b[0;37mRetrying after \x1b[0;31mERROR\x1b[0;37m, no warnings: \x1b[0;33mwarn\x1b[0;37m\x1b[1merror\x1b[0m\n",
+        );
+    }
+
+    #[test]
+    fn test_message_key_value_highlighting() {
+        let cfg: themecfg::Theme = serde_yaml::from_str(
+            "elements: {message: {foreground: white}, key: {foreground: blue}, string: {foreground: green}, number: {foreground: cyan}}\nlevels: {error: {level: {foreground: red}}}",
+        )
+        .unwrap();
+        let formatter = RecordFormatter::new(
+            Arc::new(Theme::from(cfg)),
+            DateTimeFormatter::new(LinuxDateFormat::new("%T").compile(), FixedOffset::east(0)),
+            false,
+            Arc::new(IncludeExcludeKeyFilter::default()),
+        )
+        .with_message_only(true)
+        .with_message_level_highlighting(true)
+        .with_message_key_value_highlighting(true);
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter,
+                r#"{"msg":"error host=db1 port=5432 q=\"a \\\" b\" a==b c= d"}"#
+            ),
+            concat!(
+                "\x1b[0;37m\x1b[0;31merror\x1b[0;37m \x1b[0;34mhost\x1b[0;37m=\x1b[0;32mdb1\x1b[0;37m ",
+                "\x1b[0;34mport\x1b[0;37m=\x1b[0;36m5432\x1b[0;37m ",
+                "\x1b[0;34mq\x1b[0;37m=\x1b[0;32m\"a \\\" b\"\x1b[0;37m a==b c= d\x1b[0m\n",
+            ),
+        );
+        assert_eq!(key_value_end(b"k=\"unterminated", 1), Some(15));
+        assert_eq!(key_value_end(b"k=", 1), None);
+    }
+
+    #[test]
+    fn test_separator() {
+        let mut buf = Vec::new();
+        format_separator(&Theme::none(), &mut buf, "2021-06-20 14:00", false);
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            format!("─── 2021-06-20 14:00 {}\n", "─".repeat(40)),
+        );
+    }
+
+    #[test]
+    fn test_time_delta() {
+        let delta = |delta| {
+            let mut buf = Vec::new();
+            format_time_delta(&Theme::none(), &mut buf, delta);
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(delta(Some(chrono::Duration::zero())), "  +0.000s ");
+        assert_eq!(
+            delta(Some(chrono::Duration::milliseconds(12))),
+            "  +0.012s "
+        );
+        assert_eq!(
+            delta(Some(chrono::Duration::milliseconds(-61500))),
+            " -61.500s "
+        );
+        assert_eq!(delta(None), "       -- ");
+    }
+
+    #[test]
+    fn test_flattening() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","obj":{"a":1,"b":[2,3]},"tags":["x",{"c":4}]}"#;
+        let test = |objects, arrays| {
+            format_with(
+                &Settings::default(),
+                formatter()
+                    .with_object_flattening(objects)
+                    .with_array_flattening(arrays),
+                data,
+            )
+        };
+        assert_eq!(
+            test(false, false),
+            "Jun 20 00:00:00.000 |(?)| m obj={ a=1 b=[2,3] } tags=['x',{ c=4 }]\n",
+        );
+        assert_eq!(
+            test(true, false),
+            "Jun 20 00:00:00.000 |(?)| m obj.a=1 obj.b=[2,3] tags=['x',{ c=4 }]\n",
+        );
+        assert_eq!(
+            test(false, true),
+            "Jun 20 00:00:00.000 |(?)| m obj={ a=1 b.0=2 b.1=3 } tags.0='x' tags.1={ c=4 }\n",
+        );
+        assert_eq!(
+            test(true, true),
+            "Jun 20 00:00:00.000 |(?)| m obj.a=1 obj.b.0=2 obj.b.1=3 tags.0='x' tags.1.c=4\n",
+        );
+    }
+
+    #[test]
+    fn test_max_fields() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","level":"info","msg":"m","a":1,"b":"","c":3,"d":4,"e":5}"#;
+        let mut fields = IncludeExcludeKeyFilter::default();
+        fields.entry("c").exclude();
+        let hiding = RecordFormatter::new(
+            Arc::new(Theme::none()),
+            DateTimeFormatter::new(
+                LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                FixedOffset::east(0),
+            ),
+            true,
+            Arc::new(fields),
+        );
+        assert_eq!(
+            format_with(&Settings::default(), hiding.with_max_fields(Some(2)), data),
+            "Jun 20 00:00:00.000 |INF| m a=1 d=4 \u{2026}(+1 field) ...\n",
+        );
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_max_fields(Some(1)),
+                data
+            ),
+            "Jun 20 00:00:00.000 |INF| m a=1 \u{2026}(+4 fields)\n",
+        );
+    }
+
+    #[test]
+    fn test_layout() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","level":"info","msg":"m","a":1}"#;
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_layout(Layout::LevelFirst),
+                data
+            ),
+            "|INF| Jun 20 00:00:00.000 m a=1\n",
+        );
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_layout(Layout::LevelFirst),
+                r#"{"msg":"m"}"#
+            ),
+            "|(?)|         ---         m\n",
+        );
+    }
+
+    #[test]
+    fn test_time_zone_field() {
+        let format = |data: &str| {
+            format_with(
+                &Settings::default(),
+                formatter().with_time_zone_field(Some("tz".into())),
+                data,
+            )
+        };
+        assert_eq!(
+            format(r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","tz":"+05:30"}"#),
+            "Jun 20 05:30:00.000 |(?)| m tz='+05:30'\n",
+        );
+        assert_eq!(
+            format(r#"{"ts":"2021-01-20T00:00:00Z","msg":"m","tz":"America/New_York"}"#),
+            "Jan 19 19:00:00.000 |(?)| m tz='America/New_York'\n",
+        );
+        assert_eq!(
+            format(r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","tz":"Mars/Olympus"}"#),
+            "Jun 20 00:00:00.000 |(?)| m tz='Mars/Olympus'\n",
+        );
+        assert_eq!(
+            format(r#"{"ts":"2021-06-20T00:00:00Z","msg":"m"}"#),
+            "Jun 20 00:00:00.000 |(?)| m\n",
+        );
+        let at = Utc.ymd(2021, 6, 20).and_hms(0, 0, 0);
+        assert_eq!(
+            parse_time_zone("-0800", at),
+            FixedOffset::east_opt(-8 * 3600)
+        );
+        assert_eq!(parse_time_zone("+03", at), FixedOffset::east_opt(3 * 3600));
+        assert_eq!(parse_time_zone("Z", at), FixedOffset::east_opt(0));
+        assert_eq!(
+            parse_time_zone("Europe/Berlin", at),
+            FixedOffset::east_opt(7200)
+        );
+        assert_eq!(parse_time_zone("+5:3", at), None);
+        assert_eq!(parse_time_zone("+25:00", at), None);
+    }
+
+    #[test]
+    fn test_field_folding() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut formatter = formatter().with_field_folding(true);
+        let mut format = |data: &str| {
+            let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+            let mut buf = Vec::new();
+            formatter.format_record(&mut buf, &record);
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            format(r#"{"msg":"a","x":1,"y":2}"#),
+            "        ---         |(?)| a x=1 y=2\n"
+        );
+        assert_eq!(
+            format(r#"{"msg":"b","x":1,"y":3}"#),
+            "        ---         |(?)| b y=3\n"
+        );
+        assert_eq!(
+            format(r#"{"msg":"c","x":1,"y":3}"#),
+            "        ---         |(?)| c\n"
+        );
+        assert_eq!(
+            format(r#"{"msg":"d","x":1}"#),
+            "        ---         |(?)| d x=1\n"
+        );
+    }
+
+    #[test]
+    fn test_trailing_reset() {
+        let theme: crate::themecfg::Theme = serde_yaml::from_str(
+            "elements: {message: {foreground: red}, number: {foreground: cyan}}",
+        )
+        .unwrap();
+        let formatter = |trailing_reset| {
+            RecordFormatter::new(
+                Arc::new(Theme::from(&theme)),
+                DateTimeFormatter::new(
+                    LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                    FixedOffset::east(0),
+                ),
+                false,
+                Arc::new(IncludeExcludeKeyFilter::default()),
+            )
+            .with_trailing_reset(trailing_reset)
+        };
+        let data = r#"{"msg":"m","a":1}"#;
+        let output = format_with(&Settings::default(), formatter(true), data);
+        assert_eq!(
+            output,
+            "        ---         |(?)| \x1b[0;31mm \x1b[0ma=\x1b[0;36m1\x1b[0m\n"
+        );
+        let output = format_with(&Settings::default(), formatter(false), data);
+        assert_eq!(
+            output,
+            "        ---         |(?)| \x1b[0;31mm \x1b[0ma=\x1b[0;36m1\n"
+        );
+    }
+
+    #[test]
+    fn test_path_delimiter() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","a.b":1,"a":{"b":2,"c":"x\ty"}}"#;
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter()
+                    .with_object_flattening(true)
+                    .with_path_delimiter(b'/')
+                    .with_raw_fields(["a/c"]),
+                data
+            ),
+            "Jun 20 00:00:00.000 |(?)| m a.b=1 a/b=2 a/c=\"x\\ty\"\n",
+        );
+    }
+
+    #[test]
+    fn test_field_alignment() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let mut formatter = formatter().with_field_alignment(true);
+        let mut format = |data: &str| {
+            let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+            let mut buf = Vec::new();
+            formatter.format_record(&mut buf, &record);
+            String::from_utf8(buf).unwrap()
+        };
+        format(r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","a":"long","b":12345}"#);
+        assert_eq!(
+            format(r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","a":"ü","b":1,"c":{"d":2}}"#),
+            "Jun 20 00:00:00.000 |(?)| m a='ü'    b=1     c={ d=2 }\n",
+        );
+        assert_eq!(
+            format(r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","a":"日本","b":1}"#),
+            "Jun 20 00:00:00.000 |(?)| m a='日本' b=1    \n",
+        );
+    }
+
+    #[test]
+    fn test_secondary_time() {
+        let secondary = || {
+            formatter().with_secondary_time_formatter(Some(DateTimeFormatter::new(
+                LinuxDateFormat::new("%T").compile(),
+                FixedOffset::east(3 * 3600),
+            )))
+        };
+        let settings = Settings::default();
+        assert_eq!(
+            format_with(
+                &settings,
+                secondary(),
+                r#"{"ts":"2021-06-20T00:00:00Z","msg":"m"}"#
+            ),
+            "Jun 20 00:00:00.000 (03:00:00) |(?)| m\n",
+        );
+        assert_eq!(
+            format_with(&settings, secondary(), r#"{"msg":"m"}"#),
+            format!("{:^30} |(?)| m\n", "---"),
+        );
+    }
+
+    #[test]
+    fn test_status_fields() {
+        let formatter =
+            || formatter().with_status_fields(vec!["status".into(), "duration_ms".into()], 30);
+        let settings = Settings::default();
+        assert_eq!(
+            format_with(
+                &settings,
+                formatter(),
+                r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","duration_ms":12,"a":1,"status":200}"#
+            ),
+            "Jun 20 00:00:00.000 |(?)| m a=1     status=200 duration-ms=12\n",
+        );
+        assert_eq!(
+            format_with(
+                &settings,
+                formatter(),
+                r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","a":1}"#
+            ),
+            format!("Jun 20 00:00:00.000 |(?)| m a=1{:30}\n", ""),
+        );
+        let mut fields = IncludeExcludeKeyFilter::default();
+        fields.entry("status").exclude();
+        fields.entry("duration_ms").entry("db").exclude();
+        let filtered = RecordFormatter::new(
+            Arc::new(Theme::none()),
+            DateTimeFormatter::new(
+                LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                FixedOffset::east(0),
+            ),
+            false,
+            Arc::new(fields),
+        )
+        .with_status_fields(vec!["status".into(), "duration_ms".into()], 20);
+        assert_eq!(
+            format_with(
+                &settings,
+                filtered,
+                r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","duration_ms":{"db":3,"total":12},"status":200}"#
+            ),
+            "Jun 20 00:00:00.000 |(?)| m duration-ms={ total=12 ... }\n",
+        );
+    }
+
+    #[test]
+    fn test_field_sorting() {
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_field_sorting(true),
+                r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","c":1,"a":2,"b":3}"#
+            ),
+            "Jun 20 00:00:00.000 |(?)| m a=2 b=3 c=1\n",
+        );
+    }
+
+    #[test]
+    fn test_ellipsis() {
+        let mut fields = IncludeExcludeKeyFilter::default();
+        fields.entry("b").exclude();
+        fields.entry("c").entry("d").exclude();
+        let fields = Arc::new(fields);
+        let formatter = |ellipsis: Option<&str>| {
+            RecordFormatter::new(
+                Arc::new(Theme::none()),
+                DateTimeFormatter::new(
+                    LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                    FixedOffset::east(0),
+                ),
+                false,
+                fields.clone(),
+            )
+            .with_ellipsis(ellipsis.map(|x| x.into()))
+        };
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","a":1,"b":2,"c":{"d":3,"e":4}}"#;
+        let settings = Settings::default();
+        assert_eq!(
+            format_with(&settings, formatter(Some("...")), data),
+            "Jun 20 00:00:00.000 |(?)| m a=1 c={ e=4 ... } ...\n",
+        );
+        assert_eq!(
+            format_with(&settings, formatter(Some("⋯")), data),
+            "Jun 20 00:00:00.000 |(?)| m a=1 c={ e=4 ⋯ } ⋯\n",
+        );
+        assert_eq!(
+            format_with(&settings, formatter(None), data),
+            "Jun 20 00:00:00.000 |(?)| m a=1 c={ e=4 }\n",
+        );
+    }
+
+    #[test]
+    fn test_max_value_len() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"long message","a":"abcdef","b":"ab","c":{"d":"äöüßéè"},"e":123456}"#;
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_max_value_len(Some(3)),
+                data
+            ),
+            "Jun 20 00:00:00.000 |(?)| long message a='abc…' b='ab' c={ d='äöü…' } e=123456\n",
+        );
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter()
+                    .with_max_value_len(Some(3))
+                    .with_raw_fields(["a"]),
+                data
+            ),
+            "Jun 20 00:00:00.000 |(?)| long message a=\"abcdef\" b='ab' c={ d='äöü…' } e=123456\n",
+        );
+    }
+
+    #[test]
+    fn test_ascii() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"héllo 😀","a":"äöüß","c":"x"}"#;
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_ascii(true).with_max_value_len(Some(2)),
+                data
+            ),
+            "Jun 20 00:00:00.000 |(?)| h\\u00e9llo \\ud83d\\ude00 a='\\u00e4\\u00f6...' c='x'\n",
+        );
+        assert_eq!(
+            format_with(
+                &Settings::default(),
+                formatter().with_ascii(true),
+                r#"{"ts":"2021-06-20T00:00:00Z","msg":[200,65]}"#
+            ),
+            "Jun 20 00:00:00.000 |(?)| b'\\c8A'\n",
+        );
+        let mut buf = Vec::new();
+        format_separator(&Theme::none(), &mut buf, "14:00", true);
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            format!("--- 14:00 {}\n", "-".repeat(40))
+        );
+    }
+
+    #[test]
+    fn test_hidden_count() {
+        let mut fields = IncludeExcludeKeyFilter::default();
+        fields.entry("b").exclude();
+        fields.entry("x").exclude();
+        fields.entry("c").entry("d").exclude();
+        let formatter = RecordFormatter::new(
+            Arc::new(Theme::none()),
+            DateTimeFormatter::new(
+                LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                FixedOffset::east(0),
+            ),
+            false,
+            Arc::new(fields),
+        )
+        .with_hidden_count(true);
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","a":1,"b":2,"c":{"d":3,"e":4},"x":5}"#;
+        assert_eq!(
+            format_with(&Settings::default(), formatter, data),
+            "Jun 20 00:00:00.000 |(?)| m a=1 c={ e=4 (+1 hidden) } (+2 hidden)\n",
+        );
+        let mut fields = IncludeExcludeKeyFilter::default();
+        fields.entry("b").exclude();
+        fields.entry("x").exclude();
+        let formatter = RecordFormatter::new(
+            Arc::new(Theme::none()),
+            DateTimeFormatter::new(
+                LinuxDateFormat::new("%b %d %T.%3N").compile(),
+                FixedOffset::east(0),
+            ),
+            true,
+            Arc::new(fields),
+        )
+        .with_hidden_count(true);
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":"m","a":1,"b":{"c":null},"x":""}"#;
+        assert_eq!(
+            format_with(&Settings::default(), formatter, data),
+            "Jun 20 00:00:00.000 |(?)| m a=1 (+1 hidden)\n",
+        );
+    }
+
+    #[test]
+    fn test_null_text() {
+        let data = r#"{"ts":"2021-06-20T00:00:00Z","msg":null,"a":null,"b":{"c":null}}"#;
+        let settings = Settings::default();
+        assert_eq!(
+            format(data),
+            "Jun 20 00:00:00.000 |(?)| null a=null b={ c=null }\n",
+        );
+        assert_eq!(
+            format_with(
+                &settings,
+                formatter().with_null_text(Some("∅".into())),
+                data
+            ),
+            "Jun 20 00:00:00.000 |(?)| ∅ a=∅ b={ c=∅ }\n",
+        );
+    }
+}
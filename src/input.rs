@@ -1,8 +1,8 @@
 use std::fs::File;
-use std::io::{BufReader, Error, Read, Result};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
-use ansi_term::Colour;
 use flate2::bufread::GzDecoder;
 
 pub type InputStream = Box<dyn Read + Send + Sync>;
@@ -15,22 +15,194 @@ pub struct Input {
 pub struct ConcatReader<I> {
     iter: I,
     item: Option<Input>,
+    offset: u64,
+    map: Option<Arc<InputMap>>,
+}
+
+/// Names of concatenated inputs with start offsets recorded by [`ConcatReader`] as the inputs are opened,
+/// so that offsets in the concatenated data can be mapped back to the inputs.
+pub struct InputMap {
+    names: Vec<String>,
+    starts: RwLock<Vec<u64>>,
 }
 
 pub fn open(path: &PathBuf) -> Result<Input> {
-    let name = format!("file '{}'", Colour::Yellow.paint(path.to_string_lossy()),);
+    let name = format!("file '{}'", path.to_string_lossy());
 
-    let f = File::open(path)
-        .map_err(|e| Error::new(e.kind(), format!("failed to open {}: {}", name, e)))?;
+    let failed = |e: Error| Error::new(e.kind(), format!("failed to open {}: {}", name, e));
+    let mut reader = BufReader::new(File::open(path).map_err(failed)?);
 
-    let stream: InputStream = match path.extension().map(|x| x.to_str()) {
-        Some(Some("gz")) => Box::new(GzDecoder::new(BufReader::new(f))),
-        _ => Box::new(f),
+    let gzipped = match path.extension().map(|x| x.to_str()) {
+        Some(Some("gz")) => true,
+        _ => is_gzip(reader.fill_buf().map_err(failed)?),
+    };
+    let stream: InputStream = if gzipped {
+        Box::new(GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
     };
 
     Ok(Input::new(name, stream))
 }
 
+/// Opens standard input, decompressing it if it starts with gzip magic bytes.
+pub fn open_stdin() -> Result<Input> {
+    open_stream("<stdin>".into(), std::io::stdin())
+}
+
+/// Wraps the stream into an input, decompressing it if it starts with gzip magic bytes.
+/// The stream is not read until the input is read, so opening never blocks.
+pub fn open_stream<R: Read + Send + Sync + 'static>(name: String, stream: R) -> Result<Input> {
+    Ok(Input::new(name, Box::new(GzipDetector::new(stream))))
+}
+
+/// Returns true if the given data starts with gzip magic bytes.
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+/// Returns true if the given argument looks like an http or https URL.
+pub fn is_url(arg: &str) -> bool {
+    arg.starts_with("http://") || arg.starts_with("https://")
+}
+
+/// Opens a stream reading the body of the resource at the given http or https URL.
+///
+/// Body is decompressed if the resource has `.gz` extension or gzip content type.
+/// Responses with status other than 200 are reported as errors.
+#[cfg(feature = "http")]
+pub fn open_url(url: &str) -> Result<Input> {
+    let name = format!("url '{}'", url);
+    let failed = |e: String| Error::other(format!("failed to open {}: {}", name, e));
+
+    let response = match ureq::get(url).call() {
+        Ok(response) if response.status() == 200 => response,
+        Ok(response) => {
+            return Err(failed(format!(
+                "unexpected status {} {}",
+                response.status(),
+                response.status_text()
+            )))
+        }
+        Err(ureq::Error::Status(status, response)) => {
+            return Err(failed(format!(
+                "unexpected status {} {}",
+                status,
+                response.status_text()
+            )))
+        }
+        Err(e) => return Err(failed(e.to_string())),
+    };
+
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let gzipped = path.ends_with(".gz")
+        || matches!(
+            response.content_type(),
+            "application/gzip" | "application/x-gzip"
+        );
+    let body = response.into_reader();
+    let stream: InputStream = if gzipped {
+        Box::new(GzDecoder::new(BufReader::new(body)))
+    } else {
+        Box::new(body)
+    };
+
+    Ok(Input::new(name, stream))
+}
+
+/// Fails to open the resource at the given URL because http support is not compiled in.
+#[cfg(not(feature = "http"))]
+pub fn open_url(url: &str) -> Result<Input> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        format!(
+            "failed to open url '{}': http support is not compiled in",
+            url
+        ),
+    ))
+}
+
+/// Discovers rotated siblings of the given log file and returns them ordered oldest-first.
+///
+/// Supported naming conventions for a log file `<name>` are
+/// * `<name>.<N>` - rotated file with sequence number N,
+/// * `<name>.<N>.gz` - gzipped rotated file with sequence number N.
+///
+/// Files with greater sequence numbers are considered to be older,
+/// so the result is ordered by sequence number descending followed by the live file itself.
+/// The live file is included only if it exists.
+pub fn rotated(path: &Path) -> Result<Vec<PathBuf>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid file name '{}'", path.to_string_lossy()),
+            )
+        })?;
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+
+    let mut siblings = Vec::new();
+    for entry in dir.read_dir()? {
+        let entry = entry?;
+        if let Some(index) = entry
+            .file_name()
+            .to_str()
+            .and_then(|x| rotation_index(name, x))
+        {
+            siblings.push((index, path.with_file_name(entry.file_name())));
+        }
+    }
+    siblings.sort_by_key(|x| std::cmp::Reverse(x.0));
+
+    let mut result: Vec<_> = siblings.into_iter().map(|(_, path)| path).collect();
+    if path.exists() || result.is_empty() {
+        result.push(path.into());
+    }
+    Ok(result)
+}
+
+/// Reads a list of paths separated by the given delimiter, i.e. `\n` or `\0`, skipping empty items.
+/// Trailing carriage returns are stripped from newline-separated items.
+pub fn read_file_list<R: BufRead>(reader: R, delimiter: u8) -> Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    for item in reader.split(delimiter) {
+        let item = item?;
+        let item = match item.strip_suffix(b"\r") {
+            Some(stripped) if delimiter == b'\n' => stripped,
+            _ => &item[..],
+        };
+        if !item.is_empty() {
+            result.push(path_from_bytes(item));
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::OsStr::from_bytes(bytes).into()
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    String::from_utf8_lossy(bytes).into_owned().into()
+}
+
+fn rotation_index(name: &str, candidate: &str) -> Option<usize> {
+    let suffix = candidate.strip_prefix(name)?.strip_prefix('.')?;
+    let suffix = suffix.strip_suffix(".gz").unwrap_or(suffix);
+    if suffix.is_empty() || !suffix.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    suffix.parse().ok()
+}
+
 impl Input {
     pub fn new(name: String, stream: InputStream) -> Self {
         Self { name, stream }
@@ -39,7 +211,18 @@ impl Input {
 
 impl<I> ConcatReader<I> {
     pub fn new(iter: I) -> Self {
-        Self { iter, item: None }
+        Self {
+            iter,
+            item: None,
+            offset: 0,
+            map: None,
+        }
+    }
+
+    /// Records start offsets of the inputs in the given map, the map has to list names of all inputs in order.
+    pub fn with_input_map(mut self, map: Option<Arc<InputMap>>) -> Self {
+        self.map = map;
+        self
     }
 }
 
@@ -56,6 +239,9 @@ where
                     }
                     Some(result) => {
                         self.item = Some(result?);
+                        if let Some(map) = &self.map {
+                            map.starts.write().unwrap().push(self.offset);
+                        }
                     }
                 };
             }
@@ -65,9 +251,279 @@ where
                 Error::new(e.kind(), format!("failed to read {}: {}", input.name, e))
             })?;
             if n != 0 {
+                self.offset += n as u64;
                 return Ok(n);
             }
             self.item = None;
         }
     }
 }
+
+impl InputMap {
+    pub fn new(names: Vec<String>) -> Self {
+        Self {
+            names,
+            starts: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Returns the name of the input containing the given offset of the concatenated data and the offset within the input.
+    pub fn locate(&self, offset: u64) -> Option<(&str, u64)> {
+        let starts = self.starts.read().unwrap();
+        // empty inputs start at the same offset as the next ones, so the last matching input is taken
+        let i = starts
+            .partition_point(|&start| start <= offset)
+            .checked_sub(1)?;
+        Some((self.names.get(i)?, offset - starts[i]))
+    }
+}
+
+// ---
+
+/// Reader that returns at most one line per read call, so that each line is delivered as soon as it is available.
+pub struct LineReader<R> {
+    inner: R,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+}
+
+impl<R: BufRead> Read for LineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let data = self.inner.fill_buf()?;
+        let end = match data.iter().position(|&b| b == b'\n') {
+            Some(pos) => pos + 1,
+            None => data.len(),
+        };
+        let n = std::cmp::min(end, buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.inner.consume(n);
+        Ok(n)
+    }
+}
+
+// ---
+
+/// Reader that checks for gzip magic bytes on the first read and decompresses the stream if they are found.
+pub struct GzipDetector<R> {
+    inner: Option<R>,
+    head: Vec<u8>,
+    stream: Option<InputStream>,
+}
+
+impl<R: Read + Send + Sync + 'static> GzipDetector<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: Some(inner),
+            head: Vec::with_capacity(GZIP_MAGIC.len()),
+            stream: None,
+        }
+    }
+
+    fn detect(&mut self) -> Result<&mut InputStream> {
+        if self.stream.is_none() {
+            let inner = self.inner.as_mut().unwrap();
+            // short reads are continued, so that the magic bytes are checked as a whole
+            while self.head.len() < GZIP_MAGIC.len() {
+                let mut buf = [0; GZIP_MAGIC.len()];
+                let n = inner.read(&mut buf[self.head.len()..])?;
+                if n == 0 {
+                    break;
+                }
+                self.head
+                    .extend_from_slice(&buf[self.head.len()..self.head.len() + n]);
+            }
+            let gzipped = is_gzip(&self.head);
+            let head = std::io::Cursor::new(std::mem::take(&mut self.head));
+            let stream = head.chain(self.inner.take().unwrap());
+            self.stream = Some(if gzipped {
+                Box::new(GzDecoder::new(BufReader::new(stream)))
+            } else {
+                Box::new(stream)
+            });
+        }
+        Ok(self.stream.as_mut().unwrap())
+    }
+}
+
+impl<R: Read + Send + Sync + 'static> Read for GzipDetector<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.detect()?.read(buf)
+    }
+}
+
+// ---
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    #[test]
+    fn test_open_gzip_by_content() {
+        let path = std::env::temp_dir().join(format!("hl-test-gzip-{}.log", std::process::id()));
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"{\"msg\":\"hello\"}\n").unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+        let mut data = Vec::new();
+        let result = open(&path).and_then(|mut input| input.stream.read_to_end(&mut data));
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(data, b"{\"msg\":\"hello\"}\n");
+    }
+
+    #[test]
+    fn test_open_error_is_plain() {
+        let err = open(&PathBuf::from("/nonexistent/hl-test.log"))
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .starts_with("failed to open file '/nonexistent/hl-test.log': "));
+        assert!(!err.to_string().contains('\x1b'));
+    }
+
+    #[test]
+    fn test_open_gzip_stream() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"{\"msg\":\"hello\"}\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+        for stream in [compressed, b"{\"msg\":\"hello\"}\n".to_vec()] {
+            let mut data = Vec::new();
+            let mut input = open_stream("<stream>".into(), std::io::Cursor::new(stream)).unwrap();
+            input.stream.read_to_end(&mut data).unwrap();
+            assert_eq!(data, b"{\"msg\":\"hello\"}\n");
+        }
+    }
+
+    #[test]
+    fn test_open_stream_lazily() {
+        struct Trickle(Vec<u8>, Arc<RwLock<usize>>);
+        impl Read for Trickle {
+            fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+                *self.1.write().unwrap() += 1;
+                if self.0.is_empty() || buf.is_empty() {
+                    return Ok(0);
+                }
+                buf[0] = self.0.remove(0);
+                Ok(1)
+            }
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"{\"msg\":\"hello\"}\n").unwrap();
+        let reads = Arc::new(RwLock::new(0));
+        let stream = Trickle(encoder.finish().unwrap(), reads.clone());
+        let mut input = open_stream("<stream>".into(), stream).unwrap();
+        assert_eq!(*reads.read().unwrap(), 0);
+        let mut data = Vec::new();
+        input.stream.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"{\"msg\":\"hello\"}\n");
+    }
+
+    #[test]
+    fn test_input_map() {
+        let input = |name: &str, data: &'static [u8]| Ok(Input::new(name.into(), Box::new(data)));
+        let map = Arc::new(InputMap::new(vec!["a".into(), "b".into(), "c".into()]));
+        let inputs = vec![
+            input("a", b"1\n22\n"),
+            input("b", b""),
+            input("c", b"333\n"),
+        ];
+        let mut reader = ConcatReader::new(inputs.into_iter()).with_input_map(Some(map.clone()));
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"1\n22\n333\n");
+        assert_eq!(map.locate(0), Some(("a", 0)));
+        assert_eq!(map.locate(2), Some(("a", 2)));
+        assert_eq!(map.locate(5), Some(("c", 0)));
+        assert_eq!(map.locate(7), Some(("c", 2)));
+    }
+
+    #[test]
+    fn test_line_reader() {
+        let mut reader = LineReader::new(&b"line 1\nline 2\nrest"[..]);
+        let mut buf = [0; 16];
+        assert_eq!(reader.read(&mut buf).unwrap(), 7);
+        assert_eq!(&buf[..7], b"line 1\n");
+        assert_eq!(reader.read(&mut buf[..4]).unwrap(), 4);
+        assert_eq!(&buf[..4], b"line");
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b" 2\n");
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], b"rest");
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_read_file_list() {
+        let list = read_file_list(&b"a.log\r\n\nb c.log.gz\n"[..], b'\n').unwrap();
+        assert_eq!(
+            list,
+            vec![PathBuf::from("a.log"), PathBuf::from("b c.log.gz")]
+        );
+        let list = read_file_list(&b"a\nb.log\0c.log\0"[..], 0).unwrap();
+        assert_eq!(
+            list,
+            vec![PathBuf::from("a\nb.log"), PathBuf::from("c.log")]
+        );
+    }
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.com/app.log.gz"));
+        assert!(is_url("http://localhost:8080/app.log"));
+        assert!(!is_url("app.log"));
+        assert!(!is_url("ftp://example.com/app.log"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "http"))]
+    fn test_open_url_unsupported() {
+        let err = open_url("http://localhost/app.log").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_rotation_index() {
+        assert_eq!(rotation_index("app.log", "app.log"), None);
+        assert_eq!(rotation_index("app.log", "app.log.1"), Some(1));
+        assert_eq!(rotation_index("app.log", "app.log.12.gz"), Some(12));
+        assert_eq!(rotation_index("app.log", "app.log.gz"), None);
+        assert_eq!(rotation_index("app.log", "app.log.1.bak"), None);
+        assert_eq!(rotation_index("app.log", "app.logx.1"), None);
+        assert_eq!(rotation_index("app.log", "other.log.1"), None);
+    }
+
+    #[test]
+    fn test_rotated() {
+        let dir = std::env::temp_dir().join(format!("hl-test-rotated-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in &[
+            "app.log",
+            "app.log.1",
+            "app.log.2.gz",
+            "app.log.10",
+            "other.log.3",
+        ] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let result = rotated(&dir.join("app.log"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            result.unwrap(),
+            vec![
+                dir.join("app.log.10"),
+                dir.join("app.log.2.gz"),
+                dir.join("app.log.1"),
+                dir.join("app.log"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,200 @@
+// std imports
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+// third-party imports
+use serde_json as json;
+
+// local imports
+use crate::model::{Level, Record};
+
+// ---
+
+/// Sends records to systemd journal using its native protocol.
+///
+/// Field names are sanitized to satisfy journald requirements:
+/// * ASCII letters are converted to upper case,
+/// * any character other than a letter, a digit or `_` is replaced with `_`,
+/// * leading underscores are removed since such fields are reserved for trusted fields,
+/// * names starting with a digit are prefixed with `F_`.
+///
+/// Each entry is sent as a single datagram, so entries exceeding the socket's
+/// maximum datagram size are not delivered.
+pub struct Journal {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl Journal {
+    /// Returns a new Journal connected to the default journald socket.
+    pub fn new() -> io::Result<Self> {
+        Self::with_path(SOCKET_PATH)
+    }
+
+    /// Returns a new Journal sending entries to the socket at the given path.
+    pub fn with_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            socket: UnixDatagram::unbound()?,
+            path: path.as_ref().into(),
+        })
+    }
+
+    /// Sends the entry serialized by [`serialize`] to the journal.
+    pub fn send(&self, entry: &[u8]) -> io::Result<()> {
+        self.socket.send_to(entry, &self.path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "failed to send entry to journal at '{}': {}",
+                    self.path.display(),
+                    e
+                ),
+            )
+        })?;
+        Ok(())
+    }
+}
+
+// ---
+
+/// Returns syslog priority corresponding to the given level.
+pub fn priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warning => 4,
+        Level::Info => 6,
+        Level::Debug => 7,
+    }
+}
+
+/// Serializes the given record to a journal entry using native protocol format.
+pub fn serialize(buf: &mut Vec<u8>, record: &Record) {
+    if let Some(message) = record.message {
+        put(buf, b"MESSAGE", &unescaped(message.get()));
+    }
+    if let Some(level) = record.level {
+        put(buf, b"PRIORITY", priority(level).to_string().as_bytes());
+    }
+    if let Some(ts) = &record.ts {
+        put(buf, b"SYSLOG_TIMESTAMP", ts.raw().as_bytes());
+    }
+    if let Some(logger) = record.logger {
+        put(buf, b"SYSLOG_IDENTIFIER", logger.as_bytes());
+    }
+    if let Some(caller) = record.caller {
+        put(buf, b"CODE_LINE", caller.as_bytes());
+    }
+    if let Some(function) = record.caller_function {
+        put(buf, b"CODE_FUNC", function.as_bytes());
+    }
+    let mut name = Vec::new();
+    for (key, value) in record.fields() {
+        if sanitize(&mut name, key) {
+            put(buf, &name, &unescaped(value.get()));
+        }
+    }
+}
+
+/// Converts the given key to a valid journal field name.
+/// Returns false if the key does not contain any usable characters.
+pub fn sanitize(buf: &mut Vec<u8>, key: &str) -> bool {
+    buf.clear();
+    for &b in key.as_bytes() {
+        let b = if b.is_ascii_alphanumeric() {
+            b.to_ascii_uppercase()
+        } else {
+            b'_'
+        };
+        if b == b'_' && buf.is_empty() {
+            continue;
+        }
+        if b.is_ascii_digit() && buf.is_empty() {
+            buf.extend_from_slice(b"F_");
+        }
+        buf.push(b);
+    }
+    buf.truncate(MAX_FIELD_NAME_LEN);
+    !buf.is_empty()
+}
+
+// ---
+
+fn put(buf: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    buf.extend_from_slice(name);
+    if value.contains(&b'\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value);
+    buf.push(b'\n');
+}
+
+fn unescaped(value: &str) -> Vec<u8> {
+    if value.starts_with('"') {
+        if let Ok(value) = json::from_str::<String>(value) {
+            return value.into_bytes();
+        }
+    }
+    value.as_bytes().into()
+}
+
+// ---
+
+const SOCKET_PATH: &str = "/run/systemd/journal/socket";
+const MAX_FIELD_NAME_LEN: usize = 64;
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::{Parser, ParserSettings, RawRecord};
+    use crate::settings::Settings;
+
+    #[test]
+    fn test_sanitize() {
+        let mut buf = Vec::new();
+        assert!(sanitize(&mut buf, "request-id"));
+        assert_eq!(buf, b"REQUEST_ID");
+        assert!(sanitize(&mut buf, "_private.key"));
+        assert_eq!(buf, b"PRIVATE_KEY");
+        assert!(sanitize(&mut buf, "2fa"));
+        assert_eq!(buf, b"F_2FA");
+        assert!(!sanitize(&mut buf, "__"));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let data = r#"{"level":"warning","msg":"two\nlines","user-id":42}"#;
+        let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+        let mut buf = Vec::new();
+        serialize(&mut buf, &record);
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"two\nlines\nPRIORITY=4\nUSER_ID=42\n");
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn test_send() {
+        let path =
+            std::env::temp_dir().join(format!("hl-test-journal-{}.sock", std::process::id()));
+        let socket = UnixDatagram::bind(&path).unwrap();
+        let journal = Journal::with_path(&path).unwrap();
+        journal.send(b"MESSAGE=first\n").unwrap();
+        journal.send(b"MESSAGE=second\n").unwrap();
+        let mut buf = [0; 64];
+        let n = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"MESSAGE=first\n");
+        let n = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"MESSAGE=second\n");
+        std::fs::remove_file(&path).unwrap();
+        assert!(journal.send(b"MESSAGE=lost\n").is_err());
+    }
+}
@@ -1,16 +1,27 @@
 // public modules
+pub mod anonymize;
 pub mod app;
+pub mod csvfmt;
 pub mod datefmt;
+pub mod envelope;
 pub mod error;
+pub mod fieldstats;
 pub mod fmtx;
 pub mod input;
+pub mod metrics;
 pub mod output;
+pub mod profile;
+pub mod ratelimit;
 pub mod settings;
+pub mod syslog;
+pub mod tee;
+pub mod termbg;
 pub mod theme;
 pub mod themecfg;
 pub mod timeparse;
 pub mod timestamp;
 pub mod types;
+pub mod weblog;
 
 // private modules
 mod console;
@@ -26,14 +37,21 @@ mod scanning;
 #[cfg_attr(windows, path = "signal_windows.rs")]
 pub mod signal;
 
+#[cfg(all(target_os = "linux", feature = "journald"))]
+pub mod journald;
+
 // public uses
-pub use app::{App, FieldOptions, Options, SegmentProcesor};
+pub use app::{App, FieldOptions, InputFormat, Options, OutputFormat, SegmentProcesor, SplitBy};
 pub use datefmt::{DateTimeFormatter, LinuxDateFormat};
 pub use filtering::DefaultNormalizing;
-pub use formatting::RecordFormatter;
-pub use model::{FieldFilterSet, Filter, Level, Parser, ParserSettings};
+pub use formatting::{Layout, RecordFormatter};
+pub use model::{
+    DuplicateKeys, FieldFilterSet, Filter, Level, LevelMapping, LevelMode, Parser, ParserSettings,
+    RawRecord, Record, TimeFormat,
+};
 pub use settings::Settings;
 pub use theme::Theme;
+pub use types::{SampleRate, TimeBucket};
 
 // public uses (platform-specific)
 pub use console::enable_ansi_support;
@@ -1,12 +1,14 @@
 // std imports
 use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::io::{BufWriter, LineWriter, Write};
 use std::path::PathBuf;
 use std::process;
 use std::sync::Arc;
 
 // third-party imports
 use ansi_term::Colour;
-use chrono::{FixedOffset, Local, TimeZone};
+use chrono::{DateTime, FixedOffset, Local, TimeZone};
 use chrono_tz::{Tz, UTC};
 use itertools::Itertools;
 use once_cell::sync::Lazy;
@@ -17,26 +19,40 @@ use structopt::{
 };
 
 // local imports
+use hl::anonymize::Anonymizer;
 use hl::datefmt::LinuxDateFormat;
 use hl::error::*;
-use hl::input::{open, ConcatReader, Input, InputStream};
-use hl::output::{OutputStream, Pager};
+use hl::input::{
+    is_url, open, open_stdin, open_url, read_file_list, rotated, ConcatReader, Input, InputMap,
+    InputStream,
+};
+use hl::output::{self, Finish, OutputStream, Pager, RotatingWriter, SplitWriter};
+use hl::ratelimit::RateOverflow;
 use hl::settings::Settings;
 use hl::signal::SignalHandler;
+use hl::tee::TeeReader;
+use hl::termbg::{self, Background};
 use hl::theme::{Theme, ThemeOrigin};
-use hl::timeparse::parse_time;
+use hl::themecfg;
+use hl::timeparse::{parse_time, SpanTime};
 use hl::Level;
 use hl::{IncludeExcludeKeyFilter, KeyMatchOptions};
 
 // ---
 
 const APP_NAME: &str = "hl";
+const DARK_THEME: &str = "one-dark-green";
+const LIGHT_THEME: &str = "classic-light";
+const STABLE_TIME_FORMAT: &str = "%Y-%m-%dT%T.%NZ";
+const EXPLAIN_SAMPLE_SIZE: usize = 100;
+const TERMINAL_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(100);
+const LINE_BUFFER_SIZE: usize = 64 * 1024;
 
 // ---
 
 /// JSON log converter to human readable representation.
 #[derive(StructOpt)]
-#[structopt(setting(ColorAuto), setting(ColoredHelp))]
+#[structopt(setting(ColorAuto), setting(ColoredHelp), setting(AllArgsOverrideSelf))]
 struct Opt {
     /// Color output options, one of { auto, always, never }.
     #[structopt(
@@ -65,7 +81,7 @@ struct Opt {
     paging_never: bool,
     //
     //
-    /// Color theme.
+    /// Color theme, use 'auto' to choose a dark or light theme based on the terminal background.
     #[structopt(
         long,
         default_value = &CONFIG.theme,
@@ -74,10 +90,291 @@ struct Opt {
     )]
     theme: String,
     //
+    /// Maximum color depth of the output, one of { 16, 256, truecolor }, colors of the theme are approximated to fit it.
+    #[structopt(long, possible_values = &["16", "256", "truecolor"], env = "HL_COLOR_DEPTH")]
+    color_depth: Option<themecfg::ColorDepth>,
+    //
+    /// Do not use bold and faint text styles of the theme, which may wash out colors on some terminals.
+    #[structopt(long)]
+    no_bold: bool,
+    //
+    /// Do not reset styles at the end of each record, so the style of the last element is kept across the newline.
+    #[structopt(long)]
+    no_trailing_reset: bool,
+    //
     /// Disable unescaping and prettifying of field values.
     #[structopt(short, long)]
     raw_fields: bool,
     //
+    /// Disable unescaping of values for the field with the specified key, nested keys are separated by a dot.
+    #[structopt(long, number_of_values = 1)]
+    raw_field: Vec<String>,
+    //
+    /// Render string values of the field with the specified key as structured data if they contain JSON objects or arrays,
+    /// nested keys are separated by a dot and may contain wildcards, i.e. '*.payload'.
+    #[structopt(long, number_of_values = 1)]
+    parse_nested: Vec<String>,
+    //
+    /// Replace values of the fields with the specified comma-separated keys with tokens derived from their salted hashes,
+    /// equal values are replaced with equal tokens, nested keys are separated by a dot.
+    /// Supported with human readable output only.
+    #[structopt(long, conflicts_with_all = &["debug-raw", "explain", "explain-all"])]
+    anonymize: Option<String>,
+    //
+    /// Salt for '--anonymize' making tokens reproducible across runs, a random salt is used by default.
+    #[structopt(long)]
+    anon_salt: Option<String>,
+    //
+    /// Render nested object fields as separate fields with dotted keys.
+    #[structopt(long)]
+    flatten: bool,
+    //
+    /// Render array items as separate fields with indexed keys.
+    #[structopt(long)]
+    flatten_arrays: bool,
+    //
+    /// Delimiter of keys in paths of nested fields used in filters, field visibility options and flattened keys.
+    #[structopt(long, default_value = ".", parse(try_from_str = parse_delimiter))]
+    path_delimiter: u8,
+    //
+    /// Pad field values to align them in columns within each buffered segment of input.
+    /// Segments are formatted twice, which makes output slower.
+    #[structopt(long)]
+    align_fields: bool,
+    //
+    /// Comma-separated keys of fields to render in a right-aligned status column at the end of the line.
+    #[structopt(long)]
+    status_fields: Option<String>,
+    //
+    /// Width of the status column.
+    #[structopt(long, default_value = "20")]
+    status_width: usize,
+    //
+    /// Text to show in place of hidden fields.
+    #[structopt(long, default_value = "...")]
+    ellipsis: String,
+    //
+    /// Do not show anything in place of hidden fields.
+    #[structopt(long)]
+    no_ellipsis: bool,
+    //
+    /// Show the number of hidden fields as '(+N hidden)' instead of the ellipsis.
+    #[structopt(long)]
+    show_hidden_count: bool,
+    //
+    /// Truncate string field values longer than the given number of characters, fields listed in '--raw-field' are not truncated.
+    #[structopt(long)]
+    max_value_len: Option<usize>,
+    //
+    /// Truncate lines exceeding '--max-message-size' to the given number of bytes instead of passing them through.
+    #[structopt(long, parse(try_from_str = parse_size))]
+    max_line_len: Option<usize>,
+    //
+    /// Stop after writing the given amount of output, only complete lines are written.
+    #[structopt(long, parse(try_from_str = parse_size))]
+    max_output_bytes: Option<usize>,
+    //
+    /// Use ASCII replacements for decorative glyphs and escape non-ASCII characters as '\uXXXX'.
+    #[structopt(long)]
+    ascii: bool,
+    //
+    /// Highlight level words like 'error' or 'warning' in message text with colors of the corresponding levels.
+    #[structopt(long)]
+    highlight_levels_in_message: bool,
+    //
+    /// Highlight 'key=value' pairs in message text with colors of field keys and values, without extracting them as fields.
+    /// Keys consist of letters, digits and underscores, values end at whitespace unless they are double-quoted.
+    #[structopt(long)]
+    highlight_kv: bool,
+    //
+    /// Show only message text of records, without time, level, logger, fields and caller.
+    #[structopt(long)]
+    message_only: bool,
+    //
+    /// Skip records having no message in '--message-only' mode instead of showing empty lines.
+    #[structopt(long, requires = "message-only")]
+    skip_missing_messages: bool,
+    //
+    /// Text to show in place of null values, i.e. '∅' or '-'.
+    #[structopt(long)]
+    null_text: Option<String>,
+    //
+    /// Maximum number of fields to show per record, the number of omitted fields is shown instead of the rest.
+    /// Time, level, message, logger and caller are not counted.
+    #[structopt(long)]
+    max_fields: Option<usize>,
+    //
+    /// Infer level from a leading token of the message, like '[ERROR]' or 'WARN:', for records having no level field.
+    #[structopt(long)]
+    infer_level_from_message: bool,
+    //
+    /// Compute level of records having no level field from the numeric status code in the specified field, i.e. 'status'.
+    /// Status ranges are configured by 'fields.status-levels' setting, by default 1xx-3xx are info, 4xx are warning and 5xx are error.
+    #[structopt(long)]
+    level_from: Option<String>,
+    //
+    /// Handling of keys occurring more than once in a record, one of { first, last, all }.
+    /// 'all' renders each occurrence, 'last' keeps the last value at the position of the first occurrence.
+    /// Predefined fields like time, level and message always use a single occurrence, the first one with 'first' and the last one otherwise.
+    #[structopt(long, default_value = "all", overrides_with = "duplicate-keys")]
+    duplicate_keys: DuplicateKeysOption,
+    //
+    /// Additional level value mappings in form <value>=<level>[,<value>=<level>...], i.e. 'critical=error,notice=info'.
+    #[structopt(long, number_of_values = 1)]
+    level_map: Vec<String>,
+    //
+    /// Detect the time field and its format from the first records if none of the configured time fields is present.
+    #[structopt(long)]
+    auto_time: bool,
+    //
+    /// Parse time field values using the given strftime-like pattern instead of format auto-detection,
+    /// i.e. '%d/%b/%Y:%H:%M:%S %z'. Values without a timezone are interpreted as UTC.
+    #[structopt(long, overrides_with = "time-input-format")]
+    time_input_format: Option<String>,
+    //
+    /// Print diagnostic messages to stderr.
+    #[structopt(long)]
+    verbose: bool,
+    //
+    /// Comma-separated names of the time field overriding the configured ones.
+    #[structopt(long)]
+    time_field: Option<String>,
+    //
+    /// Comma-separated names of the level field overriding the configured ones.
+    #[structopt(long)]
+    level_field: Option<String>,
+    //
+    /// Comma-separated names of the message field overriding the configured ones.
+    #[structopt(long)]
+    message_field: Option<String>,
+    //
+    /// Print Prometheus-style metrics about the input records instead of the records themselves.
+    #[structopt(long)]
+    metrics: bool,
+    //
+    /// Print the number of records matching the filters instead of the records themselves.
+    /// The whole input is scanned.
+    #[structopt(long)]
+    count: bool,
+    //
+    /// Print only a sample of records matching the filters, i.e. '1/100' or '0.01'.
+    #[structopt(long)]
+    sample: Option<hl::SampleRate>,
+    //
+    /// Print at most the given number of records per second, i.e. to keep up with a fast live stream.
+    #[structopt(long, parse(try_from_str = parse_non_zero_rate))]
+    max_rate: Option<u32>,
+    //
+    /// Action on records exceeding '--max-rate', one of { queue, drop }.
+    /// Dropped records are counted and the count is printed to stderr at the end.
+    #[structopt(long, default_value = "queue", overrides_with = "rate-overflow")]
+    rate_overflow: RateOverflowOption,
+    //
+    /// Print the union of field keys with observed value types and frequency instead of the records themselves.
+    /// Uses JSON lines output if '--output-format json' is specified.
+    #[structopt(long)]
+    list_fields: bool,
+    //
+    /// Check that records are sorted chronologically instead of printing them.
+    /// Reports the first out-of-order record and exits with non-zero status if the input is not sorted.
+    #[structopt(long, conflicts_with = "list-fields")]
+    check_sorted: bool,
+    //
+    /// Maximum number of records to sample for '--list-fields'.
+    #[structopt(long, default_value = "10000")]
+    sample_size: usize,
+    //
+    /// Print to stderr which filter predicates passed or failed for a sample of records.
+    #[structopt(long)]
+    explain: bool,
+    //
+    /// Same as '--explain' but for all records.
+    #[structopt(long)]
+    explain_all: bool,
+    //
+    /// Print the raw source of each record on the next line after the formatted record, doubling the output volume.
+    /// Intended for debugging of formatting issues.
+    #[structopt(long)]
+    debug_raw: bool,
+    //
+    /// Prefix each record with its byte offset in the input, like 'grep -b'.
+    /// Offsets are counted within each input, prefixed with the input name if there are multiple inputs.
+    #[structopt(long)]
+    byte_offset: bool,
+    //
+    /// Print records in reverse order, newest first for chronologically ordered input.
+    /// Keeps the whole output in memory until the end of input.
+    #[structopt(long)]
+    reverse: bool,
+    //
+    /// Remove the specified prefix from rendered field keys, i.e. 'app_'.
+    #[structopt(long, number_of_values = 1)]
+    strip_key_prefix: Vec<String>,
+    //
+    /// Allow omitting prefixes specified by '--strip-key-prefix' in field filter keys.
+    #[structopt(long)]
+    strip_affects_filters: bool,
+    //
+    /// Print time spent in reading, processing and writing stages along with processed data volume to stderr on completion.
+    /// Stage times are aggregated over all threads of the stage.
+    #[structopt(long)]
+    profile: bool,
+    //
+    /// Print a faint rule line with the time bucket label when the timestamp of the next record crosses
+    /// a minute, hour or day boundary in the display time zone, one of { minute, hour, day }.
+    #[structopt(long)]
+    time_separator: Option<TimeSeparatorOption>,
+    //
+    /// Prefix each record with the time elapsed since the previous record having a timestamp, like '+0.012s'.
+    /// Records without a timestamp show '--'.
+    #[structopt(long)]
+    time_delta: bool,
+    //
+    /// Text placed before each line of formatted records, outside of their styles, escapes '\t', '\n' and '\\' are supported.
+    #[structopt(long, default_value = "", hide_default_value = true, parse(from_str = parse_escapes))]
+    line_prefix: String,
+    //
+    /// Text placed after each line of formatted records, outside of their styles, escapes '\t', '\n' and '\\' are supported.
+    #[structopt(long, default_value = "", hide_default_value = true, parse(from_str = parse_escapes))]
+    line_suffix: String,
+    //
+    /// Hide fields having the same values as in the previous record, showing only what changed.
+    /// All fields are shown again when the set of field keys changes. Forces single-threaded processing.
+    #[structopt(long)]
+    fold_fields: bool,
+    //
+    /// Order of the time and level columns, one of { time-first, level-first }.
+    #[structopt(long, default_value = "time-first", possible_values = &["time-first", "level-first"], overrides_with = "layout")]
+    layout: hl::Layout,
+    //
+    /// Flush output after each line even if it is not a terminal, useful for interactive piping.
+    #[structopt(long)]
+    line_buffered: bool,
+    //
+    /// Write records with the '--split-threshold' level or a more severe one to stderr and all other output to stdout.
+    #[structopt(long, conflicts_with_all = &["list-fields", "check-sorted"])]
+    split_levels: bool,
+    //
+    /// Least severe level written to stderr with '--split-levels', one of { d[ebug], i[nfo], w[arning], e[rror] }.
+    #[structopt(long, default_value = "warning", overrides_with = "split-threshold")]
+    split_threshold: Level,
+    //
+    /// Write records to files in '--split-dir' named by the record level, i.e. 'error.log',
+    /// or by the value of the top-level field with the specified key, lines that are not records are written to the output.
+    /// Values that are not valid file names get a hash suffix, records without the value go to 'unknown.log'.
+    /// At most 512 files can be written.
+    #[structopt(long, requires = "split-dir", conflicts_with_all = &["split-levels", "reverse", "list-fields", "check-sorted"])]
+    split_by: Option<String>,
+    //
+    /// Directory for files written with '--split-by', created if it does not exist. Colors are disabled by default.
+    #[structopt(long, requires = "split-by")]
+    split_dir: Option<PathBuf>,
+    //
+    /// Produce byte-stable output for identical inputs, recommended for snapshot tests.
+    /// Disables colors, sorts fields by key and uses RFC3339 timestamps in UTC.
+    #[structopt(long)]
+    stable: bool,
+    //
     /// Number of interrupts to ignore, i.e. Ctrl-C (SIGINT).
     #[structopt(
         long,
@@ -87,9 +384,13 @@ struct Opt {
     )]
     interrupt_ignore_count: usize,
     //
-    /// Buffer size.
-    #[structopt(long, default_value = "2 MiB", env="HL_BUFFER_SIZE", overrides_with = "buffer-size", parse(try_from_str = parse_non_zero_size))]
-    buffer_size: usize,
+    /// Buffer size, or 'line' to process and flush each line separately for minimal latency at the cost of much lower throughput.
+    #[structopt(long, default_value = "2 MiB", env="HL_BUFFER_SIZE", overrides_with = "buffer-size", parse(try_from_str = parse_buffer_size))]
+    buffer_size: BufferSize,
+    //
+    /// Process and flush input line by line for live piping of interactive programs, same as '--buffer-size line'.
+    #[structopt(long, alias = "stdin-interactive")]
+    unbuffered: bool,
     //
     /// Maximum message size.
     #[structopt(long, default_value = "64 MiB", env="HL_MAX_MESSAGE_SIZE", overrides_with = "max-message-size", parse(try_from_str = parse_non_zero_size))]
@@ -108,6 +409,14 @@ struct Opt {
     #[structopt(short, long, number_of_values = 1)]
     filter: Vec<String>,
     //
+    /// Filtering by a substring of the unescaped message, combined with other filters.
+    #[structopt(long, short = "g", number_of_values = 1)]
+    grep: Vec<String>,
+    //
+    /// Filtering by a regular expression matched against the unescaped message, combined with other filters.
+    #[structopt(long, number_of_values = 1)]
+    grep_re: Vec<String>,
+    //
     /// Hide fields with the specified keys.
     #[structopt(long, short = "h", number_of_values = 1)]
     hide: Vec<String>,
@@ -120,18 +429,30 @@ struct Opt {
     #[structopt(long, short = "u", number_of_values = 1)]
     unhide: Vec<String>,
     //
-    /// Filtering by level, one of { d[ebug], i[nfo], w[arning], e[rror] }.
+    /// Filtering by level, one of { d[ebug], i[nfo], w[arning], e[rror] }, overrides min-level from config.
     #[structopt(short, long, env = "HL_LEVEL", overrides_with = "level")]
     level: Option<Level>,
     //
+    /// How '--level' is compared with record levels, one of { min, exact, max },
+    /// min shows the level and more severe ones, max shows the level and less severe ones.
+    #[structopt(long, default_value = "min", overrides_with = "level-mode")]
+    level_mode: LevelModeOption,
+    //
     /// Filtering by timestamp >= the value (--time-zone and --local options are honored).
+    /// Values like '-10%', '+10%', 'start+1h' or 'end-1h' are relative to the time range of the input files.
     #[structopt(long, allow_hyphen_values = true)]
     since: Option<String>,
     //
     /// Filtering by timestamp <= the value (--time-zone and --local options are honored).
+    /// Values like '-10%', '+10%', 'start+1h' or 'end-1h' are relative to the time range of the input files.
     #[structopt(long, allow_hyphen_values = true)]
     until: Option<String>,
     //
+    /// Drop records with timestamps within the range in form <start>..<end>, bounds are inclusive and use the same format as '--since'.
+    /// Overlapping ranges are merged, records without a timestamp are kept.
+    #[structopt(long, number_of_values = 1, allow_hyphen_values = true)]
+    exclude_range: Vec<String>,
+    //
     /// Time format, see https://man7.org/linux/man-pages/man1/date.1.html.
     #[structopt(
         short,
@@ -150,18 +471,96 @@ struct Opt {
     #[structopt(long, short = "L")]
     local: bool,
     //
-    /// Files to process
+    /// Secondary time zone name, time of day in this zone is shown in parentheses after the primary timestamp.
+    #[structopt(long)]
+    time_zone_secondary: Option<Tz>,
+    //
+    /// Field with a UTC offset like '+05:30' or a time zone name used to display the timestamp of each record,
+    /// records without the field or with an unrecognized value use '--time-zone'.
+    #[structopt(long)]
+    timezone_from_field: Option<String>,
+    //
+    /// Write output to the file instead of stdout, output is compressed with gzip if the file has '.gz' extension.
+    #[structopt(long, short = "o", parse(from_os_str))]
+    output: Option<PathBuf>,
+    //
+    /// Rotate the output file once it exceeds the size, renaming it to <file>.1 and older files to <file>.2 and so on.
+    /// Rotated compressed files keep the '.gz' extension, i.e. 'out.log.1.gz'.
+    #[structopt(long, requires = "output", parse(try_from_str = parse_non_zero_size))]
+    rotate_size: Option<usize>,
+    //
+    /// Write a copy of the complete input to the file regardless of filtering, compressed inputs are copied decompressed.
+    /// The copy is compressed if the file has '.gz' extension.
+    #[structopt(long, parse(from_os_str))]
+    tee: Option<PathBuf>,
+    //
+    /// Maximum number of input files to open in advance, files beyond the limit are opened one at a time while reading.
+    #[structopt(long, default_value = "256")]
+    max_open_files: usize,
+    //
+    /// Files to process, http and https URLs are supported if built with http feature
     #[structopt(name = "FILE", parse(from_os_str))]
     files: Vec<PathBuf>,
     //
+    /// Also process rotated siblings of each file, i.e. <FILE>.<N> and <FILE>.<N>.gz, ordered oldest-first.
+    #[structopt(long)]
+    rotated: bool,
+    //
+    /// Read newline-separated list of files to process from the file, '-' means stdin.
+    #[structopt(long, parse(from_os_str), conflicts_with = "files-from0")]
+    files_from: Option<PathBuf>,
+    //
+    /// Read NUL-separated list of files to process from the file, '-' means stdin.
+    #[structopt(long, parse(from_os_str))]
+    files_from0: Option<PathBuf>,
+    //
     /// Hide empty fields, applies for null, string, object and array fields only.
-    #[structopt(long, short = "e", env = "HL_HIDE_EMPTY_FIELDS")]
+    #[structopt(long, short = "e", env = "HL_HIDE_EMPTY_FIELDS", takes_value = false)]
     hide_empty_fields: bool,
     //
     /// Show empty fields, overrides --hide-empty-fields option.
-    #[structopt(long, short = "E", env = "HL_SHOW_EMPTY_FIELDS")]
+    #[structopt(long, short = "E", env = "HL_SHOW_EMPTY_FIELDS", takes_value = false)]
     show_empty_fields: bool,
     //
+    /// Suppress lines that cannot be parsed as records, even if no filter is specified.
+    #[structopt(long, short = "q")]
+    quiet: bool,
+    //
+    /// Show lines that cannot be parsed along with the reason, even if a filter is specified.
+    #[structopt(long)]
+    show_parse_errors: bool,
+    //
+    /// Input format, one of { json, syslog, cef, apache, w3c }, syslog stands for RFC 5424 messages,
+    /// apache stands for Apache combined or common log format and w3c stands for W3C Extended Log Format.
+    /// Lines in formats other than json are converted to JSON records, so the JSON output shows converted records.
+    /// Level of apache and w3c records is computed from the status code unless '--level-from' is specified.
+    /// The w3c format forces single-threaded processing.
+    #[structopt(long, default_value = "json", overrides_with = "input-format")]
+    input_format: InputFormatOption,
+    //
+    /// Unwrap JSON records embedded as a string in the given field, i.e. 'log' for Docker and Kubernetes logs.
+    /// The outer time is used if the inner record has none, inner strings that are not JSON are shown as messages.
+    #[structopt(long)]
+    unwrap: Option<String>,
+    //
+    /// Output format, one of { human, json, csv, journald }, journald is available on Linux only.
+    #[structopt(
+        long,
+        default_value = "human",
+        env = "HL_OUTPUT_FORMAT",
+        overrides_with = "output-format"
+    )]
+    output_format: OutputFormatOption,
+    //
+    /// Normalize timestamps to RFC3339 UTC, applies to json output format only.
+    #[structopt(long)]
+    normalize_time: bool,
+    //
+    /// Comma-separated list of fields to output as columns, applies to csv output format only.
+    /// Nested fields can be selected using dotted paths, i.e. 'ts,level,msg,req.duration'.
+    #[structopt(long, default_value = "ts,level,msg")]
+    only: String,
+    //
     /// List available themes and exit.
     #[structopt(long)]
     list_themes: bool,
@@ -185,6 +584,68 @@ arg_enum! {
     }
 }
 
+arg_enum! {
+    #[derive(Debug)]
+    enum LevelModeOption {
+        Min,
+        Exact,
+        Max,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug)]
+    enum DuplicateKeysOption {
+        First,
+        Last,
+        All,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug)]
+    enum RateOverflowOption {
+        Queue,
+        Drop,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, PartialEq)]
+    enum InputFormatOption {
+        Json,
+        Syslog,
+        Cef,
+        Apache,
+        W3c,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, PartialEq)]
+    enum OutputFormatOption {
+        Human,
+        Json,
+        Csv,
+        Journald,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug)]
+    enum TimeSeparatorOption {
+        Minute,
+        Hour,
+        Day,
+    }
+}
+
+#[derive(Debug)]
+enum BufferSize {
+    Line,
+    Bytes(usize),
+}
+
 // ---
 
 static CONFIG: Lazy<Settings> = Lazy::new(|| load_config());
@@ -208,6 +669,126 @@ fn parse_size(s: &str) -> Result<usize> {
     }
 }
 
+fn fixed_offset(tz: Tz) -> FixedOffset {
+    let offset = UTC.ymd(1970, 1, 1).and_hms(0, 0, 0) - tz.ymd(1970, 1, 1).and_hms(0, 0, 0);
+    FixedOffset::east(offset.num_seconds() as i32)
+}
+
+fn parse_names(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.into())
+        .collect()
+}
+
+fn parse_escapes(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('\\') => result.push('\\'),
+            Some(c) => {
+                result.push('\\');
+                result.push(c);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Returns true if the environment variable is set to a value other than an empty string, '0', 'false' or 'no'.
+/// Used for flags, which cannot take their values from environment variables without taking values on the command line too.
+fn env_flag(name: &str) -> bool {
+    match std::env::var(name) {
+        Ok(value) => !matches!(value.to_lowercase().as_str(), "" | "0" | "false" | "no"),
+        Err(_) => false,
+    }
+}
+
+fn parse_delimiter(s: &str) -> Result<u8> {
+    match s.as_bytes() {
+        &[b] if b.is_ascii() => Ok(b),
+        _ => Err(Error::InvalidPathDelimiter(s.into())),
+    }
+}
+
+/// Returns command line arguments with arguments from HL_OPTS environment variable inserted
+/// right after the program name, so explicit arguments take precedence.
+fn args() -> Result<Vec<OsString>> {
+    args_with_opts(std::env::args_os().collect(), std::env::var_os("HL_OPTS"))
+}
+
+/// Inserts the options right after the program name, repeated arguments override previous ones
+/// with the exception of arguments that may be specified multiple times.
+fn args_with_opts(mut args: Vec<OsString>, opts: Option<OsString>) -> Result<Vec<OsString>> {
+    if let Some(opts) = opts {
+        let opts = opts.to_string_lossy();
+        let opts = shellwords::split(&opts)
+            .map_err(|e| Error::InvalidEnvironmentVariable("HL_OPTS".into(), e.to_string()))?;
+        let n = args.len().min(1);
+        args.splice(n..n, opts.into_iter().map(OsString::from));
+    }
+    Ok(args)
+}
+
+fn open_input(path: &PathBuf) -> std::io::Result<Input> {
+    match path.to_str() {
+        Some("-") => open_stdin(),
+        Some(url) if is_url(url) => open_url(url),
+        _ => open(path),
+    }
+}
+
+/// Concatenates the inputs opening the first `max_open` of them in advance to report errors early,
+/// the rest are opened one at a time as the previous ones are consumed.
+fn concat_inputs<F>(
+    files: Vec<PathBuf>,
+    max_open: usize,
+    mut open: F,
+) -> std::io::Result<ConcatReader<impl Iterator<Item = std::io::Result<Input>>>>
+where
+    F: FnMut(&PathBuf) -> std::io::Result<Input>,
+{
+    let mut files = files.into_iter();
+    let opened = files
+        .by_ref()
+        .take(max_open)
+        .map(|x| open(&x))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    Ok(ConcatReader::new(
+        opened
+            .into_iter()
+            .map(Ok)
+            .chain(files.map(move |x| open(&x))),
+    ))
+}
+
+/// Returns the buffer size limited by the maximum message size and whether input is processed line by line.
+fn buffering(buffer_size: &BufferSize, unbuffered: bool, max_message_size: usize) -> (usize, bool) {
+    let (buffer_size, line_mode) = match buffer_size {
+        _ if unbuffered => (LINE_BUFFER_SIZE, true),
+        BufferSize::Line => (LINE_BUFFER_SIZE, true),
+        BufferSize::Bytes(size) => (*size, false),
+    };
+    (std::cmp::min(max_message_size, buffer_size), line_mode)
+}
+
+fn parse_buffer_size(s: &str) -> Result<BufferSize> {
+    if s == "line" {
+        Ok(BufferSize::Line)
+    } else {
+        parse_non_zero_size(s).map(BufferSize::Bytes)
+    }
+}
+
 fn parse_non_zero_size(s: &str) -> Result<usize> {
     let value = parse_size(s)?;
     if value == 0 {
@@ -217,13 +798,21 @@ fn parse_non_zero_size(s: &str) -> Result<usize> {
     }
 }
 
+fn parse_non_zero_rate(s: &str) -> Result<u32> {
+    match s.parse()? {
+        0 => Err(Error::ZeroRate),
+        value => Ok(value),
+    }
+}
+
 // ---
 
 fn run() -> Result<()> {
     let app_dirs = AppDirs::new(Some("hl"), true).unwrap();
-    let settings = Settings::load(&app_dirs)?;
-    let opt = Opt::from_args();
-    let stdout_is_atty = || atty::is(atty::Stream::Stdout);
+    let mut settings = Settings::load(&app_dirs)?;
+    let opt = Opt::from_iter(args()?);
+    let to_file = opt.output.is_some() || opt.split_dir.is_some();
+    let stdout_is_atty = || !to_file && atty::is(atty::Stream::Stdout);
     let color_supported = if stdout_is_atty() {
         if let Err(err) = hl::enable_ansi_support() {
             eprintln!("failed to enable ansi support: {}", err);
@@ -242,13 +831,34 @@ fn run() -> Result<()> {
         opt.color
     };
     let use_colors = match color {
+        _ if opt.stable => false,
         ColorOption::Auto => stdout_is_atty() && color_supported,
         ColorOption::Always => true,
         ColorOption::Never => false,
     };
     let theme = if use_colors {
-        let theme = &opt.theme;
-        Theme::load(&app_dirs, theme)?
+        let theme = match opt.theme.as_str() {
+            "auto" => {
+                let background = if stdout_is_atty() {
+                    termbg::detect(TERMINAL_QUERY_TIMEOUT)
+                } else {
+                    termbg::from_env()
+                };
+                match background {
+                    Some(Background::Light) => LIGHT_THEME,
+                    _ => DARK_THEME,
+                }
+            }
+            theme => theme,
+        };
+        let mut theme = themecfg::Theme::load(&app_dirs, theme)?;
+        if let Some(depth) = opt.color_depth {
+            theme = theme.with_color_depth(depth);
+        }
+        if opt.no_bold {
+            theme = theme.without_modes(&[themecfg::Mode::Bold, themecfg::Mode::Faint]);
+        }
+        Theme::from(theme)
     } else {
         Theme::none()
     };
@@ -272,39 +882,97 @@ fn run() -> Result<()> {
 
     // Configure concurrency.
     let concurrency = match opt.concurrency.or(settings.concurrency) {
+        // folding compares each record with the previous one, so records must be formatted in order
+        _ if opt.fold_fields => 1,
+        // w3c '#Fields' directives apply to all following lines, so lines must be processed in order
+        _ if opt.input_format == InputFormatOption::W3c => 1,
         None | Some(0) => num_cpus::get(),
         Some(value) => value,
     };
     // Configure timezone.
-    let tz = if opt.local {
+    let tz = if opt.stable {
+        FixedOffset::east(0)
+    } else if opt.local {
         *Local.timestamp(0, 0).offset()
     } else {
-        let tz = opt.time_zone;
-        let offset = UTC.ymd(1970, 1, 1).and_hms(0, 0, 0) - tz.ymd(1970, 1, 1).and_hms(0, 0, 0);
-        FixedOffset::east(offset.num_seconds() as i32)
+        fixed_offset(opt.time_zone)
     };
+    let secondary_tz = opt.time_zone_secondary.map(fixed_offset);
     // Configure time format.
-    let time_format = LinuxDateFormat::new(&opt.time_format).compile();
+    let time_format = if opt.stable {
+        STABLE_TIME_FORMAT
+    } else {
+        &opt.time_format
+    };
+    let time_format = LinuxDateFormat::new(time_format).compile();
+    // Times relative to the time range of the input are resolved after scanning the input files.
+    let since_span = opt.since.as_deref().and_then(SpanTime::parse);
+    let until_span = opt.until.as_deref().and_then(SpanTime::parse);
     // Configure filter.
-    let filter = hl::Filter {
-        fields: hl::FieldFilterSet::new(opt.filter)?,
-        level: opt.level,
-        since: if let Some(v) = &opt.since {
-            Some(parse_time(v, &tz, &time_format)?.into())
-        } else {
-            None
+    let mut fields = hl::FieldFilterSet::new(opt.filter)?.with_path_delimiter(opt.path_delimiter);
+    for pattern in &opt.grep {
+        fields = fields.with_message_substring(pattern);
+    }
+    for pattern in &opt.grep_re {
+        fields = fields.with_message_regex(pattern)?;
+    }
+    let mut filter = hl::Filter {
+        fields,
+        level: opt.level.or(settings.min_level),
+        level_mode: match opt.level_mode {
+            LevelModeOption::Min => hl::LevelMode::Min,
+            LevelModeOption::Exact => hl::LevelMode::Exact,
+            LevelModeOption::Max => hl::LevelMode::Max,
+        },
+        since: match &opt.since {
+            Some(v) if since_span.is_none() => Some(parse_time(v, &tz, &time_format)?.into()),
+            _ => None,
         },
-        until: if let Some(v) = &opt.until {
-            Some(parse_time(v, &tz, &time_format)?.into())
+        until: match &opt.until {
+            Some(v) if until_span.is_none() => Some(parse_time(v, &tz, &time_format)?.into()),
+            _ => None,
+        },
+        excluded: Vec::new(),
+        key_prefixes: if opt.strip_affects_filters {
+            opt.strip_key_prefix.clone()
         } else {
-            None
+            Vec::new()
         },
     };
+    for range in &opt.exclude_range {
+        let (start, end) = match range.split_once("..") {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => return Err(Error::WrongTimeRange(range.clone())),
+        };
+        let start = parse_time(start, &tz, &time_format)?.into();
+        let end = parse_time(end, &tz, &time_format)?.into();
+        if start > end {
+            return Err(Error::WrongTimeRange(range.clone()));
+        }
+        filter = filter.with_excluded_range(start, end);
+    }
     // Configure hide_empty_fields
-    let hide_empty_fields = !opt.show_empty_fields && opt.hide_empty_fields;
+    let show_empty_fields = opt.show_empty_fields || env_flag("HL_SHOW_EMPTY_FIELDS");
+    let hide_empty_fields =
+        !show_empty_fields && (opt.hide_empty_fields || env_flag("HL_HIDE_EMPTY_FIELDS"));
+
+    // Configure predefined field names.
+    let predefined = &mut settings.fields.predefined;
+    if let Some(names) = &opt.time_field {
+        predefined.set_time_names(parse_names(names));
+    }
+    if let Some(names) = &opt.level_field {
+        predefined.set_level_names(parse_names(names));
+    }
+    if let Some(names) = &opt.message_field {
+        predefined.set_message_names(parse_names(names));
+    }
 
     // Configure field filter.
-    let mut fields = IncludeExcludeKeyFilter::new(KeyMatchOptions::default());
+    let mut fields = IncludeExcludeKeyFilter::new(KeyMatchOptions {
+        delimiter: opt.path_delimiter,
+        ..KeyMatchOptions::default()
+    });
     if opt.hide.len() == 0 && opt.show.len() != 0 {
         fields.exclude();
     }
@@ -316,14 +984,49 @@ fn run() -> Result<()> {
     }
 
     let max_message_size = opt.max_message_size;
-    let buffer_size = std::cmp::min(max_message_size, opt.buffer_size);
+    let (buffer_size, line_mode) = buffering(&opt.buffer_size, opt.unbuffered, max_message_size);
+
+    // Anonymization is applied by the human readable formatter only, other formats would show original values.
+    if opt.anonymize.is_some() && opt.output_format != OutputFormatOption::Human {
+        return Err(Error::AnonymizationNotSupported);
+    }
+    // Configure output format.
+    let output_format = match opt.output_format {
+        OutputFormatOption::Human => hl::OutputFormat::Human,
+        OutputFormatOption::Json => hl::OutputFormat::Json,
+        OutputFormatOption::Csv => hl::OutputFormat::Csv,
+        #[cfg(all(target_os = "linux", feature = "journald"))]
+        OutputFormatOption::Journald => hl::OutputFormat::Journald,
+        #[cfg(not(all(target_os = "linux", feature = "journald")))]
+        OutputFormatOption::Journald => {
+            return Err(Error::UnsupportedOutputFormat("journald".into()));
+        }
+    };
+
+    // Configure input format.
+    let input_format = match opt.input_format {
+        InputFormatOption::Json => hl::InputFormat::Json,
+        InputFormatOption::Syslog => hl::InputFormat::Syslog,
+        InputFormatOption::Cef => hl::InputFormat::Cef,
+        InputFormatOption::Apache => hl::InputFormat::Apache,
+        InputFormatOption::W3c => hl::InputFormat::W3c,
+    };
 
     // Create app.
-    let app = hl::App::new(hl::Options {
+    let mut app = hl::App::new(hl::Options {
         theme: Arc::new(theme),
         raw_fields: opt.raw_fields,
+        raw_field_keys: opt.raw_field,
+        nested_fields: opt.parse_nested,
+        anonymized_fields: opt
+            .anonymize
+            .as_deref()
+            .map(parse_names)
+            .unwrap_or_default(),
+        anonymizer: Arc::new(Anonymizer::new(opt.anon_salt)),
         time_format: time_format,
         buffer_size,
+        line_mode,
         max_message_size,
         concurrency,
         filter,
@@ -332,26 +1035,173 @@ fn run() -> Result<()> {
             filter: Arc::new(fields),
         },
         time_zone: tz,
+        secondary_time_zone: secondary_tz,
+        time_zone_field: opt.timezone_from_field,
         hide_empty_fields,
+        quiet: opt.quiet,
+        show_parse_errors: opt.show_parse_errors,
+        input_format,
+        unwrap: opt.unwrap,
+        output_format,
+        normalize_time: opt.normalize_time,
+        flatten_objects: opt.flatten,
+        flatten_arrays: opt.flatten_arrays,
+        path_delimiter: opt.path_delimiter,
+        align_fields: opt.align_fields,
+        sort_fields: opt.stable,
+        ellipsis: if opt.no_ellipsis {
+            None
+        } else {
+            Some(opt.ellipsis)
+        },
+        null_text: opt.null_text,
+        show_hidden_count: opt.show_hidden_count,
+        max_value_len: opt.max_value_len,
+        max_line_len: opt.max_line_len,
+        max_output_bytes: opt.max_output_bytes,
+        ascii: opt.ascii,
+        highlight_levels_in_message: opt.highlight_levels_in_message,
+        highlight_kv: opt.highlight_kv,
+        message_only: opt.message_only,
+        skip_missing_messages: opt.skip_missing_messages,
+        max_fields: opt.max_fields,
+        trailing_reset: !opt.no_trailing_reset,
+        status_fields: opt
+            .status_fields
+            .as_deref()
+            .map(parse_names)
+            .unwrap_or_default(),
+        status_width: opt.status_width,
+        infer_level: opt.infer_level_from_message,
+        status_field: opt.level_from.or_else(|| match input_format {
+            hl::InputFormat::Apache | hl::InputFormat::W3c => Some("status".into()),
+            _ => None,
+        }),
+        duplicate_keys: match opt.duplicate_keys {
+            DuplicateKeysOption::First => hl::DuplicateKeys::First,
+            DuplicateKeysOption::Last => hl::DuplicateKeys::Last,
+            DuplicateKeysOption::All => hl::DuplicateKeys::All,
+        },
+        auto_time: opt.auto_time,
+        time_input_format: opt.time_input_format,
+        verbose: opt.verbose,
+        level_mapping: hl::LevelMapping::new(opt.level_map)?,
+        metrics: opt.metrics,
+        count: opt.count,
+        sample: opt.sample,
+        max_rate: opt.max_rate,
+        rate_overflow: match opt.rate_overflow {
+            RateOverflowOption::Queue => RateOverflow::Queue,
+            RateOverflowOption::Drop => RateOverflow::Drop,
+        },
+        explain: if opt.explain_all {
+            Some(usize::MAX)
+        } else if opt.explain {
+            Some(EXPLAIN_SAMPLE_SIZE)
+        } else {
+            None
+        },
+        debug_raw: opt.debug_raw,
+        byte_offsets: opt.byte_offset,
+        reverse: opt.reverse,
+        strip_key_prefixes: opt.strip_key_prefix,
+        csv_columns: parse_names(&opt.only),
+        profile: opt.profile,
+        time_separator: opt.time_separator.map(|x| match x {
+            TimeSeparatorOption::Minute => hl::TimeBucket::Minute,
+            TimeSeparatorOption::Hour => hl::TimeBucket::Hour,
+            TimeSeparatorOption::Day => hl::TimeBucket::Day,
+        }),
+        split_level: opt.split_threshold,
+        split_by: match opt.split_by.as_deref() {
+            Some("level") | None => hl::SplitBy::Level,
+            Some(key) => hl::SplitBy::Field(key.into()),
+        },
+        time_delta: opt.time_delta,
+        line_prefix: opt.line_prefix,
+        line_suffix: opt.line_suffix,
+        fold_fields: opt.fold_fields,
+        layout: opt.layout,
     });
 
     // Configure input.
-    let inputs = opt
-        .files
-        .iter()
-        .map(|x| {
-            if x.to_str() == Some("-") {
-                Ok(Input::new("<stdin>".into(), Box::new(std::io::stdin())))
+    let mut files = opt.files.clone();
+    let file_list = match (&opt.files_from, &opt.files_from0) {
+        (Some(path), _) => Some((path, b'\n')),
+        (None, Some(path)) => Some((path, b'\0')),
+        (None, None) => None,
+    };
+    if let Some((path, delimiter)) = file_list {
+        if path.to_str() == Some("-") {
+            if files.iter().any(|x| x.to_str() == Some("-")) {
+                return Err(Error::StdinUsedTwice);
+            }
+            files.extend(read_file_list(std::io::stdin().lock(), delimiter)?);
+        } else {
+            let failed = |e: std::io::Error| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!(
+                        "failed to read list of files from '{}': {}",
+                        path.display(),
+                        e
+                    ),
+                )
+            };
+            let reader = std::io::BufReader::new(std::fs::File::open(path).map_err(failed)?);
+            files.extend(read_file_list(reader, delimiter).map_err(failed)?);
+        }
+    }
+    if opt.rotated {
+        let mut expanded = Vec::new();
+        for file in &files {
+            if file.to_str() == Some("-") || file.to_str().map(is_url) == Some(true) {
+                expanded.push(file.clone());
             } else {
-                open(&x)
+                expanded.extend(rotated(file)?);
             }
-        })
-        .collect::<std::io::Result<Vec<_>>>()?;
-    let mut input: InputStream = if inputs.len() == 0 {
-        Box::new(std::io::stdin())
+        }
+        files = expanded;
+    }
+    // Resolve times relative to the time range of the input by scanning the input files in advance.
+    if since_span.is_some() || until_span.is_some() {
+        if files.len() == 0 || files.iter().any(|x| x.to_str() == Some("-")) {
+            return Err(Error::RelativeTimeRequiresFiles);
+        }
+        let mut range: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> = None;
+        for file in &files {
+            if let Some((min, max)) = app.time_range(open_input(file)?.stream.as_mut())? {
+                range = Some(match range {
+                    Some((lo, hi)) => (lo.min(min), hi.max(max)),
+                    None => (min, max),
+                });
+            }
+        }
+        let (min, max) = range.ok_or(Error::RelativeTimeWithoutTimestamps)?;
+        app = app.with_time_filter(
+            since_span.map(|x| x.resolve(min, max).into()),
+            until_span.map(|x| x.resolve(min, max).into()),
+        );
+    }
+    // Byte offsets and unsorted lines are reported within each input, prefixed with its name if there are multiple inputs.
+    let input_map = if (opt.byte_offset || opt.check_sorted) && files.len() > 1 {
+        let names = files.iter().map(|x| x.to_string_lossy().into()).collect();
+        let map = Arc::new(InputMap::new(names));
+        app = app.with_input_map(map.clone());
+        Some(map)
+    } else {
+        None
+    };
+    // Open inputs up to the limit in advance to report errors early.
+    let mut input: InputStream = if files.len() == 0 && file_list.is_none() {
+        open_stdin()?.stream
     } else {
-        Box::new(ConcatReader::new(inputs.into_iter().map(|x| Ok(x))))
+        Box::new(concat_inputs(files, opt.max_open_files, open_input)?.with_input_map(input_map))
     };
+    if let Some(path) = &opt.tee {
+        let file = BufWriter::with_capacity(buffer_size, output::create(path)?);
+        input = Box::new(TeeReader::new(input, file));
+    }
     let paging = match opt.paging {
         PagingOption::Auto => {
             if stdout_is_atty() {
@@ -363,8 +1213,17 @@ fn run() -> Result<()> {
         PagingOption::Always => true,
         PagingOption::Never => false,
     };
-    let paging = if opt.paging_never { false } else { paging };
-    let mut output: OutputStream = if paging {
+    let paging = if opt.paging_never || to_file {
+        false
+    } else {
+        paging
+    };
+    let output: OutputStream = if let Some(path) = &opt.output {
+        match opt.rotate_size {
+            Some(limit) => Box::new(RotatingWriter::new(path.clone(), limit)?),
+            None => output::create(path)?,
+        }
+    } else if paging {
         if let Ok(pager) = Pager::new() {
             Box::new(pager)
         } else {
@@ -373,9 +1232,49 @@ fn run() -> Result<()> {
     } else {
         Box::new(std::io::stdout())
     };
+    // Configure output buffering, interactive output is always line buffered.
+    let mut output: OutputStream = if opt.line_buffered || line_mode || paging || stdout_is_atty() {
+        Box::new(LineWriter::with_capacity(buffer_size, output))
+    } else {
+        Box::new(BufWriter::with_capacity(buffer_size, output))
+    };
+    let mut files = match &opt.split_dir {
+        Some(dir) => Some(SplitWriter::new(dir.clone())?),
+        None => None,
+    };
+    let mut errors: Option<OutputStream> = if !opt.split_levels {
+        None
+    } else if opt.line_buffered || line_mode || atty::is(atty::Stream::Stderr) {
+        Some(Box::new(LineWriter::with_capacity(
+            buffer_size,
+            std::io::stderr(),
+        )))
+    } else {
+        Some(Box::new(BufWriter::with_capacity(
+            buffer_size,
+            std::io::stderr(),
+        )))
+    };
 
     // Run the app.
-    let run = || match app.run(input.as_mut(), output.as_mut()) {
+    let list_fields = opt.list_fields;
+    let sample_size = opt.sample_size;
+    let check_sorted = opt.check_sorted;
+    let run = || match if list_fields {
+        app.list_fields(input.as_mut(), output.as_mut(), sample_size)
+    } else if check_sorted {
+        app.check_sorted(input.as_mut())
+    } else if let Some(errors) = errors.as_mut() {
+        app.run_split(input.as_mut(), output.as_mut(), errors.as_mut())
+            .and_then(|_| Ok(errors.flush()?))
+    } else if let Some(files) = files.as_mut() {
+        app.run_split_files(input.as_mut(), output.as_mut(), files)
+            .and_then(|_| Ok(files.flush()?))
+    } else {
+        app.run(input.as_mut(), output.as_mut())
+    }
+    .and_then(|_| Ok(output.finish()?))
+    {
         Ok(()) => Ok(()),
         Err(Error::Io(ref e)) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
         Err(err) => Err(err),
@@ -391,7 +1290,130 @@ fn run() -> Result<()> {
 
 fn main() {
     if let Err(err) = run() {
-        eprintln!("{}: {}", Colour::Red.paint("error"), err);
+        if atty::is(atty::Stream::Stderr) {
+            eprintln!("{}: {}", Colour::Red.paint("error"), err);
+        } else {
+            eprintln!("error: {}", err);
+        }
         process::exit(1);
     }
 }
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::io::Read;
+
+    fn parse(args: &[&str], opts: &str) -> std::result::Result<Opt, structopt::clap::Error> {
+        let args = args.iter().map(OsString::from).collect();
+        Opt::from_iter_safe(args_with_opts(args, Some(opts.into())).unwrap())
+    }
+
+    #[test]
+    fn test_args_repeated_flag() {
+        let opt = parse(&["hl", "-P", "f.log"], "-P").unwrap();
+        assert!(opt.paging_never);
+        assert_eq!(opt.files, vec![PathBuf::from("f.log")]);
+        let opt = parse(&["hl", "-e", "f.log"], "-Z UTC --theme classic -e").unwrap();
+        assert!(opt.hide_empty_fields);
+        assert_eq!(opt.theme, "classic");
+    }
+
+    #[test]
+    fn test_anonymize_conflicts() {
+        assert!(parse(&["hl", "--anonymize", "user", "--debug-raw"], "").is_err());
+        assert!(parse(&["hl", "--anonymize", "user", "--explain"], "").is_err());
+        assert!(parse(&["hl", "--anonymize", "user"], "").is_ok());
+    }
+
+    #[test]
+    fn test_args_repeated_option() {
+        let opt = parse(
+            &["hl", "--theme", "one-dark-green", "--max-fields", "3"],
+            "--theme classic --max-fields 5",
+        )
+        .unwrap();
+        assert_eq!(opt.theme, "one-dark-green");
+        assert_eq!(opt.max_fields, Some(3));
+        let opt = parse(&["hl", "-f", "a=1"], "-f b=2").unwrap();
+        assert_eq!(opt.filter, vec!["b=2".to_string(), "a=1".to_string()]);
+        assert!(args_with_opts(vec!["hl".into()], Some("'".into())).is_err());
+    }
+
+    #[test]
+    fn test_concat_inputs() {
+        let dir = std::env::temp_dir().join(format!("hl-test-concat-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files: Vec<_> = ["a", "b", "c"].iter().map(|x| dir.join(x)).collect();
+        for file in &files {
+            std::fs::write(file, file.file_name().unwrap().to_str().unwrap()).unwrap();
+        }
+        let opened = RefCell::new(Vec::new());
+        let open = |path: &PathBuf| {
+            opened.borrow_mut().push(path.clone());
+            open_input(path)
+        };
+        let mut input = concat_inputs(files.clone(), 1, open).unwrap();
+        assert_eq!(*opened.borrow(), files[..1]);
+        let mut data = String::new();
+        input.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "abc");
+        assert_eq!(*opened.borrow(), files);
+
+        let missing = vec![dir.join("missing")];
+        let eager = concat_inputs(missing.clone(), 1, open_input).err().unwrap();
+        let mut lazy = concat_inputs(missing, 0, open_input).unwrap();
+        let lazy = lazy.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(lazy.to_string(), eager.to_string());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unbuffered() {
+        let mode = |args: &[&str]| {
+            let opt = parse(args, "").unwrap();
+            buffering(&opt.buffer_size, opt.unbuffered, opt.max_message_size)
+        };
+        assert!(parse(&["hl", "--unbuffered"], "").unwrap().unbuffered);
+        assert_eq!(mode(&["hl", "--unbuffered"]), (LINE_BUFFER_SIZE, true));
+        assert_eq!(
+            mode(&["hl", "--stdin-interactive"]),
+            (LINE_BUFFER_SIZE, true)
+        );
+        assert_eq!(
+            mode(&["hl", "--buffer-size", "line"]),
+            (LINE_BUFFER_SIZE, true)
+        );
+        assert_eq!(mode(&["hl", "--buffer-size", "1 KiB"]), (1024, false));
+    }
+
+    #[test]
+    fn test_max_rate() {
+        assert_eq!(
+            parse(&["hl", "--max-rate", "10"], "").unwrap().max_rate,
+            Some(10)
+        );
+        assert!(parse(&["hl", "--max-rate", "0"], "").is_err());
+        assert!(parse(&["hl", "--max-rate", "x"], "").is_err());
+    }
+
+    #[test]
+    fn test_field_names() {
+        let opt = parse(
+            &["hl", "--time-field", "when, at,", "--message-field", "text"],
+            "--time-field ts",
+        )
+        .unwrap();
+        assert_eq!(
+            parse_names(opt.time_field.as_deref().unwrap()),
+            ["when", "at"]
+        );
+        assert_eq!(parse_names(opt.message_field.as_deref().unwrap()), ["text"]);
+        assert_eq!(opt.level_field, None);
+        assert!(parse_names(" , ").is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+// std imports
+use std::io::{Result, Write};
+
+// third-party imports
+use enum_map::EnumMap;
+
+// local imports
+use crate::model::Level;
+
+// ---
+
+/// Counters collected over processed records, rendered in Prometheus text exposition format.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    pub records: u64,
+    pub records_by_level: EnumMap<Level, u64>,
+    pub invalid_lines: u64,
+    pub oversized_lines: u64,
+}
+
+impl Metrics {
+    /// Accounts a record with the given level.
+    pub fn add_record(&mut self, level: Option<Level>) {
+        self.records += 1;
+        if let Some(level) = level {
+            self.records_by_level[level] += 1;
+        }
+    }
+
+    /// Accounts a line that could not be parsed as a record.
+    pub fn add_invalid_line(&mut self) {
+        self.invalid_lines += 1;
+    }
+
+    /// Accounts a line exceeding the maximum message size, such lines are also counted as invalid ones.
+    pub fn add_oversized_line(&mut self) {
+        self.invalid_lines += 1;
+        self.oversized_lines += 1;
+    }
+
+    /// Adds counters from the other metrics.
+    pub fn merge(&mut self, other: &Self) {
+        self.records += other.records;
+        for (level, count) in other.records_by_level.iter() {
+            self.records_by_level[level] += count;
+        }
+        self.invalid_lines += other.invalid_lines;
+        self.oversized_lines += other.oversized_lines;
+    }
+
+    /// Writes metrics in Prometheus text exposition format.
+    pub fn write<W: Write + ?Sized>(&self, out: &mut W) -> Result<()> {
+        writeln!(out, "# HELP hl_records_total Total number of records.")?;
+        writeln!(out, "# TYPE hl_records_total counter")?;
+        writeln!(out, "hl_records_total {}", self.records)?;
+        writeln!(
+            out,
+            "# HELP hl_records_by_level Number of records by level."
+        )?;
+        writeln!(out, "# TYPE hl_records_by_level counter")?;
+        for (level, count) in self.records_by_level.iter() {
+            writeln!(
+                out,
+                r#"hl_records_by_level{{level="{}"}} {}"#,
+                level.as_str(),
+                count
+            )?;
+        }
+        writeln!(
+            out,
+            "# HELP hl_invalid_lines_total Number of lines that could not be parsed."
+        )?;
+        writeln!(out, "# TYPE hl_invalid_lines_total counter")?;
+        writeln!(out, "hl_invalid_lines_total {}", self.invalid_lines)?;
+        writeln!(
+            out,
+            "# HELP hl_oversized_lines_total Number of lines exceeding the maximum message size."
+        )?;
+        writeln!(out, "# TYPE hl_oversized_lines_total counter")?;
+        writeln!(out, "hl_oversized_lines_total {}", self.oversized_lines)?;
+        Ok(())
+    }
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let mut metrics = Metrics::default();
+        metrics.add_record(Some(Level::Error));
+        metrics.add_record(None);
+        let mut other = Metrics::default();
+        other.add_record(Some(Level::Error));
+        other.add_invalid_line();
+        other.add_oversized_line();
+        metrics.merge(&other);
+        let mut buf = Vec::new();
+        metrics.write(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.contains("\nhl_records_total 3\n"));
+        assert!(text.contains("\nhl_records_by_level{level=\"error\"} 2\n"));
+        assert!(text.contains("\nhl_records_by_level{level=\"info\"} 0\n"));
+        assert!(text.contains("\nhl_invalid_lines_total 2\n"));
+        assert!(text.contains("\nhl_oversized_lines_total 1\n"));
+    }
+}
@@ -5,7 +5,7 @@ use std::iter::IntoIterator;
 use std::marker::PhantomData;
 
 // third-party imports
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, FixedOffset, Utc};
 use json::value::RawValue;
 use regex::Regex;
 use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
@@ -14,13 +14,13 @@ use wildmatch::WildMatch;
 
 // local imports
 use crate::error::{Error, Result};
-use crate::settings::Fields;
+use crate::settings::{Fields, StatusLevel};
 use crate::timestamp::Timestamp;
 use crate::types::{self, FieldKind};
 
 // ---
 
-pub use types::Level;
+pub use types::{Level, LevelMode};
 
 // ---
 
@@ -30,6 +30,7 @@ pub struct Record<'a> {
     pub level: Option<Level>,
     pub logger: Option<&'a str>,
     pub caller: Option<&'a str>,
+    pub caller_function: Option<&'a str>,
     extra: heapless::Vec<(&'a str, &'a RawValue), RECORD_EXTRA_CAPACITY>,
     extrax: Vec<(&'a str, &'a RawValue)>,
 }
@@ -44,7 +45,7 @@ impl<'a> Record<'a> {
             return true;
         }
 
-        if filter.since.is_some() || filter.until.is_some() {
+        if filter.filters_time() {
             if let Some(ts) = self.ts.as_ref().and_then(|ts| ts.parse()) {
                 if let Some(since) = filter.since {
                     if ts < since {
@@ -56,58 +57,98 @@ impl<'a> Record<'a> {
                         return false;
                     }
                 }
+                if filter.excludes(ts) {
+                    return false;
+                }
             }
         }
 
         if let Some(bound) = &filter.level {
-            if let Some(level) = self.level.as_ref() {
-                if level > bound {
-                    return false;
-                }
+            if !self.matches_level(bound, filter.level_mode) {
+                return false;
             }
         }
 
-        if !filter.fields.0.is_empty() {
-            for field in filter.fields.0.iter() {
-                match &field.key[..] {
-                    "msg" | "message" => {
-                        if !field.match_value(self.message.map(|x| x.get()), true) {
-                            return false;
-                        }
-                    }
-                    "logger" => {
-                        if !field.match_value(self.logger, false) {
-                            return false;
-                        }
-                    }
-                    "caller" => {
-                        if !field.match_value(self.caller, false) {
-                            return false;
+        filter
+            .fields
+            .0
+            .iter()
+            .all(|field| self.matches_field(field, &filter.key_prefixes))
+    }
+
+    /// Evaluates each predicate of the filter separately and returns its description along with the result.
+    /// The record matches the filter if all predicates pass.
+    pub fn explain(&self, filter: &Filter) -> Vec<(String, bool)> {
+        let mut result = Vec::new();
+        let ts = self.ts.as_ref().and_then(|ts| ts.parse());
+        if let Some(since) = filter.since {
+            let passed = ts.map(|ts| ts >= since).unwrap_or(true);
+            result.push((format!("since={}", since.to_rfc3339()), passed));
+        }
+        if let Some(until) = filter.until {
+            let passed = ts.map(|ts| ts <= until).unwrap_or(true);
+            result.push((format!("until={}", until.to_rfc3339()), passed));
+        }
+        for &(start, end) in &filter.excluded {
+            let passed = ts.map(|ts| ts < start || ts > end).unwrap_or(true);
+            let range = format!("{}..{}", start.to_rfc3339(), end.to_rfc3339());
+            result.push((format!("exclude={}", range), passed));
+        }
+        if let Some(bound) = &filter.level {
+            let operator = match filter.level_mode {
+                LevelMode::Min => "<=",
+                LevelMode::Exact => "=",
+                LevelMode::Max => ">=",
+            };
+            result.push((
+                format!("level{}{}", operator, bound.as_str()),
+                self.matches_level(bound, filter.level_mode),
+            ));
+        }
+        for field in filter.fields.0.iter() {
+            let passed = self.matches_field(field, &filter.key_prefixes);
+            result.push((field.to_string(), passed));
+        }
+        result
+    }
+
+    fn matches_level(&self, bound: &Level, mode: LevelMode) -> bool {
+        match self.level {
+            Some(level) => mode.matches(level, *bound),
+            None => true,
+        }
+    }
+
+    fn matches_field(&self, field: &FieldFilter, prefixes: &[String]) -> bool {
+        match &field.key[..] {
+            "msg" | "message" => field.match_value(self.message.map(|x| x.get()), true),
+            "logger" => field.match_value(self.logger, false),
+            "caller" => field.match_value(self.caller, false),
+            _ => {
+                let mut matched = false;
+                for (k, v) in self.extra.iter() {
+                    let key_match = field.match_key(k).or_else(|| {
+                        let stripped = strip_key_prefix(k, prefixes);
+                        if stripped.len() != k.len() {
+                            field.match_key(stripped)
+                        } else {
+                            None
                         }
-                    }
-                    _ => {
-                        let mut matched = false;
-                        for (k, v) in self.extra.iter() {
-                            match field.match_key(*k) {
-                                None => {}
-                                Some(KeyMatch::Full) => {
-                                    let escaped = v.get().starts_with('"');
-                                    matched |= field.match_value(Some(v.get()), escaped);
-                                }
-                                Some(KeyMatch::Partial(subkey)) => {
-                                    matched |= field.match_value_partial(subkey, *v);
-                                }
-                            }
+                    });
+                    match key_match {
+                        None => {}
+                        Some(KeyMatch::Full) => {
+                            let escaped = v.get().starts_with('"');
+                            matched |= field.match_value(Some(v.get()), escaped);
                         }
-                        if !matched {
-                            return false;
+                        Some(KeyMatch::Partial(subkey)) => {
+                            matched |= field.match_value_partial(subkey, *v);
                         }
                     }
                 }
+                matched
             }
         }
-
-        return true;
     }
 
     fn with_capacity(capacity: usize) -> Self {
@@ -117,6 +158,7 @@ impl<'a> Record<'a> {
             level: None,
             logger: None,
             caller: None,
+            caller_function: None,
             extra: heapless::Vec::new(),
             extrax: if capacity > RECORD_EXTRA_CAPACITY {
                 Vec::with_capacity(capacity - RECORD_EXTRA_CAPACITY)
@@ -129,28 +171,60 @@ impl<'a> Record<'a> {
 
 // ---
 
+/// Handling of keys occurring more than once in a record.
+///
+/// Records are deserialized keeping all occurrences in their source order,
+/// predefined fields such as time, level and message always take a single occurrence, the last one unless `First` is selected.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicateKeys {
+    /// Keep the first occurrence.
+    First,
+    /// Keep the value of the last occurrence at the position of the first one.
+    Last,
+    /// Keep all occurrences.
+    #[default]
+    All,
+}
+
+// ---
+
 #[derive(Default)]
 pub struct ParserSettings {
     fields: HashMap<String, (FieldSettings, usize)>,
     ignore: Vec<WildMatch>,
+    levels: HashMap<String, Level>,
+    infer_level: bool,
+    status_field: Option<String>,
+    status_levels: Vec<StatusLevel>,
+    duplicate_keys: DuplicateKeys,
+    preparse_time: bool,
+    time_format: Option<String>,
 }
 
 impl ParserSettings {
     pub fn new(s: &Fields, preparse_time: bool) -> Self {
         let mut fields = HashMap::new();
         for (i, name) in s.predefined.time.names.iter().enumerate() {
-            fields.insert(name.clone(), (FieldSettings::Time(preparse_time), i));
+            fields.insert(name.clone(), (FieldSettings::Time(preparse_time, None), i));
         }
         let mut j = 0;
+        let mut levels = HashMap::new();
         for variant in &s.predefined.level.variants {
             let mut mapping = HashMap::new();
             for (level, values) in &variant.values {
                 for value in values {
                     mapping.insert(value.clone(), level.clone());
+                    levels.insert(value.to_lowercase(), *level);
                 }
             }
             for (i, name) in variant.names.iter().enumerate() {
-                fields.insert(name.clone(), (FieldSettings::Level(mapping.clone()), j + i));
+                fields.insert(
+                    name.clone(),
+                    (
+                        FieldSettings::Level(mapping.clone(), LevelMapping::default()),
+                        j + i,
+                    ),
+                );
             }
             j += variant.names.len();
         }
@@ -163,10 +237,103 @@ impl ParserSettings {
         for (i, name) in s.predefined.caller.names.iter().enumerate() {
             fields.insert(name.clone(), (FieldSettings::Caller, i));
         }
+        for (i, name) in s.predefined.caller.function_names.iter().enumerate() {
+            fields.insert(name.clone(), (FieldSettings::CallerFunction, i));
+        }
         Self {
             fields,
             ignore: s.ignore.iter().map(|v| WildMatch::new(v)).collect(),
+            levels,
+            infer_level: false,
+            status_field: None,
+            status_levels: s.status_levels.clone(),
+            duplicate_keys: DuplicateKeys::default(),
+            preparse_time,
+            time_format: None,
+        }
+    }
+
+    /// Sets a strftime-like pattern used to parse time field values instead of format auto-detection.
+    pub fn with_time_format(mut self, format: Option<String>) -> Self {
+        for (field, _) in self.fields.values_mut() {
+            if let FieldSettings::Time(_, f) = field {
+                *f = format.clone();
+            }
+        }
+        self.time_format = format;
+        self
+    }
+
+    /// Enables inference of the level from a leading token of the message, like `[ERROR]` or `WARN:`,
+    /// for records having no level field. Tokens are matched case-insensitively against configured level values.
+    pub fn with_level_inference(mut self, value: bool) -> Self {
+        self.infer_level = value;
+        self
+    }
+
+    /// Sets a field with numeric status codes, like HTTP status, used to compute the level
+    /// of records having no level field according to the configured status ranges.
+    pub fn with_status_field(mut self, name: Option<String>) -> Self {
+        self.status_field = name;
+        self
+    }
+
+    /// Sets handling of keys occurring more than once in a record.
+    pub fn with_duplicate_keys(mut self, value: DuplicateKeys) -> Self {
+        self.duplicate_keys = value;
+        self
+    }
+
+    /// Adds level value mappings on top of the configured ones.
+    pub fn with_level_mapping(mut self, mapping: LevelMapping) -> Self {
+        for (field, _) in self.fields.values_mut() {
+            if let FieldSettings::Level(_, extra) = field {
+                *extra = mapping.clone();
+            }
+        }
+        for (value, level) in &mapping.0 {
+            self.levels.insert(value.clone(), *level);
+        }
+        self
+    }
+
+    fn infer_level(&self, message: &RawValue) -> Option<Level> {
+        let message = message.get().strip_prefix('"')?.trim_start();
+        let (token, bracketed) = match message.strip_prefix('[') {
+            Some(rest) => (&rest[..rest.find(']')?], true),
+            None => {
+                let end = message
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(message.len());
+                (&message[..end], false)
+            }
+        };
+        if token.is_empty() {
+            return None;
+        }
+        if !bracketed {
+            let colon = message[token.len()..].starts_with(':');
+            if !colon && token.bytes().any(|b| b.is_ascii_lowercase()) {
+                return None;
+            }
         }
+        self.levels.get(&token.trim().to_lowercase()).cloned()
+    }
+
+    fn status_level(&self, record: &Record) -> Option<Level> {
+        let name = self.status_field.as_ref()?;
+        let (_, value) = record.fields().find(|(k, _)| k == name)?;
+        let value = value.get();
+        let code: u64 = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value)
+            .parse()
+            .ok()?;
+        self.status_levels
+            .iter()
+            .find(|r| r.from <= code && code <= r.to)
+            .map(|r| r.level)
     }
 
     fn apply<'a>(
@@ -180,7 +347,11 @@ impl ParserSettings {
             Some((field, p)) => {
                 let kind = field.kind();
                 let priority = ctx.priority(kind);
-                if priority.is_none() || Some(*p) <= *priority {
+                let replaces = match self.duplicate_keys {
+                    DuplicateKeys::First => Some(*p) < *priority,
+                    DuplicateKeys::Last | DuplicateKeys::All => Some(*p) <= *priority,
+                };
+                if priority.is_none() || replaces {
                     field.apply(value, to);
                     *priority = Some(*p);
                 }
@@ -191,6 +362,19 @@ impl ParserSettings {
                         return;
                     }
                 }
+                if self.duplicate_keys != DuplicateKeys::All {
+                    let existing = to
+                        .extra
+                        .iter_mut()
+                        .chain(to.extrax.iter_mut())
+                        .find(|(k, _)| *k == key);
+                    if let Some((_, v)) = existing {
+                        if self.duplicate_keys == DuplicateKeys::Last {
+                            *v = value;
+                        }
+                        return;
+                    }
+                }
                 match to.extra.push((key, value)) {
                     Ok(_) => {}
                     Err(value) => to.extrax.push(value),
@@ -210,6 +394,7 @@ impl ParserSettings {
             logger: None,
             message: None,
             caller: None,
+            caller_function: None,
         };
         for (key, value) in items {
             self.apply(key, value, to, &mut ctx)
@@ -225,6 +410,7 @@ struct PriorityContext {
     logger: Option<usize>,
     message: Option<usize>,
     caller: Option<usize>,
+    caller_function: Option<usize>,
 }
 
 impl PriorityContext {
@@ -235,6 +421,7 @@ impl PriorityContext {
             FieldKind::Logger => &mut self.logger,
             FieldKind::Message => &mut self.message,
             FieldKind::Caller => &mut self.caller,
+            FieldKind::CallerFunction => &mut self.caller_function,
         }
     }
 }
@@ -242,17 +429,18 @@ impl PriorityContext {
 // ---
 
 enum FieldSettings {
-    Time(bool),
-    Level(HashMap<String, Level>),
+    Time(bool, Option<String>),
+    Level(HashMap<String, Level>, LevelMapping),
     Logger,
     Message,
     Caller,
+    CallerFunction,
 }
 
 impl FieldSettings {
     fn apply<'a>(&self, value: &'a RawValue, to: &mut Record<'a>) {
         match self {
-            Self::Time(preparse) => {
+            Self::Time(preparse, format) => {
                 let s = value.get();
                 let s = if s.as_bytes()[0] == b'"' {
                     &s[1..s.len() - 1]
@@ -260,30 +448,34 @@ impl FieldSettings {
                     s
                 };
                 let ts = Timestamp::new(s, None);
-                if *preparse {
+                if let Some(format) = format {
+                    to.ts = Some(Timestamp::new(ts.raw(), Some(ts.parse_with_format(format))));
+                } else if *preparse {
                     to.ts = Some(Timestamp::new(ts.raw(), Some(ts.parse())));
                 } else {
                     to.ts = Some(ts);
                 }
             }
-            Self::Level(values) => {
+            Self::Level(values, extra) => {
                 to.level = json::from_str(value.get())
                     .ok()
-                    .and_then(|x: &'a str| values.get(x).cloned());
+                    .and_then(|x: &'a str| values.get(x).cloned().or_else(|| extra.get(x)));
             }
             Self::Logger => to.logger = json::from_str(value.get()).ok(),
             Self::Message => to.message = Some(value),
             Self::Caller => to.caller = json::from_str(value.get()).ok(),
+            Self::CallerFunction => to.caller_function = json::from_str(value.get()).ok(),
         }
     }
 
     fn kind(&self) -> FieldKind {
         match self {
-            Self::Time(_) => FieldKind::Time,
-            Self::Level(_) => FieldKind::Level,
+            Self::Time(..) => FieldKind::Time,
+            Self::Level(..) => FieldKind::Level,
             Self::Logger => FieldKind::Logger,
             Self::Message => FieldKind::Message,
             Self::Caller => FieldKind::Caller,
+            Self::CallerFunction => FieldKind::CallerFunction,
         }
     }
 }
@@ -299,12 +491,65 @@ impl Parser {
         Self { settings }
     }
 
+    /// Adds a time field with the lowest priority.
+    pub fn with_time_field(mut self, name: &str) -> Self {
+        let priority = self
+            .settings
+            .fields
+            .values()
+            .filter(|(field, _)| field.kind() == FieldKind::Time)
+            .map(|(_, p)| p + 1)
+            .max()
+            .unwrap_or(0);
+        let field = FieldSettings::Time(
+            self.settings.preparse_time,
+            self.settings.time_format.clone(),
+        );
+        self.settings
+            .fields
+            .entry(name.into())
+            .or_insert((field, priority));
+        self
+    }
+
+    /// Detects a time field in the sample of records if none of the configured time fields is present.
+    /// Returns the most common field with values recognized as timestamps along with their format.
+    pub fn detect_time_field(&self, sample: &[u8]) -> Option<(String, TimeFormat)> {
+        let mut candidates = HashMap::<&str, (usize, TimeFormat)>::new();
+        for line in sample.split(|c| *c == b'\n') {
+            let mut stream = json::Deserializer::from_slice(line).into_iter::<RawRecord>();
+            while let Some(Ok(record)) = stream.next() {
+                let record = self.parse(record);
+                if record.ts.is_some() {
+                    return None;
+                }
+                for (key, value) in record.fields() {
+                    if let Some(format) = TimeFormat::detect(value) {
+                        candidates.entry(key).or_insert((0, format)).0 += 1;
+                    }
+                }
+            }
+        }
+        candidates
+            .into_iter()
+            .max_by_key(|(key, (count, _))| (*count, std::cmp::Reverse(*key)))
+            .map(|(key, (_, format))| (key.into(), format))
+    }
+
     pub fn parse<'a>(&self, record: RawRecord<'a>) -> Record<'a> {
         let fields = record.fields();
         let count = fields.size_hint().1.unwrap_or(0);
         let mut record = Record::<'a>::with_capacity(count);
 
         self.settings.apply_each(fields, &mut record);
+        if record.level.is_none() {
+            record.level = self.settings.status_level(&record);
+        }
+        if self.settings.infer_level && record.level.is_none() {
+            if let Some(message) = record.message {
+                record.level = self.settings.infer_level(message);
+            }
+        }
 
         record
     }
@@ -312,6 +557,53 @@ impl Parser {
 
 // ---
 
+/// Format of timestamps detected by [`Parser::detect_time_field`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeFormat {
+    Rfc3339,
+    UnixSeconds,
+    UnixMillis,
+    UnixMicros,
+}
+
+impl TimeFormat {
+    fn detect(value: &RawValue) -> Option<Self> {
+        let value = value.get();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        let ts = Timestamp::new(value, None);
+        let year = ts.parse()?.year();
+        if !(1990..2100).contains(&year) {
+            return None;
+        }
+        if ts.as_rfc3339().is_some() {
+            return Some(Self::Rfc3339);
+        }
+        // the unit is defined by the number of digits of the integer part, fractional seconds are still seconds
+        let integer = value.split('.').next().unwrap_or(value);
+        match integer.len() {
+            0..=11 => Some(Self::UnixSeconds),
+            12..=14 => Some(Self::UnixMillis),
+            _ => Some(Self::UnixMicros),
+        }
+    }
+}
+
+impl fmt::Display for TimeFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rfc3339 => "RFC3339",
+            Self::UnixSeconds => "unix timestamp in seconds",
+            Self::UnixMillis => "unix timestamp in milliseconds",
+            Self::UnixMicros => "unix timestamp in microseconds",
+        })
+    }
+}
+
+// ---
+
 pub struct RawRecord<'a> {
     fields: heapless::Vec<(&'a str, &'a RawValue), RAW_RECORD_FIELDS_CAPACITY>,
     fieldsx: Vec<(&'a str, &'a RawValue)>,
@@ -386,11 +678,21 @@ pub enum KeyMatch<'a> {
 #[derive(Debug)]
 pub struct KeyMatcher<'a> {
     key: &'a str,
+    delimiter: u8,
 }
 
 impl<'a> KeyMatcher<'a> {
     pub fn new(key: &'a str) -> Self {
-        Self { key }
+        Self {
+            key,
+            delimiter: b'.',
+        }
+    }
+
+    /// Sets delimiter separating keys of nested fields, `.` by default.
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
     }
 
     pub fn match_key<'b>(&'b self, key: &str) -> Option<KeyMatch<'a>> {
@@ -414,10 +716,10 @@ impl<'a> KeyMatcher<'a> {
         if self.key.len() == key.len() {
             Some(KeyMatch::Full)
         } else if self.key.len() > key.len() {
-            if bytes[key.len()] == b'.' {
-                Some(KeyMatch::Partial(KeyMatcher::new(
-                    &self.key[key.len() + 1..],
-                )))
+            if bytes[key.len()] == self.delimiter {
+                Some(KeyMatch::Partial(
+                    KeyMatcher::new(&self.key[key.len() + 1..]).with_delimiter(self.delimiter),
+                ))
             } else {
                 None
             }
@@ -429,6 +731,7 @@ impl<'a> KeyMatcher<'a> {
 
 // ---
 
+/// Value matching of a field filter, patterns are parsed and compiled once when the filter is created.
 #[derive(Debug)]
 pub enum ValueMatchPolicy {
     Exact(String),
@@ -478,26 +781,44 @@ pub struct FieldFilter {
     match_policy: ValueMatchPolicy,
     op: UnaryBoolOp,
     flat_key: bool,
+    delimiter: u8,
 }
 
 impl FieldFilter {
+    fn message(match_policy: ValueMatchPolicy) -> Self {
+        Self {
+            key: "msg".into(),
+            match_policy,
+            op: UnaryBoolOp::None,
+            flat_key: true,
+            delimiter: b'.',
+        }
+    }
+
     fn parse(text: &str) -> Result<Self> {
         let mut parts = text.split('=');
         match (parts.next(), parts.next()) {
             (Some(key), Some(value)) => {
                 let (key, match_policy, op) = Self::parse_mp_op(key, value)?;
-                let flat_key = key.as_bytes().iter().position(|&x| x == b'.').is_none();
-                Ok(Self {
+                let mut filter = Self {
                     key: key.into(),
                     match_policy,
                     op,
-                    flat_key,
-                })
+                    flat_key: true,
+                    delimiter: b'.',
+                };
+                filter.set_delimiter(b'.');
+                Ok(filter)
             }
             _ => Err(Error::WrongFieldFilter(text.into())),
         }
     }
 
+    fn set_delimiter(&mut self, delimiter: u8) {
+        self.delimiter = delimiter;
+        self.flat_key = !self.key.as_bytes().contains(&delimiter);
+    }
+
     fn parse_mp_op<'k>(
         key: &'k str,
         value: &str,
@@ -528,7 +849,9 @@ impl FieldFilter {
             return None;
         }
 
-        KeyMatcher::new(&self.key).match_key(key)
+        KeyMatcher::new(&self.key)
+            .with_delimiter(self.delimiter)
+            .match_key(key)
     }
 
     fn match_value(&self, value: Option<&str>, escaped: bool) -> bool {
@@ -574,6 +897,20 @@ impl FieldFilter {
     }
 }
 
+impl fmt::Display for FieldFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.key)?;
+        if let UnaryBoolOp::Negate = self.op {
+            f.write_str("!")?;
+        }
+        match &self.match_policy {
+            ValueMatchPolicy::Exact(value) => write!(f, "={}", value),
+            ValueMatchPolicy::SubString(value) => write!(f, "~={}", value),
+            ValueMatchPolicy::RegularExpression(value) => write!(f, "~~={}", value),
+        }
+    }
+}
+
 // ---
 
 #[derive(Debug, Default)]
@@ -587,16 +924,79 @@ impl FieldFilterSet {
         }
         Ok(FieldFilterSet(fields))
     }
+
+    /// Adds a filter passing records with the unescaped message containing the substring.
+    pub fn with_message_substring(mut self, pattern: &str) -> Self {
+        self.0
+            .push(FieldFilter::message(ValueMatchPolicy::SubString(
+                pattern.into(),
+            )));
+        self
+    }
+
+    /// Adds a filter passing records with the unescaped message matching the regular expression.
+    pub fn with_message_regex(mut self, pattern: &str) -> Result<Self> {
+        let pattern = ValueMatchPolicy::RegularExpression(pattern.parse()?);
+        self.0.push(FieldFilter::message(pattern));
+        Ok(self)
+    }
+
+    /// Sets delimiter splitting filter keys into paths of nested fields, `.` by default.
+    pub fn with_path_delimiter(mut self, delimiter: u8) -> Self {
+        for field in &mut self.0 {
+            field.set_delimiter(delimiter);
+        }
+        self
+    }
 }
 
 // ---
 
+/// Additional level value mappings matched case-insensitively.
+#[derive(Clone, Debug, Default)]
+pub struct LevelMapping(HashMap<String, Level>);
+
+impl LevelMapping {
+    /// Parses mappings from items in form `<value>=<level>[,<value>=<level>...]`.
+    pub fn new<T: AsRef<str>, I: IntoIterator<Item = T>>(items: I) -> Result<Self> {
+        let mut mapping = HashMap::new();
+        for item in items {
+            for pair in item.as_ref().split(',').filter(|x| !x.trim().is_empty()) {
+                let (value, level) = match pair.split_once('=') {
+                    Some((value, level)) if !value.trim().is_empty() => {
+                        (value.trim(), level.trim())
+                    }
+                    _ => return Err(Error::WrongLevelMapping(pair.into())),
+                };
+                mapping.insert(value.to_lowercase(), level.parse()?);
+            }
+        }
+        Ok(Self(mapping))
+    }
+
+    fn get(&self, value: &str) -> Option<Level> {
+        if self.0.is_empty() {
+            return None;
+        }
+        self.0.get(&value.to_lowercase()).cloned()
+    }
+}
+
+// ---
+
+/// Record filter constructed once with all of its patterns compiled and shared by reference
+/// by all processing threads, so it must stay `Sync` and must not keep per-record mutable state.
 #[derive(Debug, Default)]
 pub struct Filter {
     pub fields: FieldFilterSet,
     pub level: Option<Level>,
+    pub level_mode: LevelMode,
     pub since: Option<DateTime<Utc>>,
     pub until: Option<DateTime<Utc>>,
+    /// Inclusive time ranges of records to drop, sorted and not overlapping, see [`Filter::with_excluded_range`].
+    pub excluded: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    /// Key prefixes that may be omitted in field filter keys.
+    pub key_prefixes: Vec<String>,
 }
 
 impl Filter {
@@ -605,7 +1005,48 @@ impl Filter {
             && self.level.is_none()
             && self.since.is_none()
             && self.until.is_none()
+            && self.excluded.is_empty()
+    }
+
+    /// Adds an inclusive time range of records to drop, overlapping ranges are merged.
+    /// Records without a timestamp are kept.
+    pub fn with_excluded_range(mut self, mut start: DateTime<Utc>, mut end: DateTime<Utc>) -> Self {
+        self.excluded.retain(|&(s, e)| {
+            if s <= end && start <= e {
+                start = start.min(s);
+                end = end.max(e);
+                false
+            } else {
+                true
+            }
+        });
+        let pos = self.excluded.partition_point(|&(s, _)| s < start);
+        self.excluded.insert(pos, (start, end));
+        self
+    }
+
+    /// Returns true if the filter checks timestamps of records.
+    pub fn filters_time(&self) -> bool {
+        self.since.is_some() || self.until.is_some() || !self.excluded.is_empty()
+    }
+
+    fn excludes(&self, ts: DateTime<FixedOffset>) -> bool {
+        self.excluded.iter().any(|&(s, e)| s <= ts && ts <= e)
+    }
+}
+
+// ---
+
+/// Returns the key without the first matching prefix, unless nothing is left of it.
+pub fn strip_key_prefix<'a>(key: &'a str, prefixes: &[String]) -> &'a str {
+    for prefix in prefixes {
+        if let Some(stripped) = key.strip_prefix(prefix.as_str()) {
+            if !stripped.is_empty() {
+                return stripped;
+            }
+        }
     }
+    key
 }
 
 // ---
@@ -711,3 +1152,366 @@ impl<'de: 'a, 'a, const N: usize> Deserialize<'de> for Array<'a, N> {
 
 const RECORD_EXTRA_CAPACITY: usize = 32;
 const RAW_RECORD_FIELDS_CAPACITY: usize = RECORD_EXTRA_CAPACITY + 8;
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::settings::Settings;
+
+    #[test]
+    fn test_level_inference() {
+        let settings = Settings::default();
+        let parser =
+            Parser::new(ParserSettings::new(&settings.fields, false).with_level_inference(true));
+        let level = |data: &str| {
+            parser
+                .parse(json::from_str::<RawRecord>(data).unwrap())
+                .level
+        };
+        assert_eq!(
+            level(r#"{"msg":"[ERROR] something failed"}"#),
+            Some(Level::Error)
+        );
+        assert_eq!(
+            level(r#"{"msg":"Warn: disk is almost full"}"#),
+            Some(Level::Warning)
+        );
+        assert_eq!(level(r#"{"msg":"DEBUG connected"}"#), Some(Level::Debug));
+        assert_eq!(level(r#"{"msg":"error handling is hard"}"#), None);
+        assert_eq!(level(r#"{"msg":"[request] started"}"#), None);
+        assert_eq!(
+            level(r#"{"level":"info","msg":"[ERROR] x"}"#),
+            Some(Level::Info)
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let data = r#"{"level":"info","msg":"hello","ctx":{"service":"api"}}"#;
+        let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+        let filter = Filter {
+            fields: FieldFilterSet::new(["ctx.service=api", "msg!~=ell"]).unwrap(),
+            level: Some(Level::Info),
+            ..Default::default()
+        };
+        assert_eq!(
+            record.explain(&filter),
+            vec![
+                ("level<=info".to_string(), true),
+                ("ctx.service=api".to_string(), true),
+                ("msg!~=ell".to_string(), false),
+            ]
+        );
+        assert!(!record.matches(&filter));
+        let filter = Filter {
+            level: Some(Level::Warning),
+            level_mode: LevelMode::Max,
+            ..Default::default()
+        };
+        assert_eq!(
+            record.explain(&filter),
+            vec![("level>=warning".to_string(), true)]
+        );
+    }
+
+    #[test]
+    fn test_path_delimiter_in_filter() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let data = r#"{"msg":"m","a.b":"1","a":{"b":"2"}}"#;
+        let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+        let matches = |text: &str, delimiter| {
+            let filter = Filter {
+                fields: FieldFilterSet::new([text])
+                    .unwrap()
+                    .with_path_delimiter(delimiter),
+                ..Default::default()
+            };
+            record.matches(&filter)
+        };
+        // with the default delimiter the key is ambiguous and matches both fields
+        assert!(matches("a.b=1", b'.'));
+        assert!(matches("a.b=2", b'.'));
+        assert!(matches("a.b=1", b'/'));
+        assert!(!matches("a.b=2", b'/'));
+        assert!(matches("a/b=2", b'/'));
+        assert!(!matches("a/b=1", b'/'));
+    }
+
+    #[test]
+    fn test_excluded_ranges() {
+        let t = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let filter = Filter::default()
+            .with_excluded_range(t("2021-06-20T12:00:00Z"), t("2021-06-20T13:00:00Z"))
+            .with_excluded_range(t("2021-06-20T10:00:00Z"), t("2021-06-20T10:30:00Z"))
+            .with_excluded_range(t("2021-06-20T12:30:00Z"), t("2021-06-20T14:00:00Z"));
+        assert_eq!(
+            filter.excluded,
+            vec![
+                (t("2021-06-20T10:00:00Z"), t("2021-06-20T10:30:00Z")),
+                (t("2021-06-20T12:00:00Z"), t("2021-06-20T14:00:00Z")),
+            ]
+        );
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, true));
+        let matches = |data: &str| {
+            parser
+                .parse(json::from_str::<RawRecord>(data).unwrap())
+                .matches(&filter)
+        };
+        assert!(matches(r#"{"ts":"2021-06-20T11:00:00Z"}"#));
+        assert!(!matches(r#"{"ts":"2021-06-20T10:30:00Z"}"#));
+        assert!(!matches(r#"{"ts":"2021-06-20T13:30:00Z"}"#));
+        assert!(matches(r#"{"ts":"2021-06-20T14:00:01Z"}"#));
+        assert!(matches(r#"{"msg":"no timestamp"}"#));
+    }
+
+    #[test]
+    fn test_filter_is_sync() {
+        fn shared<T: Send + Sync>(_: &T) {}
+        let filter = Filter {
+            fields: FieldFilterSet::new(["a~~=^x+$", "b~=y", "c=z"]).unwrap(),
+            ..Default::default()
+        };
+        shared(&filter);
+        let data = r#"{"a":"xx","b":"yy","c":"z"}"#;
+        let parser = Parser::new(ParserSettings::new(&Settings::default().fields, false));
+        crossbeam_utils::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|_| {
+                    let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+                    assert!(record.matches(&filter));
+                });
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_message_grep() {
+        let parser = Parser::new(ParserSettings::new(&Settings::default().fields, false));
+        let filter = Filter {
+            fields: FieldFilterSet::new(["a=1"])
+                .unwrap()
+                .with_message_substring("refused \"db\"")
+                .with_message_regex("^conn\\w+")
+                .unwrap(),
+            ..Default::default()
+        };
+        let matches = |data: &str| {
+            parser
+                .parse(json::from_str::<RawRecord>(data).unwrap())
+                .matches(&filter)
+        };
+        assert!(matches(r#"{"msg":"connection refused \"db\"","a":"1"}"#));
+        assert!(!matches(r#"{"msg":"connection refused \"db\"","a":"2"}"#));
+        assert!(!matches(r#"{"msg":"a connection refused \"db\"","a":"1"}"#));
+        assert!(!matches(r#"{"msg":"connection refused","a":"1"}"#));
+        assert!(!matches(r#"{"a":"1"}"#));
+        assert!(FieldFilterSet::default().with_message_regex("(").is_err());
+    }
+
+    #[test]
+    fn test_key_prefixes_in_filter() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let data = r#"{"msg":"m","app_user":{"id":"42"}}"#;
+        let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+        let mut filter = Filter {
+            fields: FieldFilterSet::new(["user.id=42"]).unwrap(),
+            ..Default::default()
+        };
+        assert!(!record.matches(&filter));
+        filter.key_prefixes = vec!["app_".into()];
+        assert!(record.matches(&filter));
+        filter.fields = FieldFilterSet::new(["app_user.id=42"]).unwrap();
+        assert!(record.matches(&filter));
+    }
+
+    #[test]
+    fn test_level_mapping() {
+        let settings = Settings::default();
+        let mapping = LevelMapping::new(&["critical=error, notice=info", "verbose=d"]).unwrap();
+        let parser =
+            Parser::new(ParserSettings::new(&settings.fields, false).with_level_mapping(mapping));
+        let level = |data: &str| {
+            parser
+                .parse(json::from_str::<RawRecord>(data).unwrap())
+                .level
+        };
+        assert_eq!(level(r#"{"level":"CRITICAL"}"#), Some(Level::Error));
+        assert_eq!(level(r#"{"level":"notice"}"#), Some(Level::Info));
+        assert_eq!(level(r#"{"level":"verbose"}"#), Some(Level::Debug));
+        assert_eq!(level(r#"{"level":"warn"}"#), Some(Level::Warning));
+        assert_eq!(level(r#"{"level":"trace"}"#), None);
+        assert!(LevelMapping::new(&["critical"]).is_err());
+        assert!(LevelMapping::new(&["critical=fatal"]).is_err());
+    }
+
+    #[test]
+    fn test_min_level() {
+        use config::{Config, File, FileFormat};
+        let mut s = Config::default();
+        s.merge(File::from_str(
+            include_str!("../etc/defaults/config.yaml"),
+            FileFormat::Yaml,
+        ))
+        .unwrap();
+        s.merge(File::from_str("min-level: info", FileFormat::Yaml))
+            .unwrap();
+        let settings: Settings = s.try_into().unwrap();
+        assert_eq!(settings.min_level, Some(Level::Info));
+        assert_eq!(Settings::default().min_level, None);
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        // the configured level is the floor unless the level is specified explicitly
+        let matches = |level: Option<Level>, data: &str| {
+            let filter = Filter {
+                level: level.or(settings.min_level),
+                ..Default::default()
+            };
+            parser
+                .parse(json::from_str::<RawRecord>(data).unwrap())
+                .matches(&filter)
+        };
+        assert!(!matches(None, r#"{"level":"debug"}"#));
+        assert!(matches(None, r#"{"level":"info"}"#));
+        assert!(matches(None, r#"{"level":"error"}"#));
+        assert!(matches(Some(Level::Debug), r#"{"level":"debug"}"#));
+        assert!(!matches(Some(Level::Error), r#"{"level":"info"}"#));
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let settings = Settings::default();
+        let data = r#"{"msg":"a","x":1,"y":2,"x":3,"msg":"b"}"#;
+        let parse = |mode| {
+            let parser =
+                Parser::new(ParserSettings::new(&settings.fields, false).with_duplicate_keys(mode));
+            let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+            let fields: Vec<_> = record.fields().map(|(k, v)| (*k, v.get())).collect();
+            (record.message.unwrap().get(), fields)
+        };
+        assert_eq!(
+            parse(DuplicateKeys::All),
+            (r#""b""#, vec![("x", "1"), ("y", "2"), ("x", "3")])
+        );
+        assert_eq!(
+            parse(DuplicateKeys::First),
+            (r#""a""#, vec![("x", "1"), ("y", "2")])
+        );
+        assert_eq!(
+            parse(DuplicateKeys::Last),
+            (r#""b""#, vec![("x", "3"), ("y", "2")])
+        );
+    }
+
+    #[test]
+    fn test_status_level() {
+        let settings = Settings::default();
+        let parser = Parser::new(
+            ParserSettings::new(&settings.fields, false).with_status_field(Some("status".into())),
+        );
+        let level = |data: &str| {
+            parser
+                .parse(json::from_str::<RawRecord>(data).unwrap())
+                .level
+        };
+        assert_eq!(level(r#"{"status":200}"#), Some(Level::Info));
+        assert_eq!(level(r#"{"status":"304"}"#), Some(Level::Info));
+        assert_eq!(level(r#"{"status":404}"#), Some(Level::Warning));
+        assert_eq!(level(r#"{"status":503}"#), Some(Level::Error));
+        assert_eq!(
+            level(r#"{"status":503,"level":"debug"}"#),
+            Some(Level::Debug)
+        );
+        assert_eq!(level(r#"{"status":42}"#), None);
+        assert_eq!(level(r#"{"status":"unknown"}"#), None);
+    }
+
+    #[test]
+    fn test_time_format() {
+        let settings = Settings::default();
+        let parser = Parser::new(
+            ParserSettings::new(&settings.fields, false)
+                .with_time_format(Some("%d/%b/%Y:%H:%M:%S %z".into())),
+        )
+        .with_time_field("time_local");
+        let ts = |data: &str| {
+            parser
+                .parse(json::from_str::<RawRecord>(data).unwrap())
+                .ts
+                .and_then(|ts| ts.parse())
+        };
+        let expected = DateTime::parse_from_rfc3339("2021-06-27T00:48:30+03:00").ok();
+        assert_eq!(ts(r#"{"ts":"27/Jun/2021:00:48:30 +0300"}"#), expected);
+        assert_eq!(
+            ts(r#"{"time_local":"27/Jun/2021:00:48:30 +0300"}"#),
+            expected
+        );
+        assert_eq!(ts(r#"{"ts":"2021-06-27T00:48:30+03:00"}"#), None);
+    }
+
+    #[test]
+    fn test_custom_field_names() {
+        let mut settings = Settings::default();
+        let predefined = &mut settings.fields.predefined;
+        predefined.set_time_names(vec!["when".into()]);
+        predefined.set_level_names(vec!["severity".into(), "sev".into()]);
+        predefined.set_message_names(vec!["text".into()]);
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let data = concat!(
+            r#"{"ts":"2021-06-20T00:00:00Z","when":"2021-06-21T00:00:00Z","#,
+            r#""level":"info","sev":"error","msg":"a","text":"b"}"#,
+        );
+        let record = parser.parse(json::from_str::<RawRecord>(data).unwrap());
+        assert_eq!(
+            record.ts.as_ref().map(|ts| ts.raw()),
+            Some("2021-06-21T00:00:00Z")
+        );
+        assert_eq!(record.level, Some(Level::Error));
+        assert_eq!(record.message.map(|x| x.get()), Some(r#""b""#));
+        let fields: Vec<_> = record.fields().map(|(k, _)| *k).collect();
+        assert_eq!(fields, ["ts", "level", "msg"]);
+        let record = parser.parse(json::from_str::<RawRecord>(r#"{"severity":"warn"}"#).unwrap());
+        assert_eq!(record.level, Some(Level::Warning));
+    }
+
+    #[test]
+    fn test_time_field_detection() {
+        let settings = Settings::default();
+        let parser = Parser::new(ParserSettings::new(&settings.fields, false));
+        let sample = concat!(
+            r#"{"msg":"a","at":"2021-06-20T00:00:00Z","n":5}"#,
+            "\n",
+            r#"{"msg":"b","at":"2021-06-20T00:00:01Z","created":1624147200123}"#,
+            "\n",
+        );
+        let (name, format) = parser.detect_time_field(sample.as_bytes()).unwrap();
+        assert_eq!((&name[..], format), ("at", TimeFormat::Rfc3339));
+        let parser = parser.with_time_field(&name);
+        assert_eq!(parser.detect_time_field(sample.as_bytes()), None);
+        let record =
+            parser.parse(json::from_str::<RawRecord>(r#"{"at":"2021-06-20T00:00:00Z"}"#).unwrap());
+        assert_eq!(record.ts.map(|ts| ts.raw()), Some("2021-06-20T00:00:00Z"));
+        let sample = br#"{"created":1624147200123}"#;
+        assert_eq!(
+            Parser::new(ParserSettings::new(&settings.fields, false)).detect_time_field(sample),
+            Some(("created".into(), TimeFormat::UnixMillis))
+        );
+        let sample = br#"{"created":1624147200.123}"#;
+        assert_eq!(
+            Parser::new(ParserSettings::new(&settings.fields, false)).detect_time_field(sample),
+            Some(("created".into(), TimeFormat::UnixSeconds))
+        );
+        let sample = br#"{"created":"1624147200123.5"}"#;
+        assert_eq!(
+            Parser::new(ParserSettings::new(&settings.fields, false)).detect_time_field(sample),
+            Some(("created".into(), TimeFormat::UnixMillis))
+        );
+    }
+}
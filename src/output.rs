@@ -1,12 +1,265 @@
+use std::collections::HashMap;
 use std::env;
 use std::ffi::OsString;
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{BufWriter, Error, LineWriter, Stderr, Stdout, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use crate::error::*;
+use flate2::{write::GzEncoder, Compression};
 
-pub type OutputStream = Box<dyn Write + Send + Sync>;
+use crate::error::Result;
+
+pub type OutputStream = Box<dyn Finish + Send + Sync>;
+
+/// Writer completing the output explicitly, e.g. by writing the gzip trailer,
+/// so that errors are reported instead of being lost on drop.
+pub trait Finish: Write {
+    /// Flushes buffered data and completes the output, nothing should be written afterwards.
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W: Finish + ?Sized> Finish for Box<W> {
+    fn finish(&mut self) -> std::io::Result<()> {
+        (**self).finish()
+    }
+}
+
+impl<W: Finish> Finish for BufWriter<W> {
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.get_mut().finish()
+    }
+}
+
+impl<W: Finish> Finish for LineWriter<W> {
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.get_mut().finish()
+    }
+}
+
+impl Finish for GzEncoder<File> {
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.try_finish()
+    }
+}
+
+impl Finish for File {}
+impl Finish for Vec<u8> {}
+impl Finish for Stdout {}
+impl Finish for Stderr {}
+
+/// Creates the output file, output is compressed on the fly if the path has `.gz` extension.
+/// The gzip trailer is written by [`Finish::finish`].
+pub fn create(path: &Path) -> std::io::Result<OutputStream> {
+    let failed = |e: Error| {
+        Error::new(
+            e.kind(),
+            format!("failed to create file '{}': {}", path.display(), e),
+        )
+    };
+    let file = File::create(path).map_err(failed)?;
+    Ok(match path.extension().map(|x| x.to_str()) {
+        Some(Some("gz")) => Box::new(GzEncoder::new(file, Compression::default())),
+        _ => Box::new(file),
+    })
+}
+
+/// File output rotated once it exceeds the size limit, the current file is renamed to `<name>.1`
+/// shifting older files to `<name>.2` and so on, the index goes before the `.gz` extension of compressed files.
+/// Files are rotated at line boundaries, so a file may exceed the limit by the remainder of a line.
+/// The limit applies to uncompressed data.
+pub struct RotatingWriter {
+    path: PathBuf,
+    limit: usize,
+    size: usize,
+    at_line_start: bool,
+    inner: Option<OutputStream>,
+}
+
+impl RotatingWriter {
+    pub fn new(path: PathBuf, limit: usize) -> std::io::Result<Self> {
+        let inner = create(&path)?;
+        Ok(Self {
+            path,
+            limit,
+            size: 0,
+            at_line_start: true,
+            inner: Some(inner),
+        })
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if let Some(mut inner) = self.inner.take() {
+            inner.finish()?;
+        }
+        let mut n = 1;
+        while rotated_path(&self.path, n).exists() {
+            n += 1;
+        }
+        for i in (1..n).rev() {
+            std::fs::rename(rotated_path(&self.path, i), rotated_path(&self.path, i + 1))?;
+        }
+        std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        self.inner = Some(create(&self.path)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Finish for RotatingWriter {
+    fn finish(&mut self) -> std::io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.size >= self.limit && self.at_line_start {
+            self.rotate()?;
+        }
+        // stop at the end of the line crossing the limit, so the next write starts a new file
+        let n = match (self.size + buf.len()).checked_sub(self.limit) {
+            Some(excess) if excess != 0 => {
+                let start = (buf.len() - excess).saturating_sub(1);
+                buf[start..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(buf.len(), |i| start + i + 1)
+            }
+            _ => buf.len(),
+        };
+        let path = &self.path;
+        let inner = self
+            .inner
+            .as_mut()
+            .ok_or_else(|| Error::other(format!("failed to rotate file '{}'", path.display())))?;
+        inner.write_all(&buf[..n])?;
+        self.size += n;
+        self.at_line_start = buf[n - 1] == b'\n';
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// ---
+
+/// Output split into `<name>.log` files in a directory, each file is created when the first data for its name is written.
+/// Names are sanitized, so they cannot refer to files outside of the directory, and distinct names never share a file.
+/// All files are kept open, so the number of files is limited.
+pub struct SplitWriter {
+    dir: PathBuf,
+    max_files: usize,
+    files: HashMap<String, BufWriter<OutputStream>>,
+}
+
+impl SplitWriter {
+    pub fn new(dir: PathBuf) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("failed to create directory '{}': {}", dir.display(), e),
+            )
+        })?;
+        Ok(Self {
+            dir,
+            max_files: SPLIT_MAX_FILES,
+            files: HashMap::new(),
+        })
+    }
+
+    /// Sets the maximum number of files, writing to more files fails.
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    /// Returns the output for the given name, creating its file if it is not open yet.
+    /// The name is `None` for records missing the value to split by, they are written to `unknown.log`.
+    pub fn output(
+        &mut self,
+        name: Option<&str>,
+    ) -> std::io::Result<&mut (dyn Write + Send + Sync)> {
+        let name = split_file_name(name);
+        if !self.files.contains_key(&name) {
+            if self.files.len() >= self.max_files {
+                return Err(Error::other(format!(
+                    "too many files in '{}', at most {} files can be written with --split-by",
+                    self.dir.display(),
+                    self.max_files
+                )));
+            }
+            let file = create(&self.dir.join(format!("{}.log", name)))?;
+            self.files.insert(
+                name.clone(),
+                BufWriter::with_capacity(SPLIT_FILE_BUFFER_SIZE, file),
+            );
+        }
+        Ok(self.files.get_mut(&name).unwrap())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        for file in self.files.values_mut() {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces characters other than ASCII alphanumerics, `-`, `_` and `.` with `_`,
+/// strips leading dots and limits the length, a missing name is replaced with `unknown`.
+/// Names changed this way and the name `unknown` itself get a suffix with a hash of the name,
+/// so that different names never share a file.
+fn split_file_name(name: Option<&str>) -> String {
+    let name = match name {
+        Some(name) => name,
+        None => return "unknown".into(),
+    };
+    let sanitized: String = name
+        .trim_start_matches('.')
+        .chars()
+        .take(SPLIT_FILE_NAME_MAX_LEN)
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    if sanitized == name && name != "unknown" {
+        return sanitized;
+    }
+    // FNV-1a is used as it is stable across builds, unlike the standard hasher
+    let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{}-{:016x}", sanitized, hash)
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = match name.strip_suffix(".gz") {
+        Some(stem) => format!("{}.{}.gz", stem, index),
+        None => format!("{}.{}", name, index),
+    };
+    path.with_file_name(name)
+}
+
+// ---
 
 pub struct Pager {
     process: Child,
@@ -46,6 +299,8 @@ impl Drop for Pager {
     }
 }
 
+impl Finish for Pager {}
+
 impl Write for Pager {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         self.process.stdin.as_mut().unwrap().write(buf)
@@ -55,3 +310,178 @@ impl Write for Pager {
         self.process.stdin.as_mut().unwrap().flush()
     }
 }
+
+/// Writer passing complete lines through until the given number of bytes is written.
+/// Lines exceeding the limit and all data following them are discarded and the flag is set.
+pub struct LimitedWriter<'a, W> {
+    inner: W,
+    remaining: usize,
+    exhausted: &'a AtomicBool,
+}
+
+impl<'a, W: Write> LimitedWriter<'a, W> {
+    pub fn new(inner: W, limit: usize, exhausted: &'a AtomicBool) -> Self {
+        Self {
+            inner,
+            remaining: limit,
+            exhausted,
+        }
+    }
+}
+
+impl<'a, W: Write> Write for LimitedWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.exhausted.load(Ordering::Relaxed) {
+            return Ok(buf.len());
+        }
+        if buf.len() <= self.remaining {
+            self.inner.write_all(buf)?;
+            self.remaining -= buf.len();
+        } else {
+            let n = buf[..self.remaining]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |i| i + 1);
+            self.inner.write_all(&buf[..n])?;
+            self.remaining = 0;
+            self.exhausted.store(true, Ordering::Relaxed);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+const SPLIT_FILE_NAME_MAX_LEN: usize = 100;
+const SPLIT_FILE_BUFFER_SIZE: usize = 8 << 10;
+const SPLIT_MAX_FILES: usize = 512;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_create_gzip() {
+        let path =
+            std::env::temp_dir().join(format!("hl-test-output-{}.log.gz", std::process::id()));
+        let mut output = create(&path).unwrap();
+        output.write_all(b"hello\n").unwrap();
+        output.finish().unwrap();
+        let mut data = String::new();
+        let result = File::open(&path).and_then(|f| GzDecoder::new(f).read_to_string(&mut data));
+        result.unwrap();
+        assert_eq!(data, "hello\n");
+        drop(output);
+        let mut data = String::new();
+        let result = File::open(&path).and_then(|f| GzDecoder::new(f).read_to_string(&mut data));
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(data, "hello\n");
+    }
+
+    #[test]
+    fn test_rotating_writer() {
+        let dir = std::env::temp_dir().join(format!("hl-test-rotate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.log");
+        let mut output = RotatingWriter::new(path.clone(), 8).unwrap();
+        output.write_all(b"line 1\nline 2\nline 3\n").unwrap();
+        output.write_all(b"line 4\n").unwrap();
+        output.write_all(b"line 5\n").unwrap();
+        output.flush().unwrap();
+        drop(output);
+        let read = |name| std::fs::read_to_string(dir.join(name)).unwrap();
+        let files = (read("out.log.2"), read("out.log.1"), read("out.log"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            files,
+            (
+                "line 1\nline 2\n".into(),
+                "line 3\nline 4\n".into(),
+                "line 5\n".into()
+            )
+        );
+        assert_eq!(
+            rotated_path(Path::new("/tmp/out.log.gz"), 3),
+            Path::new("/tmp/out.log.3.gz")
+        );
+    }
+
+    #[test]
+    fn test_rotating_writer_gzip() {
+        let dir = std::env::temp_dir().join(format!("hl-test-rotate-gz-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.log.gz");
+        let mut output = RotatingWriter::new(path.clone(), 8).unwrap();
+        output.write_all(b"line 1\nline 2\nline 3\n").unwrap();
+        let mut data = String::new();
+        let result = File::open(dir.join("out.log.1.gz"))
+            .and_then(|f| GzDecoder::new(f).read_to_string(&mut data));
+        std::fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+        assert_eq!(data, "line 1\nline 2\n");
+        // rotation fails as the directory is removed, following writes fail as well
+        output.write_all(b"line 4\n").unwrap();
+        assert!(output.write_all(b"line 5\n").is_err());
+        assert!(output.write_all(b"line 6\n").is_err());
+    }
+
+    #[test]
+    fn test_split_writer() {
+        let dir = std::env::temp_dir().join(format!("hl-test-split-{}", std::process::id()));
+        let mut output = SplitWriter::new(dir.clone()).unwrap().with_max_files(3);
+        output
+            .output(Some("error"))
+            .unwrap()
+            .write_all(b"e1\n")
+            .unwrap();
+        output
+            .output(Some("info"))
+            .unwrap()
+            .write_all(b"i1\n")
+            .unwrap();
+        output
+            .output(Some("error"))
+            .unwrap()
+            .write_all(b"e2\n")
+            .unwrap();
+        output.output(None).unwrap().write_all(b"u\n").unwrap();
+        let result = output.output(Some("warning")).map(|_| ());
+        output.flush().unwrap();
+        let read = |name| std::fs::read_to_string(dir.join(name)).unwrap();
+        let files = (read("error.log"), read("info.log"), read("unknown.log"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, ("e1\ne2\n".into(), "i1\n".into(), "u\n".into()));
+        assert!(result.unwrap_err().to_string().contains("too many files"));
+        assert_eq!(split_file_name(None), "unknown");
+        assert_eq!(split_file_name(Some("eu-west-1.a")), "eu-west-1.a");
+        assert_eq!(split_file_name(Some("a_b")), "a_b");
+        let names =
+            ["../a b/", "a/b", "a b", "..", "", "unknown"].map(|x| split_file_name(Some(x)));
+        assert!(names[0].starts_with("_a_b_-"));
+        assert!(names[3].starts_with('-'));
+        assert!(names[5].starts_with("unknown-"));
+        let mut unique = names.to_vec();
+        unique.extend(["a_b".into(), "unknown".into()]);
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), names.len() + 2);
+    }
+
+    #[test]
+    fn test_limited_writer() {
+        let exhausted = AtomicBool::new(false);
+        let mut output = LimitedWriter::new(Vec::new(), 12, &exhausted);
+        output.write_all(b"line 1\n").unwrap();
+        assert!(!exhausted.load(Ordering::Relaxed));
+        output.write_all(b"2\nline 3\n").unwrap();
+        assert!(exhausted.load(Ordering::Relaxed));
+        output.write_all(b"4\n").unwrap();
+        assert_eq!(output.inner, b"line 1\n2\n");
+    }
+}
@@ -0,0 +1,118 @@
+// std imports
+use std::io::{Result, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// ---
+
+/// Processing pipeline stage.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stage {
+    Reading,
+    Processing,
+    Writing,
+}
+
+/// Time spent in each pipeline stage aggregated over all threads, along with processed data volume.
+#[derive(Debug, Default)]
+pub struct Profile {
+    reading: AtomicU64,
+    processing: AtomicU64,
+    writing: AtomicU64,
+    bytes: AtomicU64,
+    records: AtomicU64,
+}
+
+impl Profile {
+    /// Runs the given function and accounts its duration to the given stage.
+    pub fn measure<T, F: FnOnce() -> T>(&self, stage: Stage, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed().as_nanos() as u64;
+        self.counter(stage).fetch_add(elapsed, Ordering::Relaxed);
+        result
+    }
+
+    /// Accounts the given number of input bytes.
+    pub fn add_bytes(&self, n: usize) {
+        self.bytes.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Accounts the given number of processed records.
+    pub fn add_records(&self, n: u64) {
+        self.records.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns total time spent in the given stage by all threads.
+    pub fn duration(&self, stage: Stage) -> Duration {
+        Duration::from_nanos(self.counter(stage).load(Ordering::Relaxed))
+    }
+
+    /// Writes a human-readable report given the wall time of the whole run.
+    pub fn write<W: Write + ?Sized>(&self, out: &mut W, wall: Duration) -> Result<()> {
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let records = self.records.load(Ordering::Relaxed);
+        let seconds = wall.as_secs_f64();
+        writeln!(out, "profile: wall time {:.3}s", seconds)?;
+        for (name, stage) in [
+            ("reading", Stage::Reading),
+            ("processing", Stage::Processing),
+            ("writing", Stage::Writing),
+        ] {
+            writeln!(
+                out,
+                "profile: {:<10} {:.3}s",
+                name,
+                self.duration(stage).as_secs_f64()
+            )?;
+        }
+        let rate = |n: u64| {
+            if seconds > 0.0 {
+                n as f64 / seconds
+            } else {
+                0.0
+            }
+        };
+        writeln!(
+            out,
+            "profile: {} bytes ({:.1} MiB/s), {} records ({:.0}/s)",
+            bytes,
+            rate(bytes) / (1024.0 * 1024.0),
+            records,
+            rate(records)
+        )
+    }
+
+    fn counter(&self, stage: Stage) -> &AtomicU64 {
+        match stage {
+            Stage::Reading => &self.reading,
+            Stage::Processing => &self.processing,
+            Stage::Writing => &self.writing,
+        }
+    }
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let profile = Profile::default();
+        let value = profile.measure(Stage::Processing, || {
+            std::thread::sleep(Duration::from_millis(2));
+            42
+        });
+        assert_eq!(value, 42);
+        assert!(profile.duration(Stage::Processing) >= Duration::from_millis(2));
+        assert_eq!(profile.duration(Stage::Reading), Duration::ZERO);
+        profile.add_bytes(2048);
+        profile.add_records(2);
+        let mut buf = Vec::new();
+        profile.write(&mut buf, Duration::from_secs(2)).unwrap();
+        let report = String::from_utf8(buf).unwrap();
+        assert!(report.ends_with("profile: 2048 bytes (0.0 MiB/s), 2 records (1/s)\n"));
+    }
+}
@@ -0,0 +1,88 @@
+// std imports
+use std::time::{Duration, Instant};
+
+// ---
+
+/// Action taken on records exceeding the output rate limit.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum RateOverflow {
+    Drop,
+    #[default]
+    Queue,
+}
+
+// ---
+
+/// Token bucket limiting the number of events per second, allowing bursts of up to one second worth of events.
+pub struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u32) -> Self {
+        let rate = rate.max(1) as f64;
+        Self {
+            rate,
+            tokens: rate,
+            last: Instant::now(),
+        }
+    }
+
+    /// Takes a token if one is available.
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    /// Takes a token, waiting for it to become available if necessary.
+    pub fn acquire(&mut self) {
+        while !self.try_acquire() {
+            std::thread::sleep(self.wait_time());
+        }
+    }
+
+    fn try_acquire_at(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn wait_time(&self) -> Duration {
+        Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.rate)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+    }
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(2);
+        let start = limiter.last;
+        assert!(limiter.try_acquire_at(start));
+        assert!(limiter.try_acquire_at(start));
+        assert!(!limiter.try_acquire_at(start));
+        assert_eq!(limiter.wait_time(), Duration::from_millis(500));
+        assert!(!limiter.try_acquire_at(start + Duration::from_millis(400)));
+        assert!(limiter.try_acquire_at(start + Duration::from_millis(600)));
+        // tokens do not accumulate beyond one second worth of events
+        let later = start + Duration::from_secs(10);
+        assert!(limiter.try_acquire_at(later));
+        assert!(limiter.try_acquire_at(later));
+        assert!(!limiter.try_acquire_at(later));
+    }
+}
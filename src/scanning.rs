@@ -48,13 +48,6 @@ impl SegmentBuf {
         &self.data[..self.size]
     }
 
-    /// Converts the SegmentBuf to a Vec<u8>.
-    #[inline(always)]
-    pub fn to_vec(mut self) -> Vec<u8> {
-        self.data.resize(self.size, 0);
-        self.data
-    }
-
     #[inline]
     fn new(capacity: usize) -> Self {
         let mut data = Vec::with_capacity(capacity);
@@ -353,7 +346,6 @@ impl<'a, 'b> Iterator for ScannerIter<'a, 'b> {
             };
 
             let result = self.next.replace(next);
-            self.placement = placement;
             return if result.size != 0 {
                 Some(Ok(Segment::new(result, placement)))
             } else {
@@ -373,6 +365,7 @@ pub struct ScannerJumboIter<'a, 'b> {
     max_segment_size: usize,
     fetched: VecDeque<(SegmentBuf, PartialPlacement)>,
     next: Option<Result<Segment>>,
+    oversized: bool,
 }
 
 impl<'a, 'b> ScannerJumboIter<'a, 'b> {
@@ -382,6 +375,7 @@ impl<'a, 'b> ScannerJumboIter<'a, 'b> {
             max_segment_size,
             fetched: VecDeque::new(),
             next: None,
+            oversized: false,
         };
     }
 
@@ -416,6 +410,15 @@ impl<'a, 'b> Iterator for ScannerJumboIter<'a, 'b> {
             if let Some(next) = self.next.take() {
                 return Some(next);
             }
+            // remaining parts of a token exceeding max_segment_size are never joined
+            if self.oversized {
+                let next = self.inner.next();
+                self.oversized = matches!(
+                    next,
+                    Some(Ok(Segment::Incomplete(_, placement))) if placement != PartialPlacement::Last
+                );
+                return next;
+            }
 
             let mut total = 0;
             loop {
@@ -433,6 +436,7 @@ impl<'a, 'b> Iterator for ScannerJumboIter<'a, 'b> {
                     }
                 };
                 if total > self.max_segment_size {
+                    self.oversized = true;
                     break;
                 }
             }
@@ -538,4 +542,29 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn test_jumbo_2() {
+        let sf = Arc::new(SegmentBufFactory::new(2));
+        let scanner = Scanner::new(sf.clone(), "/".into());
+        let mut data = std::io::Cursor::new(b"ab/0123456789/cd/");
+        let tokens = scanner
+            .items(&mut data)
+            .with_max_segment_size(3)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Segment::Complete(b"ab/".into()),
+                Segment::Incomplete(b"01".into(), PartialPlacement::First),
+                Segment::Incomplete(b"23".into(), PartialPlacement::Next),
+                Segment::Incomplete(b"45".into(), PartialPlacement::Next),
+                Segment::Incomplete(b"67".into(), PartialPlacement::Next),
+                Segment::Incomplete(b"89".into(), PartialPlacement::Next),
+                Segment::Incomplete(b"/".into(), PartialPlacement::Last),
+                Segment::Complete(b"cd/".into()),
+            ]
+        )
+    }
 }
@@ -27,6 +27,8 @@ pub struct Settings {
     pub time_format: String,
     pub time_zone: Tz,
     pub theme: String,
+    #[serde(default)]
+    pub min_level: Option<Level>,
 }
 
 impl Settings {
@@ -53,10 +55,65 @@ impl Default for Settings {
 // ---
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Fields {
     pub predefined: PrefedinedFields,
     pub ignore: Vec<String>,
     pub hide: Vec<String>,
+    #[serde(default)]
+    pub empty: EmptyValues,
+    #[serde(default)]
+    pub status_levels: Vec<StatusLevel>,
+}
+
+// ---
+
+/// Range of numeric status codes mapped to a level, the bounds are inclusive.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StatusLevel {
+    pub from: u64,
+    pub to: u64,
+    pub level: Level,
+}
+
+// ---
+
+/// Field values treated as empty and hidden when hiding of empty fields is enabled.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EmptyValues {
+    /// Raw JSON values compared exactly.
+    pub values: Vec<String>,
+    /// Treat strings consisting of whitespace only as empty.
+    #[serde(default)]
+    pub blank_strings: bool,
+}
+
+impl EmptyValues {
+    /// Returns true if the given raw JSON value is considered empty.
+    pub fn contains(&self, value: &str) -> bool {
+        if self.values.iter().any(|v| v == value) {
+            return true;
+        }
+        if self.blank_strings && value.starts_with('"') {
+            if let Ok(value) = serde_json::from_str::<&str>(value) {
+                return value.trim().is_empty();
+            }
+            if let Ok(value) = serde_json::from_str::<String>(value) {
+                return value.trim().is_empty();
+            }
+        }
+        false
+    }
+}
+
+impl Default for EmptyValues {
+    fn default() -> Self {
+        Self {
+            values: vec![r#""""#.into(), "null".into(), "{}".into(), "[]".into()],
+            blank_strings: false,
+        }
+    }
 }
 
 // ---
@@ -70,6 +127,32 @@ pub struct PrefedinedFields {
     pub caller: CallerField,
 }
 
+impl PrefedinedFields {
+    /// Replaces names of the time field.
+    pub fn set_time_names(&mut self, names: Vec<String>) {
+        self.time.0.names = names;
+    }
+
+    /// Replaces names of the level field.
+    /// Values of all configured variants are merged into a single variant with the given names.
+    pub fn set_level_names(&mut self, names: Vec<String>) {
+        let mut values = HashMap::<Level, Vec<String>>::new();
+        for variant in self.level.variants.drain(..) {
+            for (level, items) in variant.values {
+                values.entry(level).or_default().extend(items);
+            }
+        }
+        self.level
+            .variants
+            .push(LevelFieldVariant { names, values });
+    }
+
+    /// Replaces names of the message field.
+    pub fn set_message_names(&mut self, names: Vec<String>) {
+        self.message.0.names = names;
+    }
+}
+
 // ---
 
 #[derive(Debug, Deserialize, Deref)]
@@ -102,8 +185,13 @@ pub struct LoggerField(Field);
 
 // ---
 
-#[derive(Debug, Deserialize, Deref)]
-pub struct CallerField(Field);
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CallerField {
+    pub names: Vec<String>,
+    #[serde(default)]
+    pub function_names: Vec<String>,
+}
 
 // ---
 
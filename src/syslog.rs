@@ -0,0 +1,436 @@
+//! Conversion of syslog RFC 5424 and CEF lines to JSON records, so they can be processed as regular JSON input.
+//!
+//! Supported subset and known limitations:
+//! * RFC 5424 header fields are mapped to `ts`, `level`, `host`, `app`, `pid` and `msgid`,
+//!   nil values (`-`) are omitted, each structured data element becomes an object field named by its SD-ID,
+//! * RFC 3164 (BSD) syslog lines are not supported,
+//! * CEF header fields are mapped to `msg` (name), `level` (severity), `vendor`, `product`, `version`,
+//!   `signature` and `severity`, extension key-value pairs become fields with `rt` mapped to `ts`,
+//!   if there is a `msg` extension, it is used as `msg` and the name is mapped to `name`,
+//! * anything before `CEF:` on the line, such as a syslog header, is ignored,
+//! * CEF extension keys are expected to consist of alphanumeric characters, `_`, `.`, `-`, `[` and `]`.
+
+// third-party imports
+use serde_json as json;
+
+// ---
+
+/// Converts a syslog RFC 5424 line to a JSON object, returns false if the line is not a valid RFC 5424 message.
+pub fn rfc5424_to_json(line: &[u8], buf: &mut Vec<u8>) -> bool {
+    let n = buf.len();
+    let result = std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| Rfc5424Writer::new(buf).write(line));
+    if result.is_none() {
+        buf.truncate(n);
+    }
+    result.is_some()
+}
+
+/// Converts a CEF line to a JSON object, returns false if the line does not contain a valid CEF message.
+pub fn cef_to_json(line: &[u8], buf: &mut Vec<u8>) -> bool {
+    let n = buf.len();
+    let result = std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| write_cef(buf, line));
+    if result.is_none() {
+        buf.truncate(n);
+    }
+    result.is_some()
+}
+
+// ---
+
+struct Rfc5424Writer<'a> {
+    object: ObjectWriter<'a>,
+}
+
+impl<'a> Rfc5424Writer<'a> {
+    fn new(buf: &'a mut Vec<u8>) -> Self {
+        Self {
+            object: ObjectWriter::new(buf),
+        }
+    }
+
+    fn write(mut self, line: &str) -> Option<()> {
+        let line = line.trim_end_matches('\r');
+        let (pri, rest) = line.strip_prefix('<')?.split_once('>')?;
+        if pri.is_empty() || pri.len() > 3 || !pri.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let pri: u8 = pri.parse().ok().filter(|&pri| pri <= 191)?;
+        let (version, rest) = rest.split_once(' ')?;
+        if version != "1" {
+            return None;
+        }
+        let mut header = rest.splitn(6, ' ');
+        let ts = header.next()?;
+        let host = header.next()?;
+        let app = header.next()?;
+        let pid = header.next()?;
+        let msgid = header.next()?;
+        let rest = header.next()?;
+
+        self.object.string_opt("ts", nil(ts));
+        self.object.string("level", syslog_level(pri % 8));
+        let (sd, msg) = split_structured_data(rest)?;
+        if let Some(msg) = msg {
+            self.object
+                .string("msg", msg.trim_start_matches('\u{feff}'));
+        }
+        self.object.string_opt("host", nil(host));
+        self.object.string_opt("app", nil(app));
+        self.object.string_opt("pid", nil(pid));
+        self.object.string_opt("msgid", nil(msgid));
+        self.object.number("facility", (pri / 8).into());
+        self.object.number("severity", (pri % 8).into());
+        if let Some(mut sd) = sd {
+            while let Some(element) = sd.strip_prefix('[') {
+                sd = self.write_sd_element(element)?;
+            }
+        }
+        self.object.end();
+        Some(())
+    }
+
+    fn write_sd_element<'s>(&mut self, element: &'s str) -> Option<&'s str> {
+        let end = element.find([' ', ']'])?;
+        let (id, mut rest) = element.split_at(end);
+        self.object.key(id);
+        let mut params = ObjectWriter::new(self.object.buf);
+        loop {
+            if let Some(rest) = rest.strip_prefix(']') {
+                params.end();
+                return Some(rest);
+            }
+            let (name, value) = rest.strip_prefix(' ')?.split_once("=\"")?;
+            let (value, tail) = split_quoted(value)?;
+            params.string(name, &value);
+            rest = tail;
+        }
+    }
+}
+
+// ---
+
+fn write_cef(buf: &mut Vec<u8>, line: &str) -> Option<()> {
+    let line = line.trim_end_matches('\r');
+    let start = line.find("CEF:")?;
+    let mut header = Vec::with_capacity(7);
+    let mut rest = &line[start + 4..];
+    while header.len() != 7 {
+        let (field, tail) = split_cef_header_field(rest)?;
+        header.push(field);
+        rest = tail;
+    }
+    let extensions = cef_extensions(rest);
+
+    let mut object = ObjectWriter::new(buf);
+    if let Some((_, value)) = extensions.iter().find(|(key, _)| *key == "rt") {
+        object.string("ts", value);
+    }
+    if let Some(level) = cef_level(&header[6]) {
+        object.string("level", level);
+    }
+    match extensions.iter().find(|(key, _)| *key == "msg") {
+        Some((_, msg)) => {
+            object.string("msg", msg);
+            object.string("name", &header[5]);
+        }
+        None => object.string("msg", &header[5]),
+    }
+    object.string("vendor", &header[1]);
+    object.string("product", &header[2]);
+    object.string("version", &header[3]);
+    object.string("signature", &header[4]);
+    object.string("severity", &header[6]);
+    for (key, value) in &extensions {
+        if *key != "rt" && *key != "msg" {
+            object.string(key, value);
+        }
+    }
+    object.end();
+    Some(())
+}
+
+fn split_cef_header_field(text: &str) -> Option<(String, &str)> {
+    let mut field = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, c)) => field.push(c),
+                None => field.push('\\'),
+            },
+            '|' => return Some((field, &text[i + 1..])),
+            _ => field.push(c),
+        }
+    }
+    None
+}
+
+fn cef_extensions(text: &str) -> Vec<(&str, String)> {
+    let bytes = text.as_bytes();
+    // find positions of unescaped `=` preceded by a key starting the text or following a space
+    let mut keys = Vec::new();
+    let mut escaped = false;
+    for (i, &b) in bytes.iter().enumerate() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match b {
+            b'\\' => escaped = true,
+            b'=' => {
+                let start = bytes[..i]
+                    .iter()
+                    .rposition(|&b| !is_cef_key_char(b))
+                    .map(|p| p + 1)
+                    .unwrap_or(0);
+                if start != i && (start == 0 || bytes[start - 1] == b' ') {
+                    keys.push((start, i));
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut result = Vec::with_capacity(keys.len());
+    for (j, &(start, eq)) in keys.iter().enumerate() {
+        let end = match keys.get(j + 1) {
+            Some(&(next, _)) => next,
+            None => bytes.len(),
+        };
+        let value = text[eq + 1..end].trim_end_matches(' ');
+        result.push((&text[start..eq], unescape_cef_value(value)));
+    }
+    result
+}
+
+fn is_cef_key_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'-' | b'[' | b']')
+}
+
+fn unescape_cef_value(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+fn cef_level(severity: &str) -> Option<&'static str> {
+    match severity.trim().to_ascii_lowercase().as_str() {
+        "low" => Some("info"),
+        "medium" => Some("warning"),
+        "high" | "very-high" => Some("error"),
+        severity => match severity.parse::<u8>().ok()? {
+            0..=3 => Some("info"),
+            4..=6 => Some("warning"),
+            7..=10 => Some("error"),
+            _ => None,
+        },
+    }
+}
+
+// ---
+
+/// Maps syslog severity to a level name the same way as the default configuration maps journald `PRIORITY` values.
+fn syslog_level(severity: u8) -> &'static str {
+    match severity {
+        7 => "debug",
+        6 => "info",
+        4 | 5 => "warning",
+        _ => "error",
+    }
+}
+
+fn nil(value: &str) -> Option<&str> {
+    if value == "-" {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Splits the remainder of an RFC 5424 header into structured data and message.
+fn split_structured_data(text: &str) -> Option<(Option<&str>, Option<&str>)> {
+    let (sd, msg) = if let Some(rest) = text.strip_prefix('-') {
+        (None, rest)
+    } else if text.starts_with('[') {
+        let end = structured_data_end(text)?;
+        (Some(&text[..end]), &text[end..])
+    } else {
+        return None;
+    };
+    let msg = match msg {
+        "" => None,
+        msg => Some(msg.strip_prefix(' ')?),
+    };
+    Some((sd, msg))
+}
+
+fn structured_data_end(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while bytes.get(i) == Some(&b'[') {
+        let mut quoted = false;
+        loop {
+            i += 1;
+            match bytes.get(i)? {
+                b'\\' if quoted => i += 1,
+                b'"' => quoted = !quoted,
+                b']' if !quoted => break,
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    Some(i)
+}
+
+/// Splits a quoted parameter value at the closing quote, returning the unescaped value and the rest after the quote.
+fn split_quoted(text: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                (_, c @ ('"' | '\\' | ']')) => value.push(c),
+                (_, c) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+            },
+            '"' => return Some((value, &text[i + 1..])),
+            _ => value.push(c),
+        }
+    }
+    None
+}
+
+// ---
+
+/// Writes a JSON object field by field.
+pub(crate) struct ObjectWriter<'a> {
+    buf: &'a mut Vec<u8>,
+    first: bool,
+}
+
+impl<'a> ObjectWriter<'a> {
+    pub(crate) fn new(buf: &'a mut Vec<u8>) -> Self {
+        buf.push(b'{');
+        Self { buf, first: true }
+    }
+
+    fn key(&mut self, key: &str) {
+        if !self.first {
+            self.buf.push(b',');
+        }
+        self.first = false;
+        json::to_writer(&mut *self.buf, key).ok();
+        self.buf.push(b':');
+    }
+
+    pub(crate) fn string(&mut self, key: &str, value: &str) {
+        self.key(key);
+        json::to_writer(&mut *self.buf, value).ok();
+    }
+
+    pub(crate) fn string_opt(&mut self, key: &str, value: Option<&str>) {
+        if let Some(value) = value {
+            self.string(key, value);
+        }
+    }
+
+    pub(crate) fn number(&mut self, key: &str, value: u64) {
+        self.key(key);
+        self.buf.extend_from_slice(value.to_string().as_bytes());
+    }
+
+    pub(crate) fn end(self) {
+        self.buf.push(b'}');
+    }
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(f: fn(&[u8], &mut Vec<u8>) -> bool, line: &str) -> Option<String> {
+        let mut buf = Vec::new();
+        if f(line.as_bytes(), &mut buf) {
+            Some(String::from_utf8(buf).unwrap())
+        } else {
+            assert!(buf.is_empty());
+            None
+        }
+    }
+
+    #[test]
+    fn test_rfc5424() {
+        assert_eq!(
+            convert(
+                rfc5424_to_json,
+                r#"<165>1 2003-10-11T22:14:15.003Z host.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="App\"lication\]"][meta x="1"] An application event"#
+            )
+            .unwrap(),
+            concat!(
+                r#"{"ts":"2003-10-11T22:14:15.003Z","level":"warning","msg":"An application event","#,
+                r#""host":"host.example.com","app":"evntslog","msgid":"ID47","facility":20,"severity":5,"#,
+                r#""exampleSDID@32473":{"iut":"3","eventSource":"App\"lication]"},"meta":{"x":"1"}}"#,
+            )
+        );
+        assert_eq!(
+            convert(rfc5424_to_json, "<11>1 - - app 42 - -").unwrap(),
+            r#"{"level":"error","app":"app","pid":"42","facility":1,"severity":3}"#
+        );
+        assert_eq!(
+            convert(rfc5424_to_json, "<191>1 - - - - - - \u{feff}hello").unwrap(),
+            r#"{"level":"debug","msg":"hello","facility":23,"severity":7}"#
+        );
+        assert_eq!(convert(rfc5424_to_json, "<192>1 - - - - - -"), None);
+        assert_eq!(
+            convert(
+                rfc5424_to_json,
+                "<34>Oct 11 22:14:15 mymachine su: 'su root' failed"
+            ),
+            None
+        );
+        assert_eq!(convert(rfc5424_to_json, "<13>1 - - - - - [x a=\"1\""), None);
+        assert_eq!(convert(rfc5424_to_json, r#"{"msg":"json"}"#), None);
+    }
+
+    #[test]
+    fn test_cef() {
+        assert_eq!(
+            convert(
+                cef_to_json,
+                r#"Sep 19 08:26:10 host CEF:0|Security|threat\|manager|1.0|100|worm successfully stopped|10|src=10.0.0.1 dst=2.1.2.2 msg=Detected a threat. No action needed. a\=b rt=1695111970000 cs1=x\\y\nz"#
+            )
+            .unwrap(),
+            concat!(
+                r#"{"ts":"1695111970000","level":"error","msg":"Detected a threat. No action needed. a=b","#,
+                r#""name":"worm successfully stopped","vendor":"Security","product":"threat|manager","#,
+                r#""version":"1.0","signature":"100","severity":"10","src":"10.0.0.1","dst":"2.1.2.2","cs1":"x\\y\nz"}"#,
+            )
+        );
+        assert_eq!(
+            convert(cef_to_json, "CEF:0|v|p|1|sig|name|Medium|").unwrap(),
+            r#"{"level":"warning","msg":"name","vendor":"v","product":"p","version":"1","signature":"sig","severity":"Medium"}"#
+        );
+        assert_eq!(convert(cef_to_json, "CEF:0|v|p|1|sig|name"), None);
+        assert_eq!(convert(cef_to_json, "plain text"), None);
+    }
+}
@@ -0,0 +1,56 @@
+// std imports
+use std::io::{Read, Result};
+
+// local imports
+use crate::output::Finish;
+
+// ---
+
+/// Reader that copies all data it reads from the inner reader to the given writer.
+/// The writer is finished once the end of the inner reader is reached.
+pub struct TeeReader<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Finish> TeeReader<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        Self { reader, writer }
+    }
+
+    /// Returns the inner reader and writer.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: Read, W: Finish> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.writer.write_all(&buf[..n])?;
+        if n == 0 {
+            self.writer.finish()?;
+        }
+        Ok(n)
+    }
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tee_reader() {
+        let mut reader = TeeReader::new(&b"line 1\nline 2\n"[..], Vec::new());
+        let mut head = [0; 4];
+        reader.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"line");
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b" 1\nline 2\n");
+        let (_, copy) = reader.into_inner();
+        assert_eq!(copy, b"line 1\nline 2\n");
+    }
+}
@@ -0,0 +1,176 @@
+// std imports
+use std::env;
+use std::time::Duration;
+
+// ---
+
+/// Terminal background brightness.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Background {
+    Dark,
+    Light,
+}
+
+/// Detects terminal background brightness.
+///
+/// Queries the terminal using OSC 11 sequence and waits for the response up to the given timeout.
+/// Falls back to `COLORFGBG` environment variable if the terminal does not respond.
+pub fn detect(timeout: Duration) -> Option<Background> {
+    query(timeout)
+        .and_then(|response| parse_response(&response))
+        .map(|(r, g, b)| classify(r, g, b))
+        .or_else(from_env)
+}
+
+/// Detects terminal background brightness using `COLORFGBG` environment variable.
+pub fn from_env() -> Option<Background> {
+    parse_colorfgbg(&env::var("COLORFGBG").ok()?)
+}
+
+// ---
+
+fn classify(r: f64, g: f64, b: f64) -> Background {
+    if luminance(r, g, b) > 0.5 {
+        Background::Light
+    } else {
+        Background::Dark
+    }
+}
+
+/// Returns relative luminance of the given color with components in range [0, 1].
+fn luminance(r: f64, g: f64, b: f64) -> f64 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Parses OSC 11 response like `\x1b]11;rgb:ffff/ffff/ffff\x1b\\` into color components in range [0, 1].
+fn parse_response(response: &[u8]) -> Option<(f64, f64, f64)> {
+    let response = std::str::from_utf8(response).ok()?;
+    let start = response.find("rgb:")? + 4;
+    let body = &response[start..];
+    let end = body.find(['\x1b', '\x07']).unwrap_or(body.len());
+    let mut components = body[..end].split('/').map(parse_component);
+    let r = components.next()??;
+    let g = components.next()??;
+    let b = components.next()??;
+    if components.next().is_some() {
+        return None;
+    }
+    Some((r, g, b))
+}
+
+fn parse_component(s: &str) -> Option<f64> {
+    if s.is_empty() || s.len() > 4 {
+        return None;
+    }
+    let value = u16::from_str_radix(s, 16).ok()?;
+    let max = (1u32 << (4 * s.len())) - 1;
+    Some(value as f64 / max as f64)
+}
+
+/// Parses `COLORFGBG` value like `15;0` where the last component is the background color index.
+fn parse_colorfgbg(value: &str) -> Option<Background> {
+    let bg = value.rsplit(';').next()?.parse::<u8>().ok()?;
+    match bg {
+        0..=6 | 8 => Some(Background::Dark),
+        7 | 9..=15 => Some(Background::Light),
+        _ => None,
+    }
+}
+
+#[cfg(unix)]
+fn query(timeout: Duration) -> Option<Vec<u8>> {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::time::Instant;
+
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    let fd = tty.as_raw_fd();
+
+    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+        return None;
+    }
+    let mut raw = original;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    raw.c_cc[libc::VMIN] = 0;
+    raw.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+        return None;
+    }
+
+    let mut response = Vec::new();
+    let result = (|| {
+        tty.write_all(b"\x1b]11;?\x1b\\").ok()?;
+        tty.flush().ok()?;
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0; 64];
+        loop {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            let mut pfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ms = remaining.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+            if unsafe { libc::poll(&mut pfd, 1, ms) } <= 0 {
+                return None;
+            }
+            let n = tty.read(&mut buf).ok()?;
+            if n == 0 {
+                return None;
+            }
+            response.extend_from_slice(&buf[..n]);
+            if response.ends_with(b"\x07") || response.ends_with(b"\x1b\\") {
+                return Some(());
+            }
+        }
+    })();
+
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    result.map(|_| response)
+}
+
+#[cfg(not(unix))]
+fn query(_: Duration) -> Option<Vec<u8>> {
+    None
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response(b"\x1b]11;rgb:ffff/8080/0000\x1b\\"),
+            Some((1.0, 32896.0 / 65535.0, 0.0))
+        );
+        assert_eq!(
+            parse_response(b"\x1b]11;rgb:ff/00/00\x07"),
+            Some((1.0, 0.0, 0.0))
+        );
+        assert_eq!(parse_response(b"\x1b]11;rgb:ff/00\x07"), None);
+        assert_eq!(parse_response(b"garbage"), None);
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(1.0, 1.0, 1.0), Background::Light);
+        assert_eq!(classify(0.1, 0.1, 0.1), Background::Dark);
+        assert_eq!(classify(0.0, 0.0, 1.0), Background::Dark);
+    }
+
+    #[test]
+    fn test_parse_colorfgbg() {
+        assert_eq!(parse_colorfgbg("15;0"), Some(Background::Dark));
+        assert_eq!(parse_colorfgbg("0;default;15"), Some(Background::Light));
+        assert_eq!(parse_colorfgbg("default"), None);
+    }
+}
@@ -24,6 +24,14 @@ pub trait StylingPush<B: Push<u8>> {
     fn element<R, F: FnOnce(&mut Self) -> R>(&mut self, element: Element, f: F) -> R;
     fn batch<F: FnOnce(&mut B)>(&mut self, f: F);
     fn space(&mut self);
+    /// Pushes data keeping the style of previously pushed data, even if the current style differs.
+    fn raw<F: FnOnce(&mut B)>(&mut self, f: F);
+}
+
+/// Styling push that can discard data pushed by a closure.
+pub trait StylingTransact<B: Push<u8>>: StylingPush<B> {
+    /// Calls `f` and discards everything it pushed if it returns false.
+    fn transact<F: FnOnce(&mut Self) -> bool>(&mut self, f: F) -> bool;
 }
 
 // ---
@@ -61,24 +69,46 @@ impl Theme {
     ) {
         let mut styler = Styler {
             buf,
-            pack: match level {
-                Some(level) => &self.packs[*level],
-                None => &self.default,
-            },
+            pack: self.pack(level),
             synced: None,
             current: None,
         };
         f(&mut styler);
     }
+
+    /// Returns the escape sequence emitted for the element in records with the given level,
+    /// the sequence is empty if the element is not styled and keeps the style of the enclosing element.
+    pub fn sequence_for(&self, level: &Option<Level>, element: Element) -> &[u8] {
+        let pack = self.pack(level);
+        match pack.elements[element] {
+            Some(i) => pack.styles[i].0.data(),
+            None => &[],
+        }
+    }
+
+    fn pack(&self, level: &Option<Level>) -> &StylePack {
+        match level {
+            Some(level) => &self.packs[*level],
+            None => &self.default,
+        }
+    }
 }
 
+/// Styles are resolved with the following fallback chain:
+/// * style of the element in the pack of the record level,
+/// * style of the element in the default pack,
+/// * no styling, so the element keeps the style of the enclosing element, if any,
+///   or is rendered with the terminal default style otherwise.
 impl<S: Borrow<themecfg::Theme>> From<S> for Theme {
     fn from(s: S) -> Self {
         let s = s.borrow();
         let default = StylePack::load(&s.elements);
         let mut packs = EnumMap::default();
-        for (level, pack) in &s.levels {
-            packs[*level] = StylePack::load(&s.elements.clone().merged(pack.clone()));
+        for (level, pack) in packs.iter_mut() {
+            *pack = match s.levels.get(&level) {
+                Some(patch) => StylePack::load(&s.elements.clone().merged(patch.clone())),
+                None => default.clone(),
+            };
         }
         Self { default, packs }
     }
@@ -220,7 +250,7 @@ impl<'a, B: Push<u8>> StylingPush<B> for Styler<'a, B> {
         let style = self.current;
         self.set(element);
         let result = f(self);
-        self.set_style(style);
+        self.current = style;
         result
     }
     #[inline(always)]
@@ -232,11 +262,31 @@ impl<'a, B: Push<u8>> StylingPush<B> for Styler<'a, B> {
         self.sync();
         f(self.buf)
     }
+    #[inline(always)]
+    fn raw<F: FnOnce(&mut B)>(&mut self, f: F) {
+        f(self.buf)
+    }
+}
+
+impl<'a> StylingTransact<Vec<u8>> for Styler<'a, Vec<u8>> {
+    #[inline(always)]
+    fn transact<F: FnOnce(&mut Self) -> bool>(&mut self, f: F) -> bool {
+        let len = self.buf.len();
+        let synced = self.synced;
+        let current = self.current;
+        if f(self) {
+            return true;
+        }
+        self.buf.truncate(len);
+        self.synced = synced;
+        self.current = current;
+        false
+    }
 }
 
 // ---
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct StylePack {
     elements: EnumMap<Element, Option<usize>>,
     reset: Option<usize>,
@@ -245,14 +295,17 @@ struct StylePack {
 
 impl StylePack {
     fn add(&mut self, element: Element, style: &Style) {
-        let pos = match self.styles.iter().position(|x| x == style) {
+        self.elements[element] = Some(self.intern(style));
+    }
+
+    fn intern(&mut self, style: &Style) -> usize {
+        match self.styles.iter().position(|x| x == style) {
             Some(pos) => pos,
             None => {
                 self.styles.push(style.clone());
                 self.styles.len() - 1
             }
-        };
-        self.elements[element] = Some(pos);
+        }
     }
 
     fn load(s: &themecfg::StylePack) -> Self {
@@ -260,6 +313,8 @@ impl StylePack {
         for (&element, style) in s.items() {
             result.add(element, &Style::from(style))
         }
+        // unstyled content following styled content must not inherit its style
+        result.reset = Some(result.intern(&Style::reset()));
         result
     }
 }
@@ -280,4 +335,84 @@ mod tests {
             });
         });
     }
+
+    #[test]
+    fn test_fallback() {
+        let cfg: themecfg::Theme = serde_yaml::from_str(
+            "elements: {message: {foreground: red}}\nlevels: {error: {level: {foreground: blue}}}",
+        )
+        .unwrap();
+        let theme = Theme::from(cfg);
+        let render = |level| {
+            let mut buf = Vec::new();
+            theme.apply(&mut buf, &level, |s| {
+                s.element(Element::Level, |s| {
+                    s.element(Element::LevelInner, |s| s.batch(|buf| buf.push(b'l')))
+                });
+                s.element(Element::Message, |s| s.batch(|buf| buf.push(b'm')));
+                s.element(Element::Key, |s| s.batch(|buf| buf.push(b'k')));
+            });
+            String::from_utf8(buf).unwrap()
+        };
+        // level without a pack uses the default pack, unstyled element after a styled one is reset
+        assert_eq!(render(Some(Level::Info)), "l\x1b[0;31mm\x1b[0mk");
+        assert_eq!(render(None), "l\x1b[0;31mm\x1b[0mk");
+        // nested element without a style keeps the style of the enclosing element
+        assert_eq!(render(Some(Level::Error)), "\x1b[0;34ml\x1b[0;31mm\x1b[0mk");
+    }
+
+    #[test]
+    fn test_sequence_for() {
+        let cfg: themecfg::Theme = serde_yaml::from_str(
+            "elements: {message: {foreground: red, modes: [bold]}}\nlevels: {error: {message: {foreground: 196}}}",
+        )
+        .unwrap();
+        let theme = Theme::from(cfg);
+        assert_eq!(theme.sequence_for(&None, Element::Message), b"\x1b[0;1;31m");
+        assert_eq!(
+            theme.sequence_for(&Some(Level::Info), Element::Message),
+            b"\x1b[0;1;31m"
+        );
+        assert_eq!(
+            theme.sequence_for(&Some(Level::Error), Element::Message),
+            b"\x1b[0;38;5;196m"
+        );
+        assert_eq!(theme.sequence_for(&None, Element::Key), b"");
+        assert_eq!(Theme::none().sequence_for(&None, Element::Message), b"");
+    }
+
+    #[test]
+    fn test_color_depth() {
+        let cfg = || -> themecfg::Theme {
+            serde_yaml::from_str(
+                "elements: {message: {foreground: '#ff0000', background: 238}, key: {foreground: 33}}",
+            )
+            .unwrap()
+        };
+        let render = |theme: Theme| {
+            let mut buf = Vec::new();
+            theme.apply(&mut buf, &None, |s| {
+                s.element(Element::Message, |s| s.batch(|buf| buf.push(b'm')));
+                s.element(Element::Key, |s| s.batch(|buf| buf.push(b'k')));
+            });
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            render(Theme::from(cfg())),
+            "\x1b[0;48;5;238;38;2;255;0;0mm\x1b[0;38;5;33mk"
+        );
+        assert_eq!(
+            render(Theme::from(
+                cfg().with_color_depth(themecfg::ColorDepth::Ansi256)
+            )),
+            "\x1b[0;48;5;238;38;5;196mm\x1b[0;38;5;33mk"
+        );
+        let output = render(Theme::from(
+            cfg().with_color_depth(themecfg::ColorDepth::Ansi16),
+        ));
+        assert!(
+            !output.contains("38;2;") && !output.contains("38;5;") && !output.contains("48;5;")
+        );
+        assert_eq!(output, "\x1b[0;100;91mm\x1b[0;36mk");
+    }
 }
@@ -80,6 +80,27 @@ impl Theme {
         )
     }
 
+    /// Removes the given modes from all styles, leaving colors intact.
+    pub fn without_modes(mut self, modes: &[Mode]) -> Self {
+        for pack in std::iter::once(&mut self.elements).chain(self.levels.values_mut()) {
+            for style in pack.0.values_mut() {
+                style.modes.retain(|mode| !modes.contains(mode));
+            }
+        }
+        self
+    }
+
+    /// Replaces colors exceeding the given depth with the nearest supported ones.
+    pub fn with_color_depth(mut self, depth: ColorDepth) -> Self {
+        for pack in std::iter::once(&mut self.elements).chain(self.levels.values_mut()) {
+            for style in pack.0.values_mut() {
+                style.foreground = style.foreground.map(|color| color.reduced(depth));
+                style.background = style.background.map(|color| color.reduced(depth));
+            }
+        }
+        self
+    }
+
     fn from_buf(data: &[u8]) -> Result<Self> {
         Ok(serde_yaml::from_str(std::str::from_utf8(data)?)?)
     }
@@ -196,6 +217,7 @@ pub enum Element {
     Boolean,
     Null,
     Ellipsis,
+    Offset,
 }
 
 // ---
@@ -211,7 +233,7 @@ pub struct Style {
 
 // ---
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum Mode {
     Bold,
@@ -236,6 +258,54 @@ pub enum Color {
     RGB(RGB),
 }
 
+impl Color {
+    /// Returns the nearest color supported at the given depth.
+    pub fn reduced(self, depth: ColorDepth) -> Self {
+        match (self, depth) {
+            (_, ColorDepth::TrueColor)
+            | (Self::Plain(_), _)
+            | (Self::Palette(_), ColorDepth::Ansi256) => self,
+            (Self::Palette(code), ColorDepth::Ansi16) => {
+                if code < 16 {
+                    Self::Plain(PLAIN_COLORS[code as usize])
+                } else {
+                    Self::RGB(RGB::from_palette(code)).reduced(depth)
+                }
+            }
+            (Self::RGB(rgb), ColorDepth::Ansi256) => Self::Palette(rgb.to_palette()),
+            (Self::RGB(rgb), ColorDepth::Ansi16) => {
+                let i = (0..16)
+                    .min_by_key(|&i| rgb.distance(&ANSI16_RGB[i]))
+                    .unwrap();
+                Self::Plain(PLAIN_COLORS[i])
+            }
+        }
+    }
+}
+
+// ---
+
+/// Maximum color depth supported by the terminal.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorDepth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl FromStr for ColorDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "16" => Ok(Self::Ansi16),
+            "256" => Ok(Self::Ansi256),
+            "truecolor" | "24bit" => Ok(Self::TrueColor),
+            _ => Err(format!("unknown color depth {:?}", s)),
+        }
+    }
+}
+
 // ---
 
 #[derive(Clone, Copy, Debug, Deserialize)]
@@ -284,6 +354,47 @@ impl FromStr for RGB {
     }
 }
 
+impl RGB {
+    fn from_palette(code: u8) -> Self {
+        match code {
+            0..=15 => ANSI16_RGB[code as usize],
+            16..=231 => {
+                let i = code - 16;
+                Self(
+                    CUBE_LEVELS[(i / 36) as usize],
+                    CUBE_LEVELS[(i / 6 % 6) as usize],
+                    CUBE_LEVELS[(i % 6) as usize],
+                )
+            }
+            _ => {
+                let v = 8 + 10 * (code - 232);
+                Self(v, v, v)
+            }
+        }
+    }
+
+    fn to_palette(self) -> u8 {
+        let level = |v: u8| {
+            (0..6)
+                .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - v as i32).abs())
+                .unwrap() as u8
+        };
+        let cube = 16 + 36 * level(self.0) + 6 * level(self.1) + level(self.2);
+        let avg = (self.0 as u16 + self.1 as u16 + self.2 as u16) / 3;
+        let gray = 232 + (avg.saturating_sub(3) / 10).min(23) as u8;
+        if self.distance(&Self::from_palette(gray)) < self.distance(&Self::from_palette(cube)) {
+            gray
+        } else {
+            cube
+        }
+    }
+
+    fn distance(&self, other: &Self) -> i32 {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(self.0, other.0) + d(self.1, other.1) + d(self.2, other.2)
+    }
+}
+
 impl TryFrom<String> for RGB {
     type Error = String;
 
@@ -329,6 +440,47 @@ fn write_hex<T: fmt::Write>(to: &mut T, v: u8) -> fmt::Result {
     Ok(())
 }
 
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+const PLAIN_COLORS: [PlainColor; 16] = [
+    PlainColor::Black,
+    PlainColor::Red,
+    PlainColor::Green,
+    PlainColor::Yellow,
+    PlainColor::Blue,
+    PlainColor::Magenta,
+    PlainColor::Cyan,
+    PlainColor::White,
+    PlainColor::BrightBlack,
+    PlainColor::BrightRed,
+    PlainColor::BrightGreen,
+    PlainColor::BrightYellow,
+    PlainColor::BrightBlue,
+    PlainColor::BrightMagenta,
+    PlainColor::BrightCyan,
+    PlainColor::BrightWhite,
+];
+
+// Default xterm colors.
+const ANSI16_RGB: [RGB; 16] = [
+    RGB(0, 0, 0),
+    RGB(205, 0, 0),
+    RGB(0, 205, 0),
+    RGB(205, 205, 0),
+    RGB(0, 0, 238),
+    RGB(205, 0, 205),
+    RGB(0, 205, 205),
+    RGB(229, 229, 229),
+    RGB(127, 127, 127),
+    RGB(255, 0, 0),
+    RGB(0, 255, 0),
+    RGB(255, 255, 0),
+    RGB(92, 92, 255),
+    RGB(255, 0, 255),
+    RGB(0, 255, 255),
+    RGB(255, 255, 255),
+];
+
 const HEXDIGIT: [u8; 16] = [
     b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'a', b'b', b'c', b'd', b'e', b'f',
 ];
@@ -346,4 +498,40 @@ mod tests {
         let b: RGB = serde_json::from_str(r##""#102030""##).unwrap();
         assert_eq!(b, RGB(16, 32, 48));
     }
+
+    #[test]
+    fn test_palette() {
+        assert_eq!(RGB(0, 0, 0).to_palette(), 16);
+        assert_eq!(RGB(255, 0, 0).to_palette(), 196);
+        assert_eq!(RGB(128, 128, 128).to_palette(), 244);
+        assert_eq!(RGB::from_palette(196), RGB(255, 0, 0));
+        assert_eq!(RGB::from_palette(244), RGB(128, 128, 128));
+        assert!(matches!(
+            Color::Palette(196).reduced(ColorDepth::Ansi16),
+            Color::Plain(PlainColor::BrightRed)
+        ));
+        assert!(matches!(
+            Color::RGB(RGB(0, 190, 0)).reduced(ColorDepth::Ansi16),
+            Color::Plain(PlainColor::Green)
+        ));
+        assert!(matches!(
+            Color::RGB(RGB(0, 190, 0)).reduced(ColorDepth::TrueColor),
+            Color::RGB(RGB(0, 190, 0))
+        ));
+    }
+
+    #[test]
+    fn test_without_modes() {
+        let theme = Theme::from_buf(
+            b"elements: {message: {modes: [bold, italic], foreground: red}}\nlevels: {error: {level: {modes: [faint]}}}",
+        )
+        .unwrap()
+        .without_modes(&[Mode::Bold, Mode::Faint]);
+        let message = &theme.elements.items()[&Element::Message];
+        assert_eq!(message.modes, vec![Mode::Italic]);
+        assert!(message.foreground.is_some());
+        assert!(theme.levels[&Level::Error].items()[&Element::Level]
+            .modes
+            .is_empty());
+    }
 }
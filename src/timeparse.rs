@@ -293,3 +293,81 @@ fn add_format_item(buf: &mut Vec<u8>, item: &[u8], flags: Flags) -> Option<()> {
         Some(())
     }
 }
+
+// ---
+
+/// Time relative to the time range of the input, resolved once the range is known.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpanTime {
+    /// Percentage of the time range counted from its start, i.e. `+10%`,
+    /// or from its end if negative, i.e. `-10%`.
+    Percent(f64),
+    /// Duration counted from the start of the time range, i.e. `start+1h`.
+    FromStart(Duration),
+    /// Duration counted back from the end of the time range, i.e. `end-1h`.
+    FromEnd(Duration),
+}
+
+impl SpanTime {
+    /// Parses forms like `-10%`, `+10%`, `start+1h` and `end-30m`, returns `None` for other values.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some(value) = s.strip_suffix('%') {
+            let value: f64 = value.parse().ok()?;
+            if value.abs() > 100.0 {
+                return None;
+            }
+            return Some(Self::Percent(value));
+        }
+        let duration = |s: &str| Duration::from_std(parse_duration(s.trim()).ok()?).ok();
+        if let Some(value) = s.strip_prefix("start+") {
+            return Some(Self::FromStart(duration(value)?));
+        }
+        if let Some(value) = s.strip_prefix("end-") {
+            return Some(Self::FromEnd(duration(value)?));
+        }
+        None
+    }
+
+    /// Resolves the time against the given time range.
+    pub fn resolve(
+        &self,
+        start: DateTime<FixedOffset>,
+        end: DateTime<FixedOffset>,
+    ) -> DateTime<FixedOffset> {
+        match *self {
+            Self::Percent(value) => {
+                let span = (end - start).num_milliseconds() as f64;
+                let offset = Duration::milliseconds((span * value.abs() / 100.0).round() as i64);
+                if value.is_sign_negative() {
+                    end - offset
+                } else {
+                    start + offset
+                }
+            }
+            Self::FromStart(d) => start + d,
+            Self::FromEnd(d) => end - d,
+        }
+    }
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_time() {
+        let start = DateTime::parse_from_rfc3339("2021-06-20T00:00:00Z").unwrap();
+        let end = DateTime::parse_from_rfc3339("2021-06-20T10:00:00Z").unwrap();
+        let resolve = |s| SpanTime::parse(s).unwrap().resolve(start, end).to_rfc3339();
+        assert_eq!(resolve("-10%"), "2021-06-20T09:00:00+00:00");
+        assert_eq!(resolve("+25%"), "2021-06-20T02:30:00+00:00");
+        assert_eq!(resolve("start+1h"), "2021-06-20T01:00:00+00:00");
+        assert_eq!(resolve("end-30m"), "2021-06-20T09:30:00+00:00");
+        assert_eq!(SpanTime::parse("-1h"), None);
+        assert_eq!(SpanTime::parse("-110%"), None);
+        assert_eq!(SpanTime::parse("yesterday"), None);
+    }
+}
@@ -20,14 +20,28 @@ impl<'a> Timestamp<'a> {
             return parsed;
         }
 
-        if let Ok(ts) = self.0.parse::<i64>() {
-            let (ts, nsec) = if ts < 100000000000 {
-                (ts, 0)
+        let (int, frac) = match self.0.split_once('.') {
+            Some((int, frac)) => (int, Some(frac)),
+            None => (self.0, None),
+        };
+        let frac = match frac {
+            Some(frac) if frac.is_empty() || !only_digits(frac.as_bytes()) => None,
+            Some(frac) => Some(frac),
+            None => Some(""),
+        };
+        if let (Ok(ts), Some(frac)) = (int.parse::<i64>(), frac) {
+            // unit of the timestamp in nanoseconds
+            let (ts, nsec, unit) = if ts < 100000000000 {
+                (ts, 0, 1000000000)
             } else if ts < 100000000000000 {
-                (ts / 1000, (ts % 1000) * 1000000)
+                (ts / 1000, (ts % 1000) * 1000000, 1000000)
             } else {
-                (ts / 1000000, (ts % 1000000) * 1000)
+                (ts / 1000000, (ts % 1000000) * 1000, 1000)
             };
+            // fraction of the unit, taken with 9 digits and scaled to the unit
+            let frac = frac.bytes().chain(std::iter::repeat(b'0')).take(9);
+            let frac = frac.fold(0, |acc, b| acc * 10 + (b - b'0') as i64);
+            let nsec = nsec + frac * unit / 1000000000;
             let ts = NaiveDateTime::from_timestamp_opt(ts, nsec as u32)?;
             Some(DateTime::from_utc(ts, FixedOffset::east(0)))
         } else {
@@ -35,6 +49,16 @@ impl<'a> Timestamp<'a> {
         }
     }
 
+    /// Parses the value using the given strftime-like pattern.
+    /// Patterns without a timezone specifier are interpreted as UTC.
+    pub fn parse_with_format(&self, format: &str) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_str(self.0, format).ok().or_else(|| {
+            NaiveDateTime::parse_from_str(self.0, format)
+                .ok()
+                .map(|ts| DateTime::from_utc(ts, FixedOffset::east(0)))
+        })
+    }
+
     pub fn as_rfc3339(&self) -> Option<rfc3339::Timestamp> {
         rfc3339::Timestamp::parse(self.0)
     }
@@ -401,6 +425,35 @@ fn only_digits(b: &[u8]) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_unix() {
+        let parse = |s| Timestamp::new(s, None).parse();
+        let ts = |s| DateTime::parse_from_rfc3339(s).ok();
+        assert_eq!(parse("1600000000"), ts("2020-09-13T12:26:40Z"));
+        assert_eq!(parse("1600000000.123"), ts("2020-09-13T12:26:40.123Z"));
+        assert_eq!(parse("1600000000123"), ts("2020-09-13T12:26:40.123Z"));
+        assert_eq!(parse("1600000000123.5"), ts("2020-09-13T12:26:40.1235Z"));
+        assert_eq!(parse("1600000000123456"), ts("2020-09-13T12:26:40.123456Z"));
+        assert_eq!(parse("1600000000."), None);
+        assert_eq!(parse("1600000000.1x"), None);
+    }
+
+    #[test]
+    fn test_parse_with_format() {
+        let ts = Timestamp::new("27/Jun/2021:00:48:30 +0300", None);
+        assert_eq!(
+            ts.parse_with_format("%d/%b/%Y:%H:%M:%S %z"),
+            DateTime::parse_from_rfc3339("2021-06-27T00:48:30+03:00").ok()
+        );
+        assert_eq!(ts.parse(), None);
+        let ts = Timestamp::new("Jun 27 2021 00:48:30", None);
+        assert_eq!(
+            ts.parse_with_format("%b %d %Y %H:%M:%S"),
+            DateTime::parse_from_rfc3339("2021-06-27T00:48:30Z").ok()
+        );
+        assert_eq!(ts.parse_with_format("%d/%b/%Y"), None);
+    }
+
     #[test]
     fn test_split_rfc3339() {
         use rfc3339::Timestamp;
@@ -3,6 +3,7 @@ use std::cmp::Ord;
 use std::str::FromStr;
 
 // third-party imports
+use chrono::{DateTime, FixedOffset};
 use enum_map::Enum;
 use serde::Deserialize;
 
@@ -20,6 +21,41 @@ pub enum Level {
     Debug,
 }
 
+impl Level {
+    /// Returns the lower case name of the level.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+/// Comparison of record levels with the level of the filter.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LevelMode {
+    /// Records with the level or a more severe one.
+    #[default]
+    Min,
+    /// Records with exactly the level.
+    Exact,
+    /// Records with the level or a less severe one.
+    Max,
+}
+
+impl LevelMode {
+    /// Returns true if the level satisfies the bound, levels are ordered from the most severe one.
+    pub fn matches(self, level: Level, bound: Level) -> bool {
+        match self {
+            Self::Min => level <= bound,
+            Self::Exact => level == bound,
+            Self::Max => level >= bound,
+        }
+    }
+}
+
 impl FromStr for Level {
     type Err = Error;
 
@@ -49,6 +85,90 @@ impl FromStr for Level {
 
 // ---
 
+/// Fraction of records to keep, i.e. `1/100` or `0.01`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SampleRate {
+    keep: u64,
+    every: u64,
+}
+
+impl SampleRate {
+    /// Returns true if the record with the given index should be kept.
+    /// Records are kept evenly, starting from the first one.
+    pub fn keeps(&self, index: u64) -> bool {
+        // computed in 128 bits so that large indices and rates cannot overflow
+        let bucket = |i: u128| (i * self.keep as u128).div_ceil(self.every as u128);
+        bucket(index as u128 + 1) != bucket(index as u128)
+    }
+}
+
+impl FromStr for SampleRate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidSampleRate(s.into());
+        let (keep, every) = match s.split_once('/') {
+            Some((keep, every)) => (
+                keep.trim().parse().map_err(|_| invalid())?,
+                every.trim().parse().map_err(|_| invalid())?,
+            ),
+            None => {
+                let rate: f64 = s.trim().parse().map_err(|_| invalid())?;
+                if !(0.0..=1.0).contains(&rate) {
+                    return Err(invalid());
+                }
+                (
+                    (rate * SAMPLE_RATE_PRECISION as f64).round() as u64,
+                    SAMPLE_RATE_PRECISION,
+                )
+            }
+        };
+        if keep == 0 || keep > every {
+            return Err(invalid());
+        }
+        Ok(Self { keep, every })
+    }
+}
+
+const SAMPLE_RATE_PRECISION: u64 = 1_000_000;
+
+// ---
+
+/// Time interval used to group records by their timestamps.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeBucket {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl TimeBucket {
+    /// Returns index of the bucket containing the given timestamp in the given time zone.
+    pub fn index(self, ts: &DateTime<FixedOffset>, tz: FixedOffset) -> i64 {
+        (ts.timestamp() + tz.local_minus_utc() as i64).div_euclid(self.seconds())
+    }
+
+    /// Returns human-readable label of the bucket containing the given timestamp in the given time zone.
+    pub fn label(self, ts: &DateTime<FixedOffset>, tz: FixedOffset) -> String {
+        let format = match self {
+            Self::Minute => "%Y-%m-%d %H:%M",
+            Self::Hour => "%Y-%m-%d %H:00",
+            Self::Day => "%Y-%m-%d",
+        };
+        ts.with_timezone(&tz).format(format).to_string()
+    }
+
+    fn seconds(self) -> i64 {
+        match self {
+            Self::Minute => 60,
+            Self::Hour => 60 * 60,
+            Self::Day => 24 * 60 * 60,
+        }
+    }
+}
+
+// ---
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[serde(rename_all = "kebab-case")]
 pub enum FieldKind {
@@ -57,4 +177,64 @@ pub enum FieldKind {
     Logger,
     Message,
     Caller,
+    CallerFunction,
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_mode() {
+        assert!(LevelMode::Min.matches(Level::Error, Level::Warning));
+        assert!(LevelMode::Min.matches(Level::Warning, Level::Warning));
+        assert!(!LevelMode::Min.matches(Level::Info, Level::Warning));
+        assert!(!LevelMode::Exact.matches(Level::Error, Level::Warning));
+        assert!(LevelMode::Exact.matches(Level::Warning, Level::Warning));
+        assert!(!LevelMode::Exact.matches(Level::Info, Level::Warning));
+        assert!(!LevelMode::Max.matches(Level::Error, Level::Warning));
+        assert!(LevelMode::Max.matches(Level::Warning, Level::Warning));
+        assert!(LevelMode::Max.matches(Level::Debug, Level::Warning));
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let rate: SampleRate = "1/3".parse().unwrap();
+        let kept: Vec<_> = (0..9).filter(|&i| rate.keeps(i)).collect();
+        assert_eq!(kept, vec![0, 3, 6]);
+        let rate: SampleRate = "0.5".parse().unwrap();
+        let kept: Vec<_> = (0..6).filter(|&i| rate.keeps(i)).collect();
+        assert_eq!(kept, vec![0, 2, 4]);
+        let rate: SampleRate = "0.25".parse().unwrap();
+        assert_eq!((0..100).filter(|&i| rate.keeps(i)).count(), 25);
+        let rate: SampleRate = format!("{}/{}", u64::MAX - 1, u64::MAX).parse().unwrap();
+        assert!(!rate.keeps(u64::MAX - 1));
+        assert!(rate.keeps(u64::MAX));
+        assert!("0".parse::<SampleRate>().is_err());
+        assert!("3/2".parse::<SampleRate>().is_err());
+        assert!("1.5".parse::<SampleRate>().is_err());
+        assert!("x".parse::<SampleRate>().is_err());
+    }
+
+    #[test]
+    fn test_time_bucket() {
+        let tz = FixedOffset::east(3 * 3600);
+        let ts = |s| DateTime::parse_from_rfc3339(s).unwrap();
+        let a = ts("2021-06-20T20:59:59Z");
+        let b = ts("2021-06-20T21:00:00Z");
+        assert_ne!(
+            TimeBucket::Hour.index(&a, tz),
+            TimeBucket::Hour.index(&b, tz)
+        );
+        assert_ne!(TimeBucket::Day.index(&a, tz), TimeBucket::Day.index(&b, tz));
+        assert_eq!(
+            TimeBucket::Day.index(&a, tz),
+            TimeBucket::Day.index(&ts("2021-06-20T00:00:00+03:00"), tz)
+        );
+        assert_eq!(TimeBucket::Hour.label(&b, tz), "2021-06-21 00:00");
+        assert_eq!(TimeBucket::Minute.label(&a, tz), "2021-06-20 23:59");
+        assert_eq!(TimeBucket::Day.label(&a, tz), "2021-06-20");
+    }
 }
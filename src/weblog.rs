@@ -0,0 +1,286 @@
+//! Conversion of web server access log lines to JSON records, so they can be processed as regular JSON input.
+//!
+//! Supported subset and known limitations:
+//! * Apache combined and common log formats are supported, remote host, user, status, bytes, referer and user agent
+//!   are mapped to `host`, `user`, `status`, `bytes`, `referer` and `agent`, the request line is mapped to `msg`
+//!   and the timestamp is converted to RFC 3339 `ts`, nil values (`-`) are omitted,
+//! * W3C Extended Log Format columns are taken from the `#Fields:` directive and keep their names,
+//!   except for `date` and `time` combined into `ts`, `sc-status` mapped to `status`
+//!   and `cs-method` with `cs-uri-stem` and `cs-uri-query` combined into `msg`,
+//! * W3C directives are read from the beginning of the input, directives further in the input are only
+//!   picked up by the processing thread that meets them, so inputs with changing columns need a single thread,
+//! * the level is not set by the conversion, it is computed from `status` according to configured status ranges.
+
+// third-party imports
+use chrono::DateTime;
+
+// local imports
+use crate::syslog::ObjectWriter;
+
+// ---
+
+/// Converts an Apache combined or common log line to a JSON object, returns false if the line does not match the format.
+pub fn combined_to_json(line: &[u8], buf: &mut Vec<u8>) -> bool {
+    let n = buf.len();
+    let result = std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| write_combined(buf, line));
+    if result.is_none() {
+        buf.truncate(n);
+    }
+    result.is_some()
+}
+
+// ---
+
+/// Columns of W3C Extended Log Format lines declared by the `#Fields:` directive.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct W3cFields(Vec<String>);
+
+impl W3cFields {
+    /// Parses a `#Fields:` directive, returns None for other lines.
+    pub fn parse(line: &[u8]) -> Option<Self> {
+        let line = std::str::from_utf8(line).ok()?;
+        let fields = line.trim_end_matches('\r').strip_prefix("#Fields:")?;
+        Some(Self(fields.split_whitespace().map(|f| f.into()).collect()))
+    }
+
+    /// Finds the last `#Fields:` directive preceding the first data line in the sample.
+    pub fn detect(sample: &[u8]) -> Option<Self> {
+        let mut result = None;
+        for line in sample.split(|c| *c == b'\n') {
+            if !line.starts_with(b"#") {
+                break;
+            }
+            result = Self::parse(line).or(result);
+        }
+        result
+    }
+
+    /// Returns true if the line is a directive like `#Fields:` or `#Version:`.
+    pub fn is_directive(line: &[u8]) -> bool {
+        line.starts_with(b"#")
+    }
+
+    /// Converts a data line to a JSON object, returns false if the number of values does not match the columns.
+    pub fn to_json(&self, line: &[u8], buf: &mut Vec<u8>) -> bool {
+        let n = buf.len();
+        let result = std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| self.write(buf, line));
+        if result.is_none() {
+            buf.truncate(n);
+        }
+        result.is_some()
+    }
+
+    fn write(&self, buf: &mut Vec<u8>, line: &str) -> Option<()> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let mut values = Vec::with_capacity(self.0.len());
+        let mut rest = line.trim_end_matches('\r');
+        while let Some((value, tail)) = next_token(rest) {
+            values.push(value);
+            rest = tail;
+        }
+        if values.len() != self.0.len() {
+            return None;
+        }
+        let get = |name: &str| {
+            self.0
+                .iter()
+                .position(|f| f == name)
+                .and_then(|i| nil(&values[i]))
+        };
+
+        let mut object = ObjectWriter::new(buf);
+        match (get("date"), get("time")) {
+            (Some(date), Some(time)) => object.string("ts", &format!("{}T{}Z", date, time)),
+            (None, Some(time)) => object.string("ts", time),
+            _ => {}
+        }
+        if let Some(method) = get("cs-method") {
+            let mut msg = method.to_string();
+            if let Some(stem) = get("cs-uri-stem") {
+                msg.push(' ');
+                msg.push_str(stem);
+                if let Some(query) = get("cs-uri-query") {
+                    msg.push('?');
+                    msg.push_str(query);
+                }
+            }
+            object.string("msg", &msg);
+        }
+        if let Some(status) = get("sc-status") {
+            match status.parse::<u64>() {
+                Ok(status) => object.number("status", status),
+                Err(_) => object.string("status", status),
+            }
+        }
+        for (name, value) in self.0.iter().zip(&values) {
+            match name.as_str() {
+                "date" | "time" | "cs-method" | "cs-uri-stem" | "cs-uri-query" | "sc-status" => {}
+                _ => object.string_opt(name, nil(value)),
+            }
+        }
+        object.end();
+        Some(())
+    }
+}
+
+// ---
+
+fn write_combined(buf: &mut Vec<u8>, line: &str) -> Option<()> {
+    let mut rest = line.trim_end_matches('\r');
+    let mut tokens = Vec::with_capacity(9);
+    while let Some((token, tail)) = next_token(rest) {
+        tokens.push(token);
+        rest = tail;
+    }
+    if tokens.len() != 7 && tokens.len() != 9 {
+        return None;
+    }
+    let ts = tokens[3].strip_prefix('[')?.strip_suffix(']')?;
+    let ts = DateTime::parse_from_str(ts, "%d/%b/%Y:%H:%M:%S %z").ok()?;
+    let status = tokens[5].parse::<u64>().ok()?;
+    let bytes = match nil(&tokens[6]) {
+        Some(bytes) => Some(bytes.parse::<u64>().ok()?),
+        None => None,
+    };
+
+    let mut object = ObjectWriter::new(buf);
+    object.string("ts", &ts.to_rfc3339());
+    object.string_opt("msg", nil(&tokens[4]));
+    object.string_opt("host", nil(&tokens[0]));
+    object.string_opt("user", nil(&tokens[2]));
+    object.number("status", status);
+    if let Some(bytes) = bytes {
+        object.number("bytes", bytes);
+    }
+    if tokens.len() == 9 {
+        object.string_opt("referer", nil(&tokens[7]));
+        object.string_opt("agent", nil(&tokens[8]));
+    }
+    object.end();
+    Some(())
+}
+
+/// Splits the next space-separated token, which may be a quoted string with backslash escapes
+/// or a bracketed timestamp, returning the token and the rest of the text.
+fn next_token(text: &str) -> Option<(String, &str)> {
+    let text = text.trim_start_matches(' ');
+    if text.is_empty() {
+        return None;
+    }
+    if let Some(quoted) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, c)) => value.push(c),
+                    None => value.push('\\'),
+                },
+                '"' => return Some((value, &quoted[i + 1..])),
+                _ => value.push(c),
+            }
+        }
+        return None;
+    }
+    let end = if text.starts_with('[') {
+        text.find(']').map(|i| i + 1)?
+    } else {
+        text.find(' ').unwrap_or(text.len())
+    };
+    Some((text[..end].into(), &text[end..]))
+}
+
+fn nil(value: &str) -> Option<&str> {
+    if value == "-" || value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert<F: FnOnce(&[u8], &mut Vec<u8>) -> bool>(f: F, line: &str) -> Option<String> {
+        let mut buf = Vec::new();
+        if f(line.as_bytes(), &mut buf) {
+            Some(String::from_utf8(buf).unwrap())
+        } else {
+            assert!(buf.is_empty());
+            None
+        }
+    }
+
+    #[test]
+    fn test_combined() {
+        assert_eq!(
+            convert(
+                combined_to_json,
+                r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326 "http://www.example.com/start.html" "Mozilla/4.08 [en] (Win98; I ;Nav)""#
+            )
+            .unwrap(),
+            concat!(
+                r#"{"ts":"2000-10-10T13:55:36-07:00","msg":"GET /apache_pb.gif HTTP/1.0","host":"127.0.0.1","#,
+                r#""user":"frank","status":200,"bytes":2326,"referer":"http://www.example.com/start.html","#,
+                r#""agent":"Mozilla/4.08 [en] (Win98; I ;Nav)"}"#,
+            )
+        );
+        assert_eq!(
+            convert(
+                combined_to_json,
+                r#"::1 - - [10/Oct/2000:13:55:36 +0000] "GET /a\"b HTTP/1.1" 503 -"#
+            )
+            .unwrap(),
+            r#"{"ts":"2000-10-10T13:55:36+00:00","msg":"GET /a\"b HTTP/1.1","host":"::1","status":503}"#
+        );
+        assert_eq!(
+            convert(
+                combined_to_json,
+                r#"::1 - - [yesterday] "GET / HTTP/1.1" 200 -"#
+            ),
+            None
+        );
+        assert_eq!(convert(combined_to_json, r#"{"msg":"json"}"#), None);
+    }
+
+    #[test]
+    fn test_w3c() {
+        let sample = concat!(
+            "#Software: Microsoft Internet Information Services 6.0\n",
+            "#Version: 1.0\n",
+            "#Fields: date time c-ip cs-method cs-uri-stem cs-uri-query sc-status sc-bytes cs(User-Agent)\n",
+            "2002-05-24 20:18:01 172.224.24.114 GET /Default.htm - 404 7930 Mozilla/4.0+(compatible)\n",
+        );
+        let fields = W3cFields::detect(sample.as_bytes()).unwrap();
+        assert!(W3cFields::is_directive(b"#Version: 1.0"));
+        let line = sample.lines().last().unwrap();
+        assert_eq!(
+            convert(|line, buf| fields.to_json(line, buf), line).unwrap(),
+            concat!(
+                r#"{"ts":"2002-05-24T20:18:01Z","msg":"GET /Default.htm","status":404,"#,
+                r#""c-ip":"172.224.24.114","sc-bytes":"7930","cs(User-Agent)":"Mozilla/4.0+(compatible)"}"#,
+            )
+        );
+        assert_eq!(
+            convert(|line, buf| fields.to_json(line, buf), "2002-05-24 20:18:01"),
+            None
+        );
+        assert_eq!(
+            convert(|line, buf| W3cFields::default().to_json(line, buf), line),
+            None
+        );
+        assert_eq!(
+            W3cFields::detect(b"2002-05-24 20:18:01\n#Fields: date"),
+            None
+        );
+    }
+}